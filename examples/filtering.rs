@@ -1,21 +1,25 @@
-use std::{env, sync::Arc};
+use std::env;
 
 use finra_rs::{
     ConsolidatedShortInterest, ConsolidatedShortInterestField, ConsolidatedShortInterestQuery,
-    Finra, Result,
+    FinraBuilder, Result,
 };
 use futures::{StreamExt, TryStreamExt};
-use reqwest::ClientBuilder;
 use time::macros::date;
 
 #[tokio::main]
 async fn main() {
-    let finra = Finra::new(
-        Arc::new(ClientBuilder::new),
-        env::var("CLIENT_ID").unwrap(),
-        env::var("CLIENT_SECRET").unwrap(),
-        true,
-    );
+    // reads FINRA_CLIENT_ID and FINRA_CLIENT_SECRET, or CLIENT_ID and CLIENT_SECRET, and queries
+    // the mock datasets, so that trying it out doesn't download the production data
+    let builder = FinraBuilder::from_env().unwrap_or_else(|_| {
+        FinraBuilder::new(
+            env::var("CLIENT_ID").unwrap(),
+            env::var("CLIENT_SECRET").unwrap(),
+        )
+    });
+    #[cfg(not(feature = "no-mock-datasets"))]
+    let builder = builder.use_mock_datasets(true);
+    let finra = builder.build();
 
     let stream = match finra
         .consolidated_short_interest(ConsolidatedShortInterestQuery::new(
@@ -0,0 +1,417 @@
+use std::future::Future;
+
+use futures::{future, stream, StreamExt, TryStream, TryStreamExt};
+use time::Date;
+
+use crate::{
+    summarize, BlocksSummaryField, BlocksSummaryRecord, ConsolidatedShortInterest,
+    ConsolidatedShortInterestField, DailyShortSaleVolume, DailyShortSaleVolumeField,
+    EquityShortInterest, EquityShortInterestField, MonthlySummaryField, MonthlySummaryRecord,
+    Summary, WeeklySummaryField, WeeklySummaryRecord,
+};
+
+/// A record that can be seen as a point in the time series of values of a symbol.
+pub trait TimeSeriesRecord {
+    /// The type identifying the fields of the record.
+    type Field: Copy;
+
+    fn symbol(&self) -> &str;
+
+    /// The date the record applies to, if present in the record.
+    fn date(&self) -> Option<Date>;
+
+    /// The value of the field as a number, if it is a numeric field.
+    fn value(&self, field: Self::Field) -> Option<f64>;
+
+    /// The numeric fields of the record, i.e. the fields with a [`TimeSeriesRecord::value`].
+    fn numeric_fields() -> Vec<Self::Field> {
+        Vec::new()
+    }
+}
+
+/// Projects the stream of records into `(symbol, date, value)` triples of the provided numeric
+/// field, which is what most time-series stores and charting libraries expect. The records
+/// without the date or with a non-numeric field are skipped.
+pub fn time_series<S, R>(
+    stream: S,
+    field: R::Field,
+) -> impl TryStream<Ok = (String, Date, f64), Error = S::Error>
+where
+    S: TryStream<Ok = R>,
+    R: TimeSeriesRecord,
+{
+    stream.try_filter_map(move |r| {
+        future::ok(
+            r.date()
+                .zip(r.value(field))
+                .map(|(date, value)| (r.symbol().to_string(), date, value)),
+        )
+    })
+}
+
+/// Groups the consecutive records of the stream with the same date, yielding each group as soon
+/// as a record with a different date arrives. This allows processing the results period by
+/// period, e.g. writing one partition per settlement date, without buffering the whole stream.
+///
+/// The records are expected to be ordered by the date, as FINRA mostly returns them. If they are
+/// not, the same date is yielded in several groups. The records without the date are skipped.
+pub fn group_by_date<S, R>(stream: S) -> impl TryStream<Ok = (Date, Vec<R>), Error = S::Error>
+where
+    S: TryStream<Ok = R>,
+    R: TimeSeriesRecord,
+{
+    group_consecutive(stream, |r| r.date())
+}
+
+/// Groups the consecutive records of the stream with the same symbol, yielding each group as soon
+/// as a record with a different symbol arrives. See [`group_by_date`] for the details.
+pub fn group_by_symbol<S, R>(stream: S) -> impl TryStream<Ok = (String, Vec<R>), Error = S::Error>
+where
+    S: TryStream<Ok = R>,
+    R: TimeSeriesRecord,
+{
+    group_consecutive(stream, |r| Some(r.symbol().to_string()))
+}
+
+/// Collects the `n` records with the highest values of the numeric `field`, in the descending
+/// order of the values. The records without the value of the field are skipped.
+pub async fn top_n_by<S, R>(stream: S, field: R::Field, n: usize) -> Result<Vec<R>, S::Error>
+where
+    S: TryStream<Ok = R>,
+    R: TimeSeriesRecord,
+{
+    let mut top: Vec<(f64, R)> = Vec::with_capacity(n + 1);
+    let mut stream = Box::pin(stream.into_stream());
+    while let Some(r) = stream.try_next().await? {
+        let Some(value) = r.value(field) else {
+            continue;
+        };
+        let i = top.partition_point(|(v, _)| v.total_cmp(&value).is_ge());
+        if i < n {
+            top.insert(i, (value, r));
+            top.truncate(n);
+        }
+    }
+
+    Ok(top.into_iter().map(|(_, r)| r).collect())
+}
+
+/// Groups the consecutive records with the same key. The records without the key are skipped.
+fn group_consecutive<S, R, K>(
+    stream: S,
+    key: impl Fn(&R) -> Option<K>,
+) -> impl TryStream<Ok = (K, Vec<R>), Error = S::Error>
+where
+    S: TryStream<Ok = R>,
+    K: PartialEq,
+{
+    stream::try_unfold(
+        (
+            Box::pin(stream.into_stream().fuse()),
+            None::<(K, Vec<R>)>,
+            key,
+        ),
+        |(mut stream, mut group, key)| async move {
+            while let Some(r) = stream.try_next().await? {
+                let Some(k) = key(&r) else {
+                    continue;
+                };
+                match &mut group {
+                    Some((group_key, records)) if *group_key == k => records.push(r),
+                    _ => {
+                        if let Some(done) = group.replace((k, vec![r])) {
+                            return Ok(Some((done, (stream, group, key))));
+                        }
+                    }
+                }
+            }
+
+            Ok(group.take().map(|done| (done, (stream, None, key))))
+        },
+    )
+}
+
+/// The adapters of this crate as chainable methods of the streams of the records.
+pub trait FinraStreamExt: TryStream + Sized
+where
+    Self::Ok: TimeSeriesRecord,
+{
+    /// See [`time_series`].
+    fn time_series(
+        self,
+        field: <Self::Ok as TimeSeriesRecord>::Field,
+    ) -> impl TryStream<Ok = (String, Date, f64), Error = Self::Error> {
+        time_series(self, field)
+    }
+
+    /// See [`group_by_date`].
+    fn group_by_date(self) -> impl TryStream<Ok = (Date, Vec<Self::Ok>), Error = Self::Error> {
+        group_by_date(self)
+    }
+
+    /// See [`group_by_symbol`].
+    fn group_by_symbol(self) -> impl TryStream<Ok = (String, Vec<Self::Ok>), Error = Self::Error> {
+        group_by_symbol(self)
+    }
+
+    /// See [`top_n_by`].
+    fn top_n_by(
+        self,
+        field: <Self::Ok as TimeSeriesRecord>::Field,
+        n: usize,
+    ) -> impl Future<Output = Result<Vec<Self::Ok>, Self::Error>> {
+        top_n_by(self, field, n)
+    }
+
+    /// See [`summarize`].
+    fn summarize(
+        self,
+    ) -> impl Future<Output = Result<Summary<<Self::Ok as TimeSeriesRecord>::Field>, Self::Error>>
+    {
+        summarize(self)
+    }
+}
+
+impl<S> FinraStreamExt for S
+where
+    S: TryStream,
+    S::Ok: TimeSeriesRecord,
+{
+}
+
+impl TimeSeriesRecord for ConsolidatedShortInterest {
+    type Field = ConsolidatedShortInterestField;
+
+    fn symbol(&self) -> &str {
+        &self.symbol_code
+    }
+
+    fn date(&self) -> Option<Date> {
+        self.settlement_date
+    }
+
+    fn value(&self, field: Self::Field) -> Option<f64> {
+        use ConsolidatedShortInterestField as F;
+        match field {
+            F::PreviousShortPositionQuantity => Some(self.previous_short_position_quantity as f64),
+            F::AverageDailyVolumeQuantity => Some(self.average_daily_volume_quantity as f64),
+            F::CurrentShortPositionQuantity => Some(self.current_short_position_quantity as f64),
+            F::ChangePreviousNumber => Some(self.change_previous_number as f64),
+            F::DaysToCoverQuantity => Some(self.days_to_cover_quantity),
+            F::ChangePercent => Some(self.change_percent),
+            _ => None,
+        }
+    }
+
+    fn numeric_fields() -> Vec<Self::Field> {
+        use ConsolidatedShortInterestField as F;
+        vec![
+            F::PreviousShortPositionQuantity,
+            F::AverageDailyVolumeQuantity,
+            F::CurrentShortPositionQuantity,
+            F::ChangePreviousNumber,
+            F::DaysToCoverQuantity,
+            F::ChangePercent,
+        ]
+    }
+}
+
+impl TimeSeriesRecord for EquityShortInterest {
+    type Field = EquityShortInterestField;
+
+    fn symbol(&self) -> &str {
+        &self.symbol_code
+    }
+
+    fn date(&self) -> Option<Date> {
+        self.settlement_date
+    }
+
+    fn value(&self, field: Self::Field) -> Option<f64> {
+        use EquityShortInterestField as F;
+        match field {
+            F::PreviousShortPositionQuantity => Some(self.previous_short_position_quantity as f64),
+            F::AverageDailyVolumeQuantity => Some(self.average_daily_volume_quantity as f64),
+            F::CurrentShortPositionQuantity => Some(self.current_short_position_quantity as f64),
+            F::ChangePreviousNumber => Some(self.change_previous_number as f64),
+            F::DaysToCoverQuantity => Some(self.days_to_cover_quantity),
+            F::ChangePercent => Some(self.change_percent),
+            _ => None,
+        }
+    }
+
+    fn numeric_fields() -> Vec<Self::Field> {
+        use EquityShortInterestField as F;
+        vec![
+            F::PreviousShortPositionQuantity,
+            F::AverageDailyVolumeQuantity,
+            F::CurrentShortPositionQuantity,
+            F::ChangePreviousNumber,
+            F::DaysToCoverQuantity,
+            F::ChangePercent,
+        ]
+    }
+}
+
+impl TimeSeriesRecord for DailyShortSaleVolume {
+    type Field = DailyShortSaleVolumeField;
+
+    fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    fn date(&self) -> Option<Date> {
+        self.trade_report_date
+    }
+
+    fn value(&self, field: Self::Field) -> Option<f64> {
+        use DailyShortSaleVolumeField as F;
+        match field {
+            F::ShortVolume => Some(self.short_volume as f64),
+            F::ShortExemptVolume => Some(self.short_exempt_volume as f64),
+            F::TotalVolume => Some(self.total_volume as f64),
+            _ => None,
+        }
+    }
+
+    fn numeric_fields() -> Vec<Self::Field> {
+        use DailyShortSaleVolumeField as F;
+        vec![F::ShortVolume, F::ShortExemptVolume, F::TotalVolume]
+    }
+}
+
+impl TimeSeriesRecord for WeeklySummaryRecord {
+    type Field = WeeklySummaryField;
+
+    fn symbol(&self) -> &str {
+        &self.issue_symbol_identifier
+    }
+
+    fn date(&self) -> Option<Date> {
+        self.week_start_date
+    }
+
+    fn value(&self, field: Self::Field) -> Option<f64> {
+        use WeeklySummaryField as F;
+        match field {
+            F::TotalWeeklyShareQuantity => Some(self.total_weekly_share_quantity as f64),
+            F::TotalWeeklyTradeCount => Some(self.total_weekly_trade_count as f64),
+            _ => None,
+        }
+    }
+
+    fn numeric_fields() -> Vec<Self::Field> {
+        use WeeklySummaryField as F;
+        vec![F::TotalWeeklyShareQuantity, F::TotalWeeklyTradeCount]
+    }
+}
+
+impl TimeSeriesRecord for MonthlySummaryRecord {
+    type Field = MonthlySummaryField;
+
+    fn symbol(&self) -> &str {
+        &self.issue_symbol_identifier
+    }
+
+    fn date(&self) -> Option<Date> {
+        self.month_start_date
+    }
+
+    fn value(&self, field: Self::Field) -> Option<f64> {
+        use MonthlySummaryField as F;
+        match field {
+            F::TotalMonthlyShareQuantity => Some(self.total_monthly_share_quantity as f64),
+            F::TotalMonthlyTradeCount => Some(self.total_monthly_trade_count as f64),
+            _ => None,
+        }
+    }
+
+    fn numeric_fields() -> Vec<Self::Field> {
+        use MonthlySummaryField as F;
+        vec![F::TotalMonthlyShareQuantity, F::TotalMonthlyTradeCount]
+    }
+}
+
+impl TimeSeriesRecord for BlocksSummaryRecord {
+    type Field = BlocksSummaryField;
+
+    fn symbol(&self) -> &str {
+        &self.issue_symbol_identifier
+    }
+
+    fn date(&self) -> Option<Date> {
+        self.summary_start_date
+    }
+
+    fn value(&self, field: Self::Field) -> Option<f64> {
+        use BlocksSummaryField as F;
+        match field {
+            F::TotalBlockCount => Some(self.total_block_count as f64),
+            F::TotalBlockQuantity => Some(self.total_block_quantity as f64),
+            F::AverageBlockSize => Some(self.average_block_size),
+            F::TotalTradeCount => Some(self.total_trade_count as f64),
+            F::TotalShareQuantity => Some(self.total_share_quantity as f64),
+            _ => None,
+        }
+    }
+
+    fn numeric_fields() -> Vec<Self::Field> {
+        use BlocksSummaryField as F;
+        vec![
+            F::TotalBlockCount,
+            F::TotalBlockQuantity,
+            F::AverageBlockSize,
+            F::TotalTradeCount,
+            F::TotalShareQuantity,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn volume(symbol: &str, total_volume: u64) -> DailyShortSaleVolume {
+        DailyShortSaleVolume {
+            symbol: symbol.to_string(),
+            total_volume,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn top_n_and_groups() {
+        let records = || {
+            stream::iter(
+                [
+                    volume("A", 1),
+                    volume("A", 5),
+                    volume("B", 3),
+                    volume("C", 4),
+                ]
+                .map(Ok::<_, ()>),
+            )
+        };
+
+        let top = records()
+            .top_n_by(DailyShortSaleVolumeField::TotalVolume, 2)
+            .await
+            .unwrap();
+        assert_eq!(top, vec![volume("A", 5), volume("C", 4)]);
+
+        let groups: Vec<_> = records()
+            .group_by_symbol()
+            .map_ok(|(symbol, records)| (symbol, records.len()))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                ("A".to_string(), 2),
+                ("B".to_string(), 1),
+                ("C".to_string(), 1)
+            ]
+        );
+    }
+}
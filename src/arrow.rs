@@ -0,0 +1,366 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{
+        ArrayBuilder, Date32Builder, Float64Builder, Int64Builder, StringBuilder, UInt64Builder,
+    },
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use futures::{future, StreamExt, TryStream, TryStreamExt};
+use time::Date;
+
+use crate::{
+    fixed_income::{CorporateMarketBreadth, TreasuryWeeklyAggregates},
+    registration::FirmRecord,
+    BlocksSummaryRecord, ConsolidatedShortInterest, DailyShortSaleVolume, EquityShortInterest,
+    Error, MonthlySummaryRecord, Result, ThresholdListRecord, WeeklySummaryRecord, YearMonth,
+};
+
+/// A record that can be converted into the rows of an Arrow [`RecordBatch`].
+///
+/// The columns are named by the FINRA names of the fields and are in the order of the declaration
+/// of the fields in the records. The quantities are the unsigned or signed 64-bit integers, the
+/// dates are `Date32` and the months, like the accounting periods, are `Date32` of their first
+/// days. The optional fields are nullable.
+pub trait ArrowRecord: Sized {
+    /// The schema of the batches of the records.
+    fn schema() -> SchemaRef;
+
+    /// Converts the records into a single batch with the [`ArrowRecord::schema`].
+    fn to_batch(records: &[Self]) -> Result<RecordBatch>;
+}
+
+/// Converts the records of the stream into the Arrow record batches of `chunk_size` records,
+/// except for the last one, which may be shorter. Only a single chunk of the records is held in
+/// memory at a time.
+///
+/// # Panics
+///
+/// If `chunk_size` is zero.
+pub fn record_batches<S, T>(
+    stream: S,
+    chunk_size: usize,
+) -> impl TryStream<Ok = RecordBatch, Error = Error>
+where
+    S: TryStream<Ok = T, Error = Error>,
+    T: ArrowRecord,
+{
+    stream
+        .into_stream()
+        .try_chunks(chunk_size)
+        .map(|chunk| chunk.map_err(|e| e.1))
+        .and_then(|records| future::ready(T::to_batch(&records)))
+}
+
+/// A type of the fields of the records with its Arrow representation.
+trait ArrowValue {
+    type Builder: ArrayBuilder + Default;
+
+    fn data_type() -> DataType;
+
+    fn nullable() -> bool {
+        false
+    }
+
+    fn append(&self, builder: &mut Self::Builder);
+}
+
+impl ArrowValue for String {
+    type Builder = StringBuilder;
+
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn append(&self, builder: &mut StringBuilder) {
+        builder.append_value(self);
+    }
+}
+
+impl ArrowValue for u64 {
+    type Builder = UInt64Builder;
+
+    fn data_type() -> DataType {
+        DataType::UInt64
+    }
+
+    fn append(&self, builder: &mut UInt64Builder) {
+        builder.append_value(*self);
+    }
+}
+
+impl ArrowValue for i64 {
+    type Builder = Int64Builder;
+
+    fn data_type() -> DataType {
+        DataType::Int64
+    }
+
+    fn append(&self, builder: &mut Int64Builder) {
+        builder.append_value(*self);
+    }
+}
+
+impl ArrowValue for f64 {
+    type Builder = Float64Builder;
+
+    fn data_type() -> DataType {
+        DataType::Float64
+    }
+
+    fn append(&self, builder: &mut Float64Builder) {
+        builder.append_value(*self);
+    }
+}
+
+impl ArrowValue for Date {
+    type Builder = Date32Builder;
+
+    fn data_type() -> DataType {
+        DataType::Date32
+    }
+
+    fn append(&self, builder: &mut Date32Builder) {
+        builder.append_value(days_since_epoch(*self));
+    }
+}
+
+impl ArrowValue for YearMonth {
+    type Builder = Date32Builder;
+
+    fn data_type() -> DataType {
+        DataType::Date32
+    }
+
+    fn append(&self, builder: &mut Date32Builder) {
+        builder.append_value(days_since_epoch(self.first_day()));
+    }
+}
+
+/// The optional values are nullable.
+impl<T> ArrowValue for Option<T>
+where
+    T: ArrowValue,
+    T::Builder: NullAppender,
+{
+    type Builder = T::Builder;
+
+    fn data_type() -> DataType {
+        T::data_type()
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+
+    fn append(&self, builder: &mut T::Builder) {
+        match self {
+            Some(value) => value.append(builder),
+            None => builder.append_null_value(),
+        }
+    }
+}
+
+trait NullAppender {
+    fn append_null_value(&mut self);
+}
+
+macro_rules! null_appender {
+    ($($builder:ty),*) => {
+        $(impl NullAppender for $builder {
+            fn append_null_value(&mut self) {
+                self.append_null();
+            }
+        })*
+    };
+}
+
+null_appender!(
+    StringBuilder,
+    UInt64Builder,
+    Int64Builder,
+    Float64Builder,
+    Date32Builder
+);
+
+/// The number of days since the Unix epoch, as used by the Arrow dates.
+fn days_since_epoch(date: Date) -> i32 {
+    const UNIX_EPOCH_JULIAN_DAY: i32 = 2_440_588;
+    date.to_julian_day() - UNIX_EPOCH_JULIAN_DAY
+}
+
+fn field<R, V: ArrowValue>(name: &str, _value: impl Fn(&R) -> &V) -> Field {
+    Field::new(name, V::data_type(), V::nullable())
+}
+
+fn column<R, V: ArrowValue>(records: &[R], value: impl Fn(&R) -> &V) -> ArrayRef {
+    let mut builder = V::Builder::default();
+    for record in records {
+        value(record).append(&mut builder);
+    }
+    builder.finish()
+}
+
+/// Implements [`ArrowRecord`] for a record, mapping its fields onto the columns named by the FINRA
+/// names of the fields.
+macro_rules! arrow_record {
+    ($record:ty { $($field:ident => $name:literal),* $(,)? }) => {
+        impl ArrowRecord for $record {
+            fn schema() -> SchemaRef {
+                Arc::new(Schema::new(vec![$(field($name, |r: &Self| &r.$field)),*]))
+            }
+
+            fn to_batch(records: &[Self]) -> Result<RecordBatch> {
+                RecordBatch::try_new(
+                    Self::schema(),
+                    vec![$(column(records, |r: &Self| &r.$field)),*],
+                )
+                .map_err(|e| Error::Export(e.to_string()))
+            }
+        }
+    };
+}
+
+arrow_record!(ConsolidatedShortInterest {
+    stock_split_flag => "stockSplitFlag",
+    previous_short_position_quantity => "previousShortPositionQuantity",
+    average_daily_volume_quantity => "averageDailyVolumeQuantity",
+    issue_name => "issueName",
+    current_short_position_quantity => "currentShortPositionQuantity",
+    change_previous_number => "changePreviousNumber",
+    accounting_year_month_number => "accountingYearMonthNumber",
+    settlement_date => "settlementDate",
+    market_class_code => "marketClassCode",
+    symbol_code => "symbolCode",
+    days_to_cover_quantity => "daysToCoverQuantity",
+    issuer_services_group_exchange_code => "issuerServicesGroupExchangeCode",
+    revision_flag => "revisionFlag",
+    change_percent => "changePercent",
+});
+
+arrow_record!(EquityShortInterest {
+    stock_split_flag => "stockSplitFlag",
+    previous_short_position_quantity => "previousShortPositionQuantity",
+    average_daily_volume_quantity => "averageDailyVolumeQuantity",
+    issue_name => "issueName",
+    current_short_position_quantity => "currentShortPositionQuantity",
+    change_previous_number => "changePreviousNumber",
+    accounting_year_month_number => "accountingYearMonthNumber",
+    settlement_date => "settlementDate",
+    market_class_code => "marketClassCode",
+    symbol_code => "symbolCode",
+    days_to_cover_quantity => "daysToCoverQuantity",
+    issuer_services_group_exchange_code => "issuerServicesGroupExchangeCode",
+    revision_flag => "revisionFlag",
+    change_percent => "changePercent",
+});
+
+arrow_record!(DailyShortSaleVolume {
+    trade_report_date => "tradeReportDate",
+    symbol => "securitiesInformationProcessorSymbolIdentifier",
+    short_volume => "shortParQuantity",
+    short_exempt_volume => "shortExemptParQuantity",
+    total_volume => "totalParQuantity",
+    market_code => "marketCode",
+    reporting_facility_code => "reportingFacilityCode",
+});
+
+arrow_record!(ThresholdListRecord {
+    trade_date => "tradeDate",
+    issue_symbol_identifier => "issueSymbolIdentifier",
+    issue_name => "issueName",
+    market_class_code => "marketClassCode",
+    reg_sho_threshold_flag => "regShoThresholdFlag",
+    rule_4320_flag => "rule4320Flag",
+});
+
+arrow_record!(WeeklySummaryRecord {
+    week_start_date => "weekStartDate",
+    tier_identifier => "tierIdentifier",
+    tier_description => "tierDescription",
+    issue_symbol_identifier => "issueSymbolIdentifier",
+    issue_name => "issueName",
+    summary_type_code => "summaryTypeCode",
+    market_participant_name => "marketParticipantName",
+    mpid => "MPID",
+    firm_crd_number => "firmCRDNumber",
+    total_weekly_share_quantity => "totalWeeklyShareQuantity",
+    total_weekly_trade_count => "totalWeeklyTradeCount",
+    product_type_code => "productTypeCode",
+    summary_start_date => "summaryStartDate",
+    initial_published_date => "initialPublishedDate",
+    last_update_date => "lastUpdateDate",
+    last_reported_date => "lastReportedDate",
+});
+
+arrow_record!(MonthlySummaryRecord {
+    month_start_date => "monthStartDate",
+    tier_identifier => "tierIdentifier",
+    tier_description => "tierDescription",
+    issue_symbol_identifier => "issueSymbolIdentifier",
+    issue_name => "issueName",
+    summary_type_code => "summaryTypeCode",
+    market_participant_name => "marketParticipantName",
+    mpid => "MPID",
+    firm_crd_number => "firmCRDNumber",
+    total_monthly_share_quantity => "totalMonthlyShareQuantity",
+    total_monthly_trade_count => "totalMonthlyTradeCount",
+    product_type_code => "productTypeCode",
+    summary_start_date => "summaryStartDate",
+    initial_published_date => "initialPublishedDate",
+    last_update_date => "lastUpdateDate",
+    last_reported_date => "lastReportedDate",
+});
+
+arrow_record!(BlocksSummaryRecord {
+    summary_start_date => "summaryStartDate",
+    tier_identifier => "tierIdentifier",
+    tier_description => "tierDescription",
+    issue_symbol_identifier => "issueSymbolIdentifier",
+    issue_name => "issueName",
+    summary_type_code => "summaryTypeCode",
+    market_participant_name => "marketParticipantName",
+    mpid => "MPID",
+    firm_crd_number => "firmCRDNumber",
+    total_block_count => "totalBlockCount",
+    total_block_quantity => "totalBlockQuantity",
+    average_block_size => "averageBlockSize",
+    total_trade_count => "totalTradeCount",
+    total_share_quantity => "totalShareQuantity",
+    initial_published_date => "initialPublishedDate",
+    last_update_date => "lastUpdateDate",
+});
+
+arrow_record!(TreasuryWeeklyAggregates {
+    beginning_of_the_week_date => "beginningOfTheWeekDate",
+    product_category => "productCategory",
+    benchmark => "benchmark",
+    years_to_maturity => "yearsToMaturity",
+    ats_interdealer_count => "atsInterdealerCount",
+    ats_interdealer_volume => "atsInterdealerVolume",
+    dealer_customer_count => "dealerCustomerCount",
+    dealer_customer_volume => "dealerCustomerVolume",
+});
+
+arrow_record!(CorporateMarketBreadth {
+    trade_report_date => "tradeReportDate",
+    product_category => "productCategory",
+    total_securities_traded_count => "totalSecuritiesTradedCount",
+    advances_count => "advancesCount",
+    declines_count => "declinesCount",
+    unchanged_count => "unchangedCount",
+    fifty_two_week_high_count => "fiftyTwoWeekHighCount",
+    fifty_two_week_low_count => "fiftyTwoWeekLowCount",
+});
+
+arrow_record!(FirmRecord {
+    firm_crd_number => "firmCRDNumber",
+    firm_name => "firmName",
+    sec_number => "secNumber",
+    registration_status => "registrationStatus",
+    registration_date => "registrationDate",
+    main_office_city => "mainOfficeCity",
+    main_office_state => "mainOfficeState",
+    main_office_country => "mainOfficeCountry",
+});
@@ -0,0 +1,345 @@
+use std::{future::Future, time::Duration};
+
+use futures::{stream, StreamExt, TryStream, TryStreamExt};
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    endpoints,
+    pager::{self, HttpPages, Page, PageSource},
+    rt, telemetry, DatasetQuery, Error, Finra, Query, ResponseFormat, Result, RetryPolicy,
+};
+
+/// A query submitted to FINRA to be processed asynchronously, as returned by
+/// [`Finra::submit_async_query`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AsyncRequest {
+    /// The FINRA identifier of the request.
+    #[serde(rename = "requestId")]
+    pub id: String,
+
+    /// The data group of the queried dataset. Together with the `name`, it chooses the token used
+    /// with the request, see [`crate::FinraBuilder::dataset_scopes`].
+    #[serde(skip)]
+    pub group: String,
+
+    /// The name of the queried dataset.
+    #[serde(skip)]
+    pub name: String,
+}
+
+/// The state of an [`AsyncRequest`], as returned by [`Finra::poll`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsyncRequestStatus {
+    /// The request is waiting to be processed or is being processed.
+    Pending,
+    /// The results are ready to be downloaded using [`Finra::download`].
+    Completed,
+    /// FINRA could not process the request.
+    Failed { message: Option<String> },
+}
+
+/// The status of a request as returned by FINRA.
+#[derive(Deserialize)]
+struct StatusResponse {
+    status: String,
+    message: Option<String>,
+}
+
+impl Finra {
+    /// Submits the query of the dataset `name` in the `group` to be processed by FINRA
+    /// asynchronously. This is meant for the extracts too large to page through reliably. Use
+    /// [`Finra::poll`] to check whether the results are ready and [`Finra::download`] to read
+    /// them, or [`crate::PaginationStrategy::AsyncDownload`] to do all of it in a single query.
+    ///
+    /// The whole results are requested, so the limit and the pagination of the query are
+    /// ignored. The polls and the download are retried and re-authenticated like the pages of the
+    /// other queries and each of them must finish within the [`crate::FinraBuilder::page_timeout`],
+    /// including the download of the results. The submission is never retried, so that it cannot
+    /// create the same request twice. It is only sent again with a new token if FINRA rejects the
+    /// token, which means the request was not created.
+    ///
+    /// Only available with the `experimental-async-requests` feature.
+    ///
+    /// The endpoints and the statuses of the asynchronous requests follow the FINRA API
+    /// documentation, but they have not been verified against the live API yet.
+    pub async fn submit_async_query(
+        &self,
+        group: &str,
+        name: &str,
+        query: DatasetQuery,
+    ) -> Result<AsyncRequest> {
+        self.submit(group, name, query).await
+    }
+
+    /// Submits the query of the dataset `name` in the `group`, see [`Finra::submit_async_query`].
+    pub(crate) async fn submit<Q: Query>(
+        &self,
+        group: &str,
+        name: &str,
+        query: Q,
+    ) -> Result<AsyncRequest> {
+        let (source, query) = self.prepare(group, name, query).await?;
+        // every submission must reach FINRA to get a request of its own, but only once - a
+        // submission failing on the way back might still have created the request
+        #[cfg(feature = "cache")]
+        let source = source.with_cache(None);
+        let source = source.with_retry_policy(RetryPolicy::none());
+
+        let mut body = serde_json::to_value(&query)?;
+        if let Some(body) = body.as_object_mut() {
+            body.remove("limit");
+            body.remove("offset");
+            body.insert("async".to_string(), true.into());
+        }
+
+        let request: AsyncRequest = source
+            .with_format(ResponseFormat::Json)
+            .fetch_json(serde_json::to_vec(&body)?)
+            .await?;
+        Ok(AsyncRequest {
+            group: group.to_string(),
+            name: name.to_string(),
+            ..request
+        })
+    }
+
+    /// Checks the status of the asynchronous `request`.
+    pub async fn poll(&self, request: &AsyncRequest) -> Result<AsyncRequestStatus> {
+        status(&self.async_source(request, "", ResponseFormat::Json)).await
+    }
+
+    /// Streams the results of the completed asynchronous `request`. The records are decoded as
+    /// the download progresses, exactly like the pages of the synchronous queries.
+    pub async fn download<T: DeserializeOwned>(
+        &self,
+        request: &AsyncRequest,
+    ) -> Result<impl TryStream<Ok = T, Error = Error>> {
+        Ok(self.downloaded(request))
+    }
+
+    /// Submits the query, polls the request every `poll_interval` until it is completed and
+    /// streams its results, as in [`crate::PaginationStrategy::AsyncDownload`]. The query is
+    /// submitted right away, the rest happens as the stream is read.
+    pub(crate) async fn fetch_async<T, Q>(
+        &self,
+        group: &str,
+        name: &str,
+        query: Q,
+        poll_interval: Duration,
+    ) -> Result<impl TryStream<Ok = T, Error = Error>>
+    where
+        T: DeserializeOwned,
+        Q: Query,
+    {
+        let request = self.submit(group, name, query).await?;
+        let status_source = self.async_source(&request, "", ResponseFormat::Json);
+        let records = self.downloaded(&request);
+
+        let completed = async move {
+            loop {
+                match status(&status_source).await? {
+                    AsyncRequestStatus::Completed => return Ok(records),
+                    AsyncRequestStatus::Failed { message } => {
+                        return Err(Error::AsyncRequestFailed {
+                            id: request.id,
+                            message,
+                        })
+                    }
+                    AsyncRequestStatus::Pending => rt::sleep(poll_interval).await,
+                }
+            }
+        };
+        Ok(stream::once(completed).try_flatten())
+    }
+
+    /// The stream of the results of the `request`, downloaded as the stream is read.
+    fn downloaded<T: DeserializeOwned>(
+        &self,
+        request: &AsyncRequest,
+    ) -> impl TryStream<Ok = T, Error = Error> {
+        let source = Download(self.async_source(request, "/data", self.config.response_format));
+
+        // the results are downloaded as a single page
+        pager::paginate(
+            source,
+            DatasetQuery::new().with_limit(u64::MAX),
+            self.config.warnings.clone(),
+            self.config.metrics.clone(),
+            self.config.progress.clone(),
+            self.config.strict_rows,
+            self.config.overflow_policy,
+        )
+        .map_ok(|vs| stream::iter(vs).map(Ok::<T, Error>))
+        .try_flatten()
+    }
+
+    /// The source of the responses at the `suffix` of the URL of the `request` in the `format`.
+    fn async_source(
+        &self,
+        request: &AsyncRequest,
+        suffix: &str,
+        format: ResponseFormat,
+    ) -> HttpPages {
+        let path = format!("{}/{}{}", endpoints::ASYNC_PATH, request.id, suffix);
+        HttpPages::new(
+            self.authenticator_for(&request.group, &request.name),
+            self.config.endpoints.clone(),
+            path.clone(),
+            self.config.metrics.clone(),
+            self.config.retry_policy.clone(),
+            self.config.rate_limiter.clone(),
+            format,
+        )
+        .with_url(Method::GET, self.config.api_url(&path))
+        .with_middlewares(self.config.middlewares.clone())
+        .with_timeout(self.config.page_timeout)
+        .with_retry_budget(self.config.retry_budget.clone())
+    }
+}
+
+/// Reads the status of the request from the `source`.
+async fn status(source: &HttpPages) -> Result<AsyncRequestStatus> {
+    let response: StatusResponse = source.fetch_json(Vec::new()).await?;
+    Ok(match response.status.to_ascii_uppercase().as_str() {
+        "COMPLETED" | "COMPLETE" => AsyncRequestStatus::Completed,
+        "FAILED" | "ERROR" => AsyncRequestStatus::Failed {
+            message: response.message,
+        },
+        _ => AsyncRequestStatus::Pending,
+    })
+}
+
+/// The results of an asynchronous request, downloaded as a single page.
+#[derive(Clone)]
+struct Download(HttpPages);
+
+impl PageSource for Download {
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn fetch<Q: Query>(&self, query: &Q) -> impl Future<Output = Result<Option<Page>>> + Send {
+        let first = query.offset() == 0;
+        let headers = telemetry::trace_headers();
+        let pages = self.0.clone();
+
+        async move {
+            if !first {
+                return Ok(None);
+            }
+            pages.fetch_body(headers, Vec::new()).await
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod test {
+    use super::*;
+    use crate::{
+        testing::{Fixture, MockServer},
+        PaginationStrategy,
+    };
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Record {
+        id: u32,
+    }
+
+    async fn server() -> (MockServer, Finra) {
+        let server = MockServer::start().await.unwrap();
+        server.dataset(
+            "otcMarket",
+            "mock",
+            Fixture::Csv("id\n1\n2\n3\n".to_string()),
+        );
+        let finra = server
+            .builder()
+            .retry_policy(RetryPolicy {
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build();
+        (server, finra)
+    }
+
+    #[tokio::test]
+    async fn submitted_polled_and_downloaded() {
+        let (server, finra) = server().await;
+
+        // the token is rejected once and the download fails once before succeeding
+        server.fail_next(401);
+        let request = finra
+            .submit_async_query("otcMarket", "mock", DatasetQuery::new().with_limit(2))
+            .await
+            .unwrap();
+        assert_eq!(
+            AsyncRequestStatus::Pending,
+            finra.poll(&request).await.unwrap()
+        );
+        assert_eq!(
+            AsyncRequestStatus::Completed,
+            finra.poll(&request).await.unwrap()
+        );
+        server.fail_next(503);
+        let records: Vec<Record> = finra
+            .download(&request)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![Record { id: 1 }, Record { id: 2 }, Record { id: 3 }],
+            records
+        );
+        let requests = server.requests();
+        let logins = requests.iter().filter(|r| r.path.starts_with("/oauth2"));
+        assert_eq!(2, logins.count());
+        let submitted: serde_json::Value = serde_json::from_str(&requests[3].body).unwrap();
+        assert_eq!(Some(&true.into()), submitted.get("async"));
+        assert_eq!(None, submitted.get("limit"));
+        let downloads = requests.iter().filter(|r| r.path == "/async/0/data");
+        assert_eq!(2, downloads.count());
+    }
+
+    #[tokio::test]
+    async fn downloaded_by_pagination_strategy() {
+        let (server, finra) = server().await;
+
+        let query = DatasetQuery::new().pagination(PaginationStrategy::AsyncDownload {
+            poll_interval: Duration::from_millis(1),
+        });
+        let records: Vec<Record> = finra
+            .dataset("otcMarket", "mock", query)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![Record { id: 1 }, Record { id: 2 }, Record { id: 3 }],
+            records
+        );
+        let polls = server.requests();
+        let polls = polls.iter().filter(|r| r.path == "/async/0");
+        assert_eq!(2, polls.count());
+    }
+
+    #[tokio::test]
+    async fn submission_not_retried() {
+        let (server, finra) = server().await;
+
+        server.fail_next(503);
+        let submitted = finra
+            .submit_async_query("otcMarket", "mock", DatasetQuery::new())
+            .await;
+
+        assert!(submitted.is_err());
+        let submissions = server.requests();
+        let submissions = submissions.iter().filter(|r| r.path.contains("/name/mock"));
+        assert_eq!(1, submissions.count());
+    }
+}
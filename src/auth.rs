@@ -0,0 +1,1073 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use crate::{
+    metrics::Metrics,
+    retry::{self, Failure},
+    rt,
+    warning::Warnings,
+    Error, Result, RetryPolicy, Warning,
+};
+use base64::Engine;
+use reqwest::{
+    header::{self, HeaderValue},
+    Client, ClientBuilder, StatusCode,
+};
+use time::{Duration, OffsetDateTime};
+use tracing::Instrument;
+
+#[cfg(not(feature = "tokio"))]
+use futures::lock::Mutex;
+
+#[cfg(feature = "tokio")]
+use tokio::sync::Mutex;
+
+pub(crate) const OAUTH2_ENDPOINT: &str =
+    "https://ews.fip.finra.org/fip/rest/ews/oauth2/access_token?grant_type=client_credentials";
+
+/// How long before the expiry of a token that cannot be refreshed to warn about it.
+const NEAR_EXPIRY_WARNING: Duration = Duration::minutes(5);
+
+/// The OAuth2 access token obtained from FINRA. It can be used to authorize requests made outside
+/// of this crate by setting the `Authorization` header to the value of
+/// [`Token::authorization_header`].
+///
+/// The access token itself is redacted from the `Debug` output.
+#[derive(Clone)]
+pub struct Token {
+    pub access_token: String,
+    /// The scope the token was issued for, if reported by FINRA.
+    pub scope: Option<String>,
+    pub issued_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+}
+
+/// Stores the OAuth2 tokens obtained from FINRA, so that multiple [`crate::Finra`] instances or
+/// processes using the same credentials can share a token instead of each of them authenticating
+/// on its own. FINRA limits how many tokens can be issued.
+///
+/// A stored token is only used if it is valid for long enough and was issued for the requested
+/// scopes. Otherwise a new token is obtained and stored.
+///
+/// To keep the tokens in the OS keychain, e.g. using the `keyring` crate, store the strings
+/// produced by [`Token::to_secret`] under the `client_id` and decode them using
+/// [`Token::from_secret`]. The client credentials can be kept in the keychain the same way and
+/// passed to [`crate::FinraBuilder::new`].
+///
+/// Use [`crate::FinraBuilder::token_store`] to set it up.
+pub trait TokenStore: Send + Sync {
+    /// The last stored token of the `client_id`, if any.
+    fn get(&self, client_id: &str) -> Option<Token>;
+
+    /// Stores the newly obtained `token` of the `client_id`.
+    fn put(&self, client_id: &str, token: &Token);
+}
+
+/// Keeps the tokens in memory. Share it between the instances in an `Arc`.
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    tokens: std::sync::Mutex<HashMap<String, Token>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn get(&self, client_id: &str) -> Option<Token> {
+        self.tokens
+            .lock()
+            .ok()
+            .and_then(|tokens| tokens.get(client_id).cloned())
+    }
+
+    fn put(&self, client_id: &str, token: &Token) {
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.insert(client_id.to_string(), token.clone());
+        }
+    }
+}
+
+impl<T: TokenStore + ?Sized> TokenStore for Arc<T> {
+    fn get(&self, client_id: &str) -> Option<Token> {
+        (**self).get(client_id)
+    }
+
+    fn put(&self, client_id: &str, token: &Token) {
+        (**self).put(client_id, token)
+    }
+}
+
+/// Hands out authorized clients. While the token is valid, this only requires a short read lock.
+/// When the token needs refreshing, only a single refresh is performed at a time and all the
+/// callers waiting for it use its result.
+pub(crate) struct Authenticator {
+    current: RwLock<Option<Authorized>>,
+    refresh: Mutex<RefreshState>,
+    attempts: AtomicU64,
+    refresh_margin: Duration,
+    refresh_jitter: Duration,
+    warnings: Warnings,
+    near_expiry_reported: AtomicBool,
+    /// The scopes requested for the tokens.
+    scopes: Vec<String>,
+}
+
+struct RefreshState {
+    client_getter: ClientGetter,
+    last_failure: Option<String>,
+    /// How long before the expiry of the current token to refresh it.
+    margin: Duration,
+}
+
+#[derive(Clone)]
+pub(crate) struct Authorized {
+    pub(crate) client: Client,
+    pub(crate) token: Token,
+    refresh_at: OffsetDateTime,
+    refreshable: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct LoginData {
+    pub(crate) client_builder: Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    pub(crate) scopes: Vec<String>,
+    pub(crate) clock_skew: Duration,
+    pub(crate) trust_server_time: bool,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) token_store: Option<Arc<dyn TokenStore>>,
+    pub(crate) oauth2_url: String,
+}
+
+pub(crate) enum ClientGetter {
+    Unauthenticated {
+        login_data: LoginData,
+    },
+    Authenticated {
+        login_data: LoginData,
+        client: Client,
+        token: Token,
+    },
+    /// Uses a token obtained outside of this crate. It cannot be refreshed.
+    External {
+        client_builder: Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
+        client: Option<Client>,
+        token: Token,
+    },
+}
+
+impl Token {
+    /// The value of the `Authorization` header to use with this token.
+    pub fn authorization_header(&self) -> String {
+        "Bearer ".to_string() + &self.access_token
+    }
+
+    /// The `margin` before the expiry to refresh the token at, limited to half of the lifetime of
+    /// the token so that short-lived tokens are not refreshed all the time.
+    fn refresh_margin(&self, margin: Duration) -> Duration {
+        margin.min((self.expires_at - self.issued_at) / 2)
+    }
+
+    /// Whether the token is already expired according to the local clock.
+    pub fn is_expired(&self) -> bool {
+        OffsetDateTime::now_utc() >= self.expires_at
+    }
+
+    /// Encodes the token into a string to keep in a secret store, e.g. the OS keychain, by a
+    /// [`TokenStore`]. Use [`Token::from_secret`] to decode it.
+    pub fn to_secret(&self) -> String {
+        serde_json::json!({
+            "access_token": self.access_token,
+            "scope": self.scope,
+            "issued_at": self.issued_at.unix_timestamp(),
+            "expires_at": self.expires_at.unix_timestamp(),
+        })
+        .to_string()
+    }
+
+    /// Decodes the token encoded by [`Token::to_secret`]. `None` if the `secret` is not a token.
+    pub fn from_secret(secret: &str) -> Option<Self> {
+        let json: serde_json::Value = serde_json::from_str(secret).ok()?;
+        let timestamp = |field: &str| {
+            json.get(field)
+                .and_then(serde_json::Value::as_i64)
+                .and_then(|t| OffsetDateTime::from_unix_timestamp(t).ok())
+        };
+
+        Some(Self {
+            access_token: json.get("access_token")?.as_str()?.to_string(),
+            scope: json
+                .get("scope")
+                .and_then(serde_json::Value::as_str)
+                .map(str::to_string),
+            issued_at: timestamp("issued_at")?,
+            expires_at: timestamp("expires_at")?,
+        })
+    }
+}
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("access_token", &"<redacted>")
+            .field("scope", &self.scope)
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl Authenticator {
+    /// Creates a new instance. Each token is refreshed randomly up to `refresh_jitter` before its
+    /// expiry so that many instances sharing the same credentials don't all try to
+    /// re-authenticate at the same moment.
+    pub(crate) fn new(
+        client_getter: ClientGetter,
+        refresh_margin: Duration,
+        refresh_jitter: Duration,
+        warnings: Warnings,
+    ) -> Self {
+        let scopes = match &client_getter {
+            ClientGetter::Unauthenticated { login_data }
+            | ClientGetter::Authenticated { login_data, .. } => login_data.scopes.clone(),
+            ClientGetter::External { .. } => vec![],
+        };
+        Self {
+            current: RwLock::new(None),
+            refresh: Mutex::new(RefreshState {
+                client_getter,
+                last_failure: None,
+                margin: refresh_margin,
+            }),
+            attempts: AtomicU64::new(0),
+            refresh_margin,
+            refresh_jitter,
+            warnings,
+            near_expiry_reported: AtomicBool::new(false),
+            scopes,
+        }
+    }
+
+    /// A new instance obtaining its own tokens with the `scopes` instead of the scopes of this
+    /// one, or `None` if this instance uses an externally obtained token. The new tokens are not
+    /// put in the token store.
+    async fn with_scopes(&self, scopes: Vec<String>) -> Option<Authenticator> {
+        let login_data = match &self.refresh.lock().await.client_getter {
+            ClientGetter::Unauthenticated { login_data }
+            | ClientGetter::Authenticated { login_data, .. } => LoginData {
+                scopes,
+                token_store: None,
+                ..login_data.clone()
+            },
+            ClientGetter::External { .. } => return None,
+        };
+        Some(Authenticator::new(
+            ClientGetter::Unauthenticated { login_data },
+            self.refresh_margin,
+            self.refresh_jitter,
+            self.warnings.clone(),
+        ))
+    }
+
+    /// Returns the authorized client together with the token it uses, authenticating if needed.
+    pub(crate) async fn authorized(&self) -> Result<Authorized> {
+        let authorized = self._authorized().await?;
+
+        if !authorized.refreshable
+            && authorized.token.expires_at - OffsetDateTime::now_utc() < NEAR_EXPIRY_WARNING
+            && !self.near_expiry_reported.swap(true, Ordering::AcqRel)
+        {
+            self.warnings.emit(Warning::TokenNearExpiry {
+                expires_at: authorized.token.expires_at,
+            });
+        }
+
+        Ok(authorized)
+    }
+
+    async fn _authorized(&self) -> Result<Authorized> {
+        if let Some(a) = self.valid_current() {
+            return Ok(a);
+        }
+
+        let attempts_before = self.attempts.load(Ordering::Acquire);
+
+        let mut state = self.refresh.lock().await;
+
+        // someone else might have refreshed the token while we were waiting for the lock
+        if let Some(a) = self.valid_current() {
+            return Ok(a);
+        }
+
+        if self.attempts.load(Ordering::Acquire) != attempts_before {
+            if let Some(failure) = &state.last_failure {
+                return Err(Error::CannotLogin(format!(
+                    "concurrent authentication attempt failed: {}",
+                    failure
+                )));
+            }
+        }
+
+        self.attempts.fetch_add(1, Ordering::AcqRel);
+        // if this attempt is cancelled, the waiters must not mistake an older failure for its own
+        state.last_failure = None;
+
+        let margin = state.margin;
+        let authorized = match state.client_getter.ensure_authenticated(margin).await {
+            Ok(refreshed) => {
+                if refreshed {
+                    state.margin = self.refresh_margin + self.refresh_jitter * fastrand::f64();
+                }
+                state.authorized()
+            }
+            Err(e) => Err(e),
+        };
+
+        match authorized {
+            Ok(a) => {
+                state.last_failure = None;
+                if let Ok(mut current) = self.current.write() {
+                    *current = Some(a.clone());
+                }
+                Ok(a)
+            }
+            Err(e) => {
+                state.last_failure = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Re-authenticates after FINRA rejected the `rejected` token, e.g. because it was revoked
+    /// before its expiry. Returns `None` if the token cannot be replaced because it was provided
+    /// externally.
+    pub(crate) async fn reauthorized(&self, rejected: &Token) -> Result<Option<Authorized>> {
+        let mut state = self.refresh.lock().await;
+
+        // someone else might have already replaced the token while we were waiting for the lock
+        if let Ok(a) = state.authorized() {
+            if a.token.access_token != rejected.access_token {
+                return Ok(Some(a));
+            }
+        }
+
+        self.attempts.fetch_add(1, Ordering::AcqRel);
+        state.last_failure = None;
+
+        let authorized = match state.client_getter.reauthenticate().await {
+            Ok(true) => {
+                state.margin = self.refresh_margin + self.refresh_jitter * fastrand::f64();
+                state.authorized()
+            }
+            Ok(false) => return Ok(None),
+            Err(e) => Err(e),
+        };
+
+        match authorized {
+            Ok(a) => {
+                state.last_failure = None;
+                if let Ok(mut current) = self.current.write() {
+                    *current = Some(a.clone());
+                }
+                Ok(Some(a))
+            }
+            Err(e) => {
+                state.last_failure = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    fn valid_current(&self) -> Option<Authorized> {
+        self.current
+            .read()
+            .ok()
+            .and_then(|c| c.clone())
+            .filter(|a| OffsetDateTime::now_utc() < a.refresh_at)
+    }
+}
+
+/// The authenticators of the datasets with their own scopes, by the lowercase group and the name
+/// of the dataset. They are either configured using [`crate::FinraBuilder::dataset_scopes`] or
+/// added when FINRA rejects a token for not having the scopes a dataset needs.
+#[derive(Clone)]
+pub(crate) struct DatasetAuthenticators(Arc<RwLock<ByDataset>>);
+
+/// The authenticators by the lowercase group and the name of the dataset.
+pub(crate) type ByDataset = HashMap<(String, String), Arc<Authenticator>>;
+
+impl DatasetAuthenticators {
+    pub(crate) fn new(authenticators: ByDataset) -> Self {
+        Self(Arc::new(RwLock::new(authenticators)))
+    }
+}
+
+/// The authenticator of a single dataset - its own one if it has any, otherwise the default one.
+#[derive(Clone)]
+pub(crate) struct DatasetAuthenticator {
+    default: Arc<Authenticator>,
+    datasets: DatasetAuthenticators,
+    dataset: (String, String),
+}
+
+impl DatasetAuthenticator {
+    pub(crate) fn new(
+        default: Arc<Authenticator>,
+        datasets: DatasetAuthenticators,
+        group: &str,
+        name: &str,
+    ) -> Self {
+        Self {
+            default,
+            datasets,
+            dataset: (group.to_lowercase(), name.to_string()),
+        }
+    }
+
+    /// The authenticator to use for the dataset now.
+    pub(crate) fn current(&self) -> Arc<Authenticator> {
+        self.datasets
+            .0
+            .read()
+            .ok()
+            .and_then(|datasets| datasets.get(&self.dataset).cloned())
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// Switches the dataset to the tokens with the `required` scopes in addition to the scopes of
+    /// the `rejected` authenticator, after FINRA rejected its token for not having them. Returns
+    /// `None` if the `rejected` authenticator already requests all of them or uses an externally
+    /// obtained token. Fails with [`Error::InsufficientScope`] if FINRA doesn't grant them.
+    pub(crate) async fn rescoped(
+        &self,
+        rejected: &Arc<Authenticator>,
+        required: &[String],
+    ) -> Result<Option<Authorized>> {
+        let missing: Vec<_> = required
+            .iter()
+            .filter(|scope| !rejected.scopes.contains(scope))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return Ok(None);
+        }
+
+        // someone else might have already switched the dataset while we were waiting for FINRA
+        let current = self.current();
+        if !Arc::ptr_eq(&current, rejected) {
+            return current.authorized().await.map(Some);
+        }
+
+        let scopes = rejected.scopes.iter().cloned().chain(missing).collect();
+        let Some(authenticator) = rejected.with_scopes(scopes).await else {
+            return Ok(None);
+        };
+        let authorized = authenticator.authorized().await?;
+        if let Ok(mut datasets) = self.datasets.0.write() {
+            datasets.insert(self.dataset.clone(), Arc::new(authenticator));
+        }
+        Ok(Some(authorized))
+    }
+}
+
+/// The error for a request FINRA rejected as forbidden even with the token `authorized` by the
+/// `authenticator`, given the `required` scopes FINRA asked for, if any.
+pub(crate) fn insufficient_scope(
+    authenticator: &Authenticator,
+    authorized: &Authorized,
+    required: Vec<String>,
+) -> Error {
+    Error::InsufficientScope {
+        requested: if required.is_empty() {
+            authenticator.scopes.clone()
+        } else {
+            required
+        },
+        granted: authorized.token.scope.clone().unwrap_or_default(),
+    }
+}
+
+/// The scopes FINRA asks for in the `WWW-Authenticate` header of a response rejecting a token for
+/// not having them, as in `Bearer error="insufficient_scope", scope="data otc"` (RFC 6750).
+pub(crate) fn required_scopes(headers: &header::HeaderMap) -> Vec<String> {
+    headers
+        .get_all(header::WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter(|value| value.contains("insufficient_scope"))
+        .flat_map(|value| {
+            let params = value.trim_start();
+            let params = params
+                .get(..7)
+                .filter(|scheme| scheme.eq_ignore_ascii_case("bearer "))
+                .map_or(params, |_| &params[7..]);
+            params.split(',').filter_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                (name.trim() == "scope").then(|| value.trim().trim_matches('"'))
+            })
+        })
+        .flat_map(str::split_whitespace)
+        .map(str::to_string)
+        .collect()
+}
+
+impl RefreshState {
+    fn authorized(&self) -> Result<Authorized> {
+        match (
+            self.client_getter.get_client(),
+            self.client_getter.get_token(),
+        ) {
+            (Some(client), Some(token)) => {
+                let refreshable = !matches!(self.client_getter, ClientGetter::External { .. });
+                Ok(Authorized {
+                    client,
+                    refresh_at: if refreshable {
+                        token.expires_at - token.refresh_margin(self.margin)
+                    } else {
+                        // there's no point in trying to refresh before the token expires
+                        token.expires_at
+                    },
+                    token,
+                    refreshable,
+                })
+            }
+            _ => Err(Error::CannotConstructHttpClient),
+        }
+    }
+}
+
+impl ClientGetter {
+    /// Makes sure there's a token valid for at least `margin`, returning `true` if a new token
+    /// was obtained.
+    async fn ensure_authenticated(&mut self, margin: Duration) -> Result<bool> {
+        match self {
+            Self::Unauthenticated { login_data } => {
+                let ld = login_data.clone();
+                self._authenticated_self(ld, Some(margin)).await?;
+                Ok(true)
+            }
+            Self::Authenticated {
+                login_data,
+                client: _,
+                token,
+            } => {
+                if OffsetDateTime::now_utc() + token.refresh_margin(margin) < token.expires_at {
+                    Ok(false)
+                } else {
+                    let ld = login_data.clone();
+                    self._authenticated_self(ld, Some(margin)).await?;
+                    Ok(true)
+                }
+            }
+            Self::External {
+                client_builder,
+                client,
+                token,
+            } => {
+                if token.is_expired() {
+                    return Err(Error::TokenExpired);
+                }
+
+                if client.is_none() {
+                    *client = Some(Self::_build_authorized_client(client_builder, token)?);
+                }
+
+                // the externally provided token is never refreshed
+                Ok(false)
+            }
+        }
+    }
+
+    /// Obtains a new token regardless of the validity of the current one, returning `false` if
+    /// that is not possible.
+    async fn reauthenticate(&mut self) -> Result<bool> {
+        match self {
+            Self::Unauthenticated { login_data }
+            | Self::Authenticated {
+                login_data,
+                client: _,
+                token: _,
+            } => {
+                let ld = login_data.clone();
+                // the stored token might be the one that was just rejected
+                self._authenticated_self(ld, None).await?;
+                Ok(true)
+            }
+            Self::External { .. } => Ok(false),
+        }
+    }
+
+    fn get_client(&self) -> Option<Client> {
+        match self {
+            Self::Authenticated {
+                client,
+                login_data: _,
+                token: _,
+            } => Some(client.clone()),
+            Self::External {
+                client,
+                client_builder: _,
+                token: _,
+            } => client.clone(),
+            _ => None,
+        }
+    }
+
+    fn get_token(&self) -> Option<Token> {
+        match self {
+            Self::Authenticated {
+                token,
+                login_data: _,
+                client: _,
+            }
+            | Self::External {
+                token,
+                client_builder: _,
+                client: _,
+            } => Some(token.clone()),
+            _ => None,
+        }
+    }
+
+    /// Authenticates using the `login_data`. If `stored_margin` is set, the token in the token
+    /// store is used instead, if it is valid for longer than the margin.
+    async fn _authenticated_self(
+        &mut self,
+        login_data: LoginData,
+        stored_margin: Option<Duration>,
+    ) -> Result<()> {
+        let stored = stored_margin.and_then(|margin| Self::_stored_token(&login_data, margin));
+        let (client, token) = match stored {
+            Some(token) => {
+                tracing::debug!(expires_at = %token.expires_at, "using the stored token");
+                (
+                    Self::_build_authorized_client(&login_data.client_builder, &token)?,
+                    token,
+                )
+            }
+            None => {
+                let span =
+                    tracing::info_span!("finra_authenticate", client_id = %login_data.client_id);
+                let (client, token) = Self::_authenticate_client(login_data.clone())
+                    .instrument(span)
+                    .await?;
+                if let Some(store) = &login_data.token_store {
+                    store.put(&login_data.client_id, &token);
+                }
+                (client, token)
+            }
+        };
+
+        *self = Self::Authenticated {
+            login_data,
+            client,
+            token,
+        };
+
+        Ok(())
+    }
+
+    /// The token from the token store, if it is valid for longer than the `margin` and has the
+    /// requested scopes.
+    fn _stored_token(login_data: &LoginData, margin: Duration) -> Option<Token> {
+        login_data
+            .token_store
+            .as_ref()?
+            .get(&login_data.client_id)
+            .filter(|t| OffsetDateTime::now_utc() + t.refresh_margin(margin) < t.expires_at)
+            .filter(|t| Self::_check_scopes(&login_data.scopes, t).is_ok())
+    }
+
+    async fn _authenticate_client(login_data: LoginData) -> Result<(Client, Token)> {
+        let policy = &login_data.retry_policy;
+        let mut attempt = 1;
+        loop {
+            match Self::_try_authenticate_client(&login_data).await {
+                Ok(res) => {
+                    tracing::debug!(expires_at = %res.1.expires_at, "obtained a new token");
+                    Metrics::add(&login_data.metrics.token_refreshes, 1);
+                    return Ok(res);
+                }
+                Err(Failure::Fatal(e)) => return Err(e),
+                Err(Failure::Transient(e, retry_after)) => {
+                    if attempt >= policy.max_attempts {
+                        return Err(Error::AuthenticationRetriesExhausted {
+                            attempts: attempt,
+                            last_error: Box::new(e),
+                        });
+                    }
+
+                    let delay = retry_after.unwrap_or_else(|| policy.delay(attempt));
+                    tracing::warn!(attempt, ?delay, error = %e, "login attempt failed, retrying");
+                    rt::sleep(delay).await;
+                    Metrics::add(&login_data.metrics.retries, 1);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn _try_authenticate_client(
+        login_data: &LoginData,
+    ) -> std::result::Result<(Client, Token), Failure> {
+        let auth_header = "Basic ".to_string()
+            + &base64::prelude::BASE64_STANDARD
+                .encode(login_data.client_id.clone() + ":" + &login_data.client_secret);
+
+        let login_client = (login_data.client_builder)()
+            .build()
+            .map_err(|e| Failure::Fatal(e.into()))?;
+        let mut login_req = login_client.post(&login_data.oauth2_url);
+        if !login_data.scopes.is_empty() {
+            login_req = login_req.query(&[("scope", login_data.scopes.join(" "))]);
+        }
+        let login_req = login_req.header(header::AUTHORIZATION, auth_header);
+
+        let now = OffsetDateTime::now_utc();
+        Metrics::add(&login_data.metrics.requests, 1);
+        let login_response = login_req.send().await.map_err(Failure::from_http_error)?;
+        let server_offset = if login_data.trust_server_time {
+            Self::_server_clock_offset(login_response.headers())
+        } else {
+            None
+        };
+        let login_status = login_response.status();
+        if login_status == StatusCode::TOO_MANY_REQUESTS {
+            Metrics::add(&login_data.metrics.throttled, 1);
+        }
+        if login_status != StatusCode::OK {
+            let e = Error::CannotLogin(format!(
+                "login attempt failed with status code {}",
+                login_status
+            ));
+            return Err(
+                if login_status.is_server_error() || login_status == StatusCode::TOO_MANY_REQUESTS {
+                    Failure::Transient(e, retry::retry_after(login_response.headers()))
+                } else {
+                    Failure::Fatal(e)
+                },
+            );
+        }
+
+        let login_json: serde_json::Value = login_response
+            .json()
+            .await
+            .map_err(Failure::from_http_error)?;
+
+        let token = Self::_parse_token(&login_json, now, server_offset, login_data.clock_skew)
+            .map_err(Failure::Fatal)?;
+        Self::_check_scopes(&login_data.scopes, &token).map_err(Failure::Fatal)?;
+
+        let client = Self::_build_authorized_client(&login_data.client_builder, &token)
+            .map_err(Failure::Fatal)?;
+
+        Ok((client, token))
+    }
+
+    fn _build_authorized_client(
+        client_builder: &Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
+        token: &Token,
+    ) -> Result<Client> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&token.authorization_header())?,
+        );
+
+        Ok(client_builder().default_headers(headers).build()?)
+    }
+
+    fn _check_scopes(requested: &[String], token: &Token) -> Result<()> {
+        // FINRA doesn't always report the scope. We can only check what we're told.
+        let Some(granted) = &token.scope else {
+            return Ok(());
+        };
+
+        if requested
+            .iter()
+            .all(|r| granted.split_whitespace().any(|g| g == r))
+        {
+            Ok(())
+        } else {
+            Err(Error::InsufficientScope {
+                requested: requested.to_vec(),
+                granted: granted.clone(),
+            })
+        }
+    }
+
+    /// How much the server clock is ahead of the local clock according to the `Date` header of
+    /// the response.
+    fn _server_clock_offset(headers: &header::HeaderMap) -> Option<Duration> {
+        let server_now = headers
+            .get(header::DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+            .map(OffsetDateTime::from)?;
+
+        Some(server_now - OffsetDateTime::now_utc())
+    }
+
+    /// Parses the token from the login response. `now` is the local time the login request was
+    /// sent at. If `server_offset` is known, the issue time reported by the server is converted to
+    /// the local time and the expiry is computed from it. Otherwise the expiry is computed from
+    /// `now`. In both cases, the expiry is moved earlier by `clock_skew`.
+    fn _parse_token(
+        login_json: &serde_json::Value,
+        now: OffsetDateTime,
+        server_offset: Option<Duration>,
+        clock_skew: Duration,
+    ) -> Result<Token> {
+        let valid_for = login_json
+            .get("expires_in")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                Error::CannotLogin(
+                    "the login response didn't contain the expiry of the token".to_string(),
+                )
+            })?;
+
+        let valid_for = valid_for.parse::<i64>().map_err(|e| {
+            Error::CannotLogin(format!(
+                "could not parse the token expiry as a number: {}",
+                e
+            ))
+        })?;
+
+        let access_token = login_json
+            .get("access_token")
+            .ok_or_else(|| {
+                Error::CannotLogin("access_token not present in the login response".to_string())
+            })?
+            .as_str()
+            .ok_or_else(|| {
+                Error::CannotLogin("access_token is not a string in the login response".to_string())
+            })?
+            .to_string();
+
+        let scope = login_json
+            .get("scope")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string());
+
+        // FINRA reports the issue time in milliseconds since the epoch. If it's not there, the
+        // time of sending the login request is the best approximation we've got.
+        let reported_issued_at = login_json
+            .get("issued_at")
+            .and_then(|v| v.as_str())
+            .and_then(|v| v.parse::<i128>().ok())
+            .and_then(|ms| OffsetDateTime::from_unix_timestamp_nanos(ms * 1_000_000).ok());
+
+        let (issued_at, valid_from) = match (reported_issued_at, server_offset) {
+            (Some(issued_at), Some(offset)) => (issued_at - offset, issued_at - offset),
+            (Some(issued_at), None) => (issued_at, now),
+            (None, _) => (now, now),
+        };
+
+        Ok(Token {
+            access_token,
+            scope,
+            issued_at,
+            expires_at: valid_from + Duration::new(valid_for, 0) - clock_skew,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_parsed_and_redacted() {
+        let now = OffsetDateTime::now_utc();
+        let json = serde_json::json!({
+            "access_token": "s3cr3t",
+            "token_type": "Bearer",
+            "expires_in": "7199",
+            "scope": "",
+            "issued_at": "1700000000000",
+        });
+
+        let token = ClientGetter::_parse_token(&json, now, None, Duration::ZERO).unwrap();
+
+        assert_eq!("s3cr3t", token.access_token);
+        assert_eq!(None, token.scope);
+        assert_eq!(1_700_000_000, token.issued_at.unix_timestamp());
+        assert_eq!(now + Duration::new(7199, 0), token.expires_at);
+        assert!(!format!("{:?}", token).contains("s3cr3t"));
+    }
+
+    #[test]
+    fn token_restored_from_secret() {
+        let token = Token {
+            access_token: "s3cr3t".to_string(),
+            scope: Some("data".to_string()),
+            issued_at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+            expires_at: OffsetDateTime::from_unix_timestamp(1_700_007_199).unwrap(),
+        };
+
+        let restored = Token::from_secret(&token.to_secret()).unwrap();
+
+        assert_eq!(token.access_token, restored.access_token);
+        assert_eq!(token.scope, restored.scope);
+        assert_eq!(token.issued_at, restored.issued_at);
+        assert_eq!(token.expires_at, restored.expires_at);
+        assert!(Token::from_secret("not a token").is_none());
+    }
+
+    #[test]
+    fn token_expiry_accounts_for_skew_and_server_clock() {
+        let now = OffsetDateTime::now_utc();
+        let issued_at = now - Duration::minutes(5);
+        let json = serde_json::json!({
+            "access_token": "s3cr3t",
+            "expires_in": "7199",
+            "issued_at": (issued_at.unix_timestamp() * 1000).to_string(),
+        });
+
+        let token = ClientGetter::_parse_token(&json, now, None, Duration::seconds(30)).unwrap();
+        assert_eq!(now + Duration::new(7199 - 30, 0), token.expires_at);
+
+        // the server clock is 5 minutes behind, so the token was in fact issued locally "just now"
+        let token = ClientGetter::_parse_token(
+            &json,
+            now,
+            Some(Duration::minutes(-5)),
+            Duration::seconds(30),
+        )
+        .unwrap();
+        assert_eq!(
+            issued_at.unix_timestamp() + 5 * 60,
+            token.issued_at.unix_timestamp()
+        );
+        assert_eq!(
+            token.issued_at + Duration::new(7199 - 30, 0),
+            token.expires_at
+        );
+    }
+
+    #[tokio::test]
+    async fn stored_token_used_instead_of_login() {
+        let now = OffsetDateTime::now_utc();
+        let store = Arc::new(MemoryTokenStore::new());
+        store.put(
+            "id",
+            &Token {
+                access_token: "stored".to_string(),
+                scope: None,
+                issued_at: now,
+                expires_at: now + Duration::hours(1),
+            },
+        );
+
+        let mut getter = ClientGetter::Unauthenticated {
+            login_data: LoginData {
+                client_builder: Arc::new(Client::builder),
+                client_id: "id".to_string(),
+                client_secret: "secret".to_string(),
+                scopes: vec![],
+                clock_skew: Duration::ZERO,
+                trust_server_time: false,
+                retry_policy: RetryPolicy::default(),
+                metrics: Arc::new(Metrics::default()),
+                token_store: Some(store),
+                oauth2_url: OAUTH2_ENDPOINT.to_string(),
+            },
+        };
+
+        assert!(getter
+            .ensure_authenticated(Duration::minutes(5))
+            .await
+            .unwrap());
+        assert_eq!("stored", getter.get_token().unwrap().access_token);
+    }
+
+    #[tokio::test]
+    async fn cancelled_login_leaves_no_failure_behind() {
+        use std::sync::atomic::AtomicU8;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 0 - the logins fail, 1 - the logins never finish, 2 - the logins succeed
+        let mode = Arc::new(AtomicU8::new(0));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/oauth2", listener.local_addr().unwrap());
+        let server_mode = mode.clone();
+        tokio::spawn(async move {
+            let mut hanging = Vec::new();
+            while let Ok((mut connection, _)) = listener.accept().await {
+                let _ = connection.read(&mut [0; 4096]).await;
+                let response = match server_mode.load(Ordering::SeqCst) {
+                    0 => "HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n"
+                        .to_string(),
+                    1 => {
+                        hanging.push(connection);
+                        continue;
+                    }
+                    _ => {
+                        let body = r#"{"access_token":"new","expires_in":"3600"}"#;
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                };
+                let _ = connection.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let authenticator = Authenticator::new(
+            ClientGetter::Unauthenticated {
+                login_data: LoginData {
+                    client_builder: Arc::new(Client::builder),
+                    client_id: "id".to_string(),
+                    client_secret: "secret".to_string(),
+                    scopes: vec![],
+                    clock_skew: Duration::ZERO,
+                    trust_server_time: false,
+                    retry_policy: RetryPolicy {
+                        max_attempts: 1,
+                        ..RetryPolicy::default()
+                    },
+                    metrics: Arc::new(Metrics::default()),
+                    token_store: None,
+                    oauth2_url: url,
+                },
+            },
+            Duration::minutes(1),
+            Duration::ZERO,
+            Warnings::default(),
+        );
+        assert!(authenticator.authorized().await.is_err());
+
+        // the first login holds the lock, the others wait for it
+        mode.store(1, Ordering::SeqCst);
+        let mut first = Box::pin(authenticator.authorized());
+        let mut second = Box::pin(authenticator.authorized());
+        let mut third = Box::pin(authenticator.authorized());
+        assert!(futures::poll!(&mut first).is_pending());
+        assert!(futures::poll!(&mut second).is_pending());
+        assert!(futures::poll!(&mut third).is_pending());
+
+        // the second login starts once the first one is cancelled, and is cancelled, too
+        drop(first);
+        let wait = std::time::Duration::from_millis(200);
+        assert!(tokio::time::timeout(wait, &mut second).await.is_err());
+        drop(second);
+
+        mode.store(2, Ordering::SeqCst);
+        assert_eq!("new", third.await.unwrap().token.access_token);
+    }
+}
@@ -0,0 +1,421 @@
+//! Streams the records of a FINRA dataset into a file or the standard output, or lists and
+//! describes the available datasets. Built with the `cli` feature, run it without arguments for
+//! the usage.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use finra_rs::{
+    fixed_income::{CorporateMarketBreadth, TreasuryWeeklyAggregates},
+    registration::FirmRecord,
+    BlocksSummaryRecord, ConsolidatedShortInterest, DailyShortSaleVolume, DatasetQuery,
+    EquityShortInterest, Finra, MonthlySummaryRecord, ThresholdListRecord, WeeklySummaryRecord,
+};
+use futures::{TryStream, TryStreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use time::{Date, Month};
+
+const USAGE: &str = "\
+Usage: finra <dataset> [options]
+       finra datasets list [--format <FORMAT>] [--output <PATH>]
+       finra datasets describe <group> <name> [--format <FORMAT>] [--output <PATH>]
+
+Streams the records of a FINRA dataset, authenticating using the FINRA_CLIENT_ID and
+FINRA_CLIENT_SECRET environment variables. Set FINRA_USE_MOCK_DATASETS=true to query the mock
+datasets instead. The datasets commands list all the datasets available in the FINRA API or
+describe the fields of one, written as a table by default.
+
+Datasets:
+  consolidatedShortInterest, equityShortInterest, regShoDaily, weeklySummary, monthlySummary,
+  otcBlocksSummary, thresholdList, treasuryWeeklyAggregates, corporateMarketBreadth,
+  registeredFirms
+
+Options:
+  --fields <FIELD,...>         return only the listed fields
+  --equal <FIELD=VALUE>        only the records with the value of the field, can be repeated
+  --dates <FIELD=FROM..TO>     only the records with the date in the field in the range, e.g.
+                               settlementDate=2024-01-01..2024-02-01
+  --format <FORMAT>            the output format, one of csv (the default), json or its alias
+                               ndjson writing an object per line, table aligning the columns
+                               once all the records are read, or parquet
+  --output <PATH>              the output file, the standard output by default
+";
+
+type Error = Box<dyn std::error::Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+    Table,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    /// Writes the records of the dataset.
+    Records(String),
+    ListDatasets,
+    DescribeDataset {
+        group: String,
+        name: String,
+    },
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    query: DatasetQuery,
+    format: Format,
+    output: Option<PathBuf>,
+}
+
+/// The records that can be written in all the formats.
+#[cfg(feature = "parquet")]
+trait Record: DeserializeOwned + Serialize + finra_rs::ArrowRecord {}
+
+#[cfg(feature = "parquet")]
+impl<T: DeserializeOwned + Serialize + finra_rs::ArrowRecord> Record for T {}
+
+/// The records that can be written in all the formats.
+#[cfg(not(feature = "parquet"))]
+trait Record: DeserializeOwned + Serialize {}
+
+#[cfg(not(feature = "parquet"))]
+impl<T: DeserializeOwned + Serialize> Record for T {}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(args).await {
+        Ok(count) => {
+            eprintln!("{} records written", count);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let dataset = args.next().ok_or("no dataset given")?;
+    let command = if dataset == "datasets" {
+        match args.next().as_deref() {
+            Some("list") => Command::ListDatasets,
+            Some("describe") => Command::DescribeDataset {
+                group: args.next().ok_or("no group of the dataset given")?,
+                name: args.next().ok_or("no name of the dataset given")?,
+            },
+            _ => return Err("datasets needs list or describe".to_string()),
+        }
+    } else {
+        Command::Records(dataset)
+    };
+    let mut query = DatasetQuery::new();
+    let mut filtered = false;
+    let mut format = None;
+    let mut output = None;
+
+    while let Some(option) = args.next() {
+        let mut value = || args.next().ok_or(format!("no value of {}", option));
+        filtered |= matches!(option.as_str(), "--fields" | "--equal" | "--dates");
+        match option.as_str() {
+            "--fields" => query = query.fields(value()?.split(',').map(str::to_string)),
+            "--equal" => {
+                let value = value()?;
+                let (field, value) = value
+                    .split_once('=')
+                    .ok_or(format!("{} is not FIELD=VALUE", value))?;
+                query = query.equal(field, value);
+            }
+            "--dates" => {
+                let value = value()?;
+                let (field, from, to) = value
+                    .split_once('=')
+                    .and_then(|(field, range)| {
+                        let (from, to) = range.split_once("..")?;
+                        Some((field, parse_date(from)?, parse_date(to)?))
+                    })
+                    .ok_or(format!("{} is not FIELD=YYYY-MM-DD..YYYY-MM-DD", value))?;
+                query = query.date_range(field, from..to);
+            }
+            "--format" => {
+                format = Some(match value()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" | "ndjson" => Format::Json,
+                    "table" => Format::Table,
+                    #[cfg(feature = "parquet")]
+                    "parquet" => Format::Parquet,
+                    #[cfg(not(feature = "parquet"))]
+                    "parquet" => return Err("built without the parquet feature".to_string()),
+                    other => return Err(format!("unknown format {}", other)),
+                })
+            }
+            "--output" => output = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+
+    let records = matches!(command, Command::Records(_));
+    if filtered && !records {
+        return Err("the datasets can't be filtered".to_string());
+    }
+    #[cfg(feature = "parquet")]
+    if format == Some(Format::Parquet) && !records {
+        return Err("the datasets can't be written as parquet".to_string());
+    }
+
+    Ok(Args {
+        command,
+        query,
+        format: format.unwrap_or(if records { Format::Csv } else { Format::Table }),
+        output,
+    })
+}
+
+fn parse_date(s: &str) -> Option<Date> {
+    let mut parts = s.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+/// Writes the records of the dataset or the datasets, returning their number.
+async fn run(args: Args) -> Result<u64, Error> {
+    let finra = Finra::from_env()?;
+    let dataset = match &args.command {
+        Command::Records(dataset) => dataset.clone(),
+        Command::ListDatasets => {
+            let datasets = finra.catalog().await?;
+            return write_all(datasets, args).await;
+        }
+        Command::DescribeDataset { group, name } => {
+            let fields = finra.field_metadata(group, name).await?;
+            return write_all(fields, args).await;
+        }
+    };
+    match dataset.as_str() {
+        "consolidatedShortInterest" => {
+            write::<ConsolidatedShortInterest>(&finra, "otcMarket", args).await
+        }
+        "equityShortInterest" => write::<EquityShortInterest>(&finra, "otcMarket", args).await,
+        "regShoDaily" => write::<DailyShortSaleVolume>(&finra, "otcMarket", args).await,
+        "weeklySummary" => write::<WeeklySummaryRecord>(&finra, "otcMarket", args).await,
+        "monthlySummary" => write::<MonthlySummaryRecord>(&finra, "otcMarket", args).await,
+        "otcBlocksSummary" => write::<BlocksSummaryRecord>(&finra, "otcMarket", args).await,
+        "thresholdList" => write::<ThresholdListRecord>(&finra, "otcMarket", args).await,
+        "treasuryWeeklyAggregates" => {
+            write::<TreasuryWeeklyAggregates>(&finra, "fixedIncomeMarket", args).await
+        }
+        "corporateMarketBreadth" => {
+            write::<CorporateMarketBreadth>(&finra, "fixedIncomeMarket", args).await
+        }
+        "registeredFirms" => write::<FirmRecord>(&finra, "registration", args).await,
+        other => Err(format!("unknown dataset {}", other).into()),
+    }
+}
+
+async fn write<T: Record>(finra: &Finra, group: &str, args: Args) -> Result<u64, Error> {
+    let Command::Records(dataset) = &args.command else {
+        unreachable!("only the records of a dataset are written here")
+    };
+    let records = finra.dataset::<T>(group, dataset, args.query).await?;
+
+    #[cfg(feature = "parquet")]
+    if args.format == Format::Parquet {
+        let path = args.output.ok_or("the parquet output needs --output")?;
+        return Ok(finra_rs::sink::write_parquet(records, path).await?);
+    }
+
+    write_text(records, args.format, open_output(&args.output)?).await
+}
+
+/// Writes the already fetched items, like the datasets in the catalog.
+async fn write_all<T: Serialize>(items: Vec<T>, args: Args) -> Result<u64, Error> {
+    let items = futures::stream::iter(items.into_iter().map(Ok));
+    write_text(items, args.format, open_output(&args.output)?).await
+}
+
+fn open_output(path: &Option<PathBuf>) -> io::Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
+
+async fn write_text<S, T>(records: S, format: Format, mut output: impl Write) -> Result<u64, Error>
+where
+    S: TryStream<Ok = T, Error = finra_rs::Error>,
+    T: Serialize,
+{
+    let mut records = Box::pin(records.into_stream());
+    let mut count = 0;
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            while let Some(record) = records.try_next().await? {
+                writer.serialize(&record)?;
+                count += 1;
+            }
+            writer.flush()?;
+        }
+        Format::Json => {
+            while let Some(record) = records.try_next().await? {
+                serde_json::to_writer(&mut output, &record)?;
+                output.write_all(b"\n")?;
+                count += 1;
+            }
+            output.flush()?;
+        }
+        Format::Table => {
+            // the records are written as CSV first to get their columns in the order of the fields
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            while let Some(record) = records.try_next().await? {
+                writer.serialize(&record)?;
+                count += 1;
+            }
+            let rows = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(&writer.into_inner()?[..])
+                .into_records()
+                .collect::<Result<Vec<_>, _>>()?;
+            write_table(&rows, &mut output)?;
+            output.flush()?;
+        }
+        #[cfg(feature = "parquet")]
+        Format::Parquet => unreachable!("written by the sink"),
+    }
+    Ok(count)
+}
+
+/// Writes the rows with the columns padded to the widest value, the numbers aligned to the right.
+fn write_table(rows: &[csv::StringRecord], mut output: impl Write) -> io::Result<()> {
+    let mut widths = Vec::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    for row in rows {
+        let mut line = String::new();
+        for (i, (value, width)) in row.iter().zip(&widths).enumerate() {
+            if i > 0 {
+                line.push_str("  ");
+            }
+            if value.parse::<f64>().is_ok() {
+                line.push_str(&format!("{:>width$}", value));
+            } else {
+                line.push_str(&format!("{:<width$}", value));
+            }
+        }
+        writeln!(output, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &str) -> Result<Args, String> {
+        parse_args(args.split(' ').map(str::to_string))
+    }
+
+    #[test]
+    fn args_parsed() {
+        let parsed = args(
+            "thresholdList --equal issueSymbolIdentifier=AAPL \
+             --dates tradeDate=2024-01-01..2024-02-01 --format json --output out.json",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Command::Records("thresholdList".to_string()),
+            parsed.command
+        );
+        assert_eq!(Format::Json, parsed.format);
+        assert_eq!(Some(PathBuf::from("out.json")), parsed.output);
+        let query = serde_json::to_value(&parsed.query).unwrap();
+        assert_eq!("AAPL", query["compareFilters"][0]["fieldValue"]);
+        assert_eq!("2024-01-01", query["dateRangeFilters"][0]["startDate"]);
+
+        assert_eq!(
+            Format::Json,
+            args("thresholdList --format ndjson").unwrap().format
+        );
+        assert_eq!(
+            Format::Table,
+            args("thresholdList --format table").unwrap().format
+        );
+        assert!(args("thresholdList --format xml").is_err());
+        assert!(args("thresholdList --dates tradeDate=2024-01-01").is_err());
+    }
+
+    #[test]
+    fn datasets_commands_parsed() {
+        let list = args("datasets list").unwrap();
+        assert_eq!(Command::ListDatasets, list.command);
+        assert_eq!(Format::Table, list.format);
+
+        let describe = args("datasets describe otcMarket weeklySummary --format csv").unwrap();
+        assert_eq!(
+            Command::DescribeDataset {
+                group: "otcMarket".to_string(),
+                name: "weeklySummary".to_string(),
+            },
+            describe.command
+        );
+        assert_eq!(Format::Csv, describe.format);
+
+        assert!(args("datasets describe otcMarket").is_err());
+        assert!(args("datasets show").is_err());
+        assert!(args("datasets list --fields name").is_err());
+    }
+
+    #[tokio::test]
+    async fn table_aligned() {
+        #[derive(Serialize)]
+        struct Row {
+            symbol: &'static str,
+            quantity: u64,
+        }
+        let records = futures::stream::iter([
+            Ok(Row {
+                symbol: "AAPL",
+                quantity: 1500,
+            }),
+            Ok(Row {
+                symbol: "T",
+                quantity: 20,
+            }),
+        ]);
+
+        let mut output = Vec::new();
+        let count = write_text(records, Format::Table, &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(2, count);
+        assert_eq!(
+            "symbol  quantity\nAAPL        1500\nT             20\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
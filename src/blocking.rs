@@ -0,0 +1,190 @@
+//! A synchronous API for the code that is not async. It runs the async [`crate::Finra`] on its
+//! own single-threaded tokio runtime.
+//!
+//! ```no_run
+//! use finra_rs::{blocking, ConsolidatedShortInterestQuery, Finra};
+//!
+//! let finra = blocking::Finra::new(Finra::builder("id".into(), "secret".into()).build())?;
+//! for record in finra.consolidated_short_interest(ConsolidatedShortInterestQuery::new(
+//!     None,
+//!     None,
+//!     Some("AAPL".into()),
+//! ))? {
+//!     println!("{:?}", record?);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::pin::Pin;
+
+use futures::{Future, Stream, StreamExt, TryStream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    registration::{FirmQuery, FirmRecord},
+    BlocksSummaryQuery, BlocksSummaryRecord, ConsolidatedShortInterest,
+    ConsolidatedShortInterestQuery, DailyShortSaleVolume, DailyShortSaleVolumeQuery, DatasetInfo,
+    DatasetQuery, EquityShortInterest, EquityShortInterestQuery, Error, FieldMetadata,
+    MonthlySummaryQuery, MonthlySummaryRecord, Result, ThresholdListQuery, ThresholdListRecord,
+    WeeklySummaryQuery, WeeklySummaryRecord,
+};
+#[cfg(feature = "experimental-async-requests")]
+use crate::{AsyncRequest, AsyncRequestStatus};
+
+/// The blocking counterpart of [`crate::Finra`]. The records are returned as iterators that block
+/// while the pages of the results are fetched.
+pub struct Finra {
+    inner: crate::Finra,
+    runtime: Runtime,
+}
+
+/// The records of a query. Each call to `next` blocks until the next record is available.
+pub struct Records<'a, T> {
+    runtime: &'a Runtime,
+    stream: Pin<Box<dyn Stream<Item = Result<T>> + 'a>>,
+}
+
+impl Finra {
+    /// Wraps the async `finra`. Fails if the runtime to run it on cannot be created.
+    pub fn new(finra: crate::Finra) -> std::io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self {
+            inner: finra,
+            runtime,
+        })
+    }
+
+    /// The wrapped async instance.
+    pub fn inner(&self) -> &crate::Finra {
+        &self.inner
+    }
+
+    /// See [`crate::Finra::consolidated_short_interest`].
+    pub fn consolidated_short_interest(
+        &self,
+        query: ConsolidatedShortInterestQuery,
+    ) -> Result<Records<'_, ConsolidatedShortInterest>> {
+        self.records(self.inner.consolidated_short_interest(query))
+    }
+
+    /// See [`crate::Finra::equity_short_interest`].
+    pub fn equity_short_interest(
+        &self,
+        query: EquityShortInterestQuery,
+    ) -> Result<Records<'_, EquityShortInterest>> {
+        self.records(self.inner.equity_short_interest(query))
+    }
+
+    /// See [`crate::Finra::daily_short_sale_volume`].
+    pub fn daily_short_sale_volume(
+        &self,
+        query: DailyShortSaleVolumeQuery,
+    ) -> Result<Records<'_, DailyShortSaleVolume>> {
+        self.records(self.inner.daily_short_sale_volume(query))
+    }
+
+    /// See [`crate::Finra::weekly_summary`].
+    pub fn weekly_summary(
+        &self,
+        query: WeeklySummaryQuery,
+    ) -> Result<Records<'_, WeeklySummaryRecord>> {
+        self.records(self.inner.weekly_summary(query))
+    }
+
+    /// See [`crate::Finra::monthly_summary`].
+    pub fn monthly_summary(
+        &self,
+        query: MonthlySummaryQuery,
+    ) -> Result<Records<'_, MonthlySummaryRecord>> {
+        self.records(self.inner.monthly_summary(query))
+    }
+
+    /// See [`crate::Finra::blocks_summary`].
+    pub fn blocks_summary(
+        &self,
+        query: BlocksSummaryQuery,
+    ) -> Result<Records<'_, BlocksSummaryRecord>> {
+        self.records(self.inner.blocks_summary(query))
+    }
+
+    /// See [`crate::Finra::threshold_list`].
+    pub fn threshold_list(
+        &self,
+        query: ThresholdListQuery,
+    ) -> Result<Records<'_, ThresholdListRecord>> {
+        self.records(self.inner.threshold_list(query))
+    }
+
+    /// See [`crate::Finra::firms`].
+    pub fn firms(&self, query: FirmQuery) -> Result<Records<'_, FirmRecord>> {
+        self.records(self.inner.firms(query))
+    }
+
+    /// See [`crate::Finra::dataset`].
+    pub fn dataset<T: DeserializeOwned + 'static>(
+        &self,
+        group: &str,
+        name: &str,
+        query: DatasetQuery,
+    ) -> Result<Records<'_, T>> {
+        self.records(self.inner.dataset(group, name, query))
+    }
+
+    /// See [`crate::Finra::catalog`].
+    pub fn catalog(&self) -> Result<Vec<DatasetInfo>> {
+        self.runtime.block_on(self.inner.catalog())
+    }
+
+    /// See [`crate::Finra::field_metadata`].
+    pub fn field_metadata(&self, group: &str, name: &str) -> Result<Vec<FieldMetadata>> {
+        self.runtime
+            .block_on(self.inner.field_metadata(group, name))
+    }
+
+    /// See [`crate::Finra::submit_async_query`].
+    #[cfg(feature = "experimental-async-requests")]
+    pub fn submit_async_query(
+        &self,
+        group: &str,
+        name: &str,
+        query: DatasetQuery,
+    ) -> Result<AsyncRequest> {
+        self.runtime
+            .block_on(self.inner.submit_async_query(group, name, query))
+    }
+
+    /// See [`crate::Finra::poll`].
+    #[cfg(feature = "experimental-async-requests")]
+    pub fn poll(&self, request: &AsyncRequest) -> Result<AsyncRequestStatus> {
+        self.runtime.block_on(self.inner.poll(request))
+    }
+
+    /// See [`crate::Finra::download`].
+    #[cfg(feature = "experimental-async-requests")]
+    pub fn download<T: DeserializeOwned + 'static>(
+        &self,
+        request: &AsyncRequest,
+    ) -> Result<Records<'_, T>> {
+        self.records(self.inner.download(request))
+    }
+
+    fn records<'a, T, S>(&'a self, fetch: impl Future<Output = Result<S>>) -> Result<Records<'a, T>>
+    where
+        S: TryStream<Ok = T, Error = Error> + 'a,
+    {
+        let stream = self.runtime.block_on(fetch)?;
+        Ok(Records {
+            runtime: &self.runtime,
+            stream: Box::pin(stream.into_stream()),
+        })
+    }
+}
+
+impl<T> Iterator for Records<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
@@ -0,0 +1,234 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    dates::finra_date,
+    numbers,
+    query::{
+        dataset_fields, CompareFilter, DateRangeFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    PaginationStrategy, Tier,
+};
+
+/// Represents the OTC block trading statistics of a single symbol, either in total or for a single
+/// ATS or non-ATS market participant, depending on the summary type.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct BlocksSummaryRecord {
+    #[serde(rename = "summaryStartDate", with = "finra_date")]
+    pub summary_start_date: Option<Date>,
+
+    #[serde(rename = "tierIdentifier")]
+    pub tier_identifier: String,
+
+    #[serde(rename = "tierDescription")]
+    pub tier_description: String,
+
+    #[serde(rename = "issueSymbolIdentifier")]
+    pub issue_symbol_identifier: String,
+
+    #[serde(rename = "issueName")]
+    pub issue_name: String,
+
+    #[serde(rename = "summaryTypeCode")]
+    pub summary_type_code: String,
+
+    #[serde(rename = "marketParticipantName")]
+    pub market_participant_name: String,
+
+    #[serde(rename = "MPID")]
+    pub mpid: String,
+
+    #[serde(
+        rename = "firmCRDNumber",
+        deserialize_with = "numbers::deserialize_option"
+    )]
+    pub firm_crd_number: Option<u64>,
+
+    #[serde(rename = "totalBlockCount", deserialize_with = "numbers::deserialize")]
+    pub total_block_count: u64,
+
+    #[serde(
+        rename = "totalBlockQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub total_block_quantity: u64,
+
+    #[serde(rename = "averageBlockSize")]
+    pub average_block_size: f64,
+
+    #[serde(rename = "totalTradeCount", deserialize_with = "numbers::deserialize")]
+    pub total_trade_count: u64,
+
+    #[serde(
+        rename = "totalShareQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub total_share_quantity: u64,
+
+    #[serde(rename = "initialPublishedDate", with = "finra_date")]
+    pub initial_published_date: Option<Date>,
+
+    #[serde(rename = "lastUpdateDate", with = "finra_date")]
+    pub last_update_date: Option<Date>,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the OTC blocks summary query
+    /// results.
+    BlocksSummaryField {
+        SummaryStartDate => "summaryStartDate",
+        TierIdentifier => "tierIdentifier",
+        TierDescription => "tierDescription",
+        IssueSymbolIdentifier => "issueSymbolIdentifier",
+        IssueName => "issueName",
+        SummaryTypeCode => "summaryTypeCode",
+        MarketParticipantName => "marketParticipantName",
+        Mpid => "MPID",
+        FirmCrdNumber => "firmCRDNumber",
+        TotalBlockCount => "totalBlockCount",
+        TotalBlockQuantity => "totalBlockQuantity",
+        AverageBlockSize => "averageBlockSize",
+        TotalTradeCount => "totalTradeCount",
+        TotalShareQuantity => "totalShareQuantity",
+        InitialPublishedDate => "initialPublishedDate",
+        LastUpdateDate => "lastUpdateDate",
+    }
+}
+
+/// The query of the OTC blocks summary.
+#[derive(Debug, Clone)]
+pub struct BlocksSummaryQuery {
+    /// If `None`, all fields are included.
+    pub fields: Option<Vec<BlocksSummaryField>>,
+    /// The range of the summary start dates. If `None`, the full available history is included.
+    pub date_range: Option<Range<Date>>,
+    /// If `None` the data for all tiers is included.
+    pub tier: Option<Tier>,
+    /// If `None` the data for all symbols is included.
+    pub symbol: Option<String>,
+    /// Any additional filters the records need to match.
+    pub compare_filters: Vec<CompareFilter>,
+    /// The order of the results. If empty, the order is decided by FINRA.
+    pub sort: Vec<SortField>,
+    /// How the results are fetched from FINRA.
+    pub pagination: PaginationStrategy,
+
+    // These are internally used for paging...
+    limit: u64,
+    offset: u64,
+}
+
+impl BlocksSummaryQuery {
+    pub fn new(
+        fields: Option<Vec<BlocksSummaryField>>,
+        date_range: Option<Range<Date>>,
+        tier: Option<Tier>,
+        symbol: Option<String>,
+    ) -> Self {
+        Self {
+            fields,
+            date_range,
+            tier,
+            symbol,
+            compare_filters: Vec::new(),
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl Query for BlocksSummaryQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(self, by: u64) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, date_range: Range<Date>) {
+        if self.date_range.is_none() {
+            self.date_range = Some(date_range);
+        }
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        BlocksSummaryField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        BlocksSummaryField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        self.symbol.iter_mut().collect()
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref()
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        self.date_range = Some(date_range);
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+}
+
+impl Serialize for BlocksSummaryQuery {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let tier_filter = self
+            .tier
+            .map(|t| CompareFilter::equal(BlocksSummaryField::TierIdentifier, t.as_str()));
+        let symbol_filter = self
+            .symbol
+            .as_ref()
+            .map(|s| CompareFilter::equal(BlocksSummaryField::IssueSymbolIdentifier, s.as_str()));
+
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|r| DateRangeFilter::new(BlocksSummaryField::SummaryStartDate.as_str(), r))
+                .collect(),
+            compare_filters: tier_filter
+                .into_iter()
+                .chain(symbol_filter)
+                .chain(self.compare_filters.iter().cloned())
+                .collect(),
+            domain_filters: Vec::new(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
@@ -0,0 +1,448 @@
+use std::{collections::HashMap, sync::Arc};
+
+use reqwest::ClientBuilder;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    auth::{Authenticator, ClientGetter, LoginData, OAUTH2_ENDPOINT},
+    endpoints::{self, Endpoints},
+    finra::Config,
+    progress::ProgressSink,
+    rate_limit::RateLimiter,
+    warning::Warnings,
+    Error, FailoverPolicy, Finra, Middleware, OverflowPolicy, Progress, RateLimit, ResponseFormat,
+    Result, RetryBudget, RetryPolicy, SymbolMap, SymbolValidator, Token, TokenStore,
+    UnknownSymbolPolicy, Warning,
+};
+
+/// A builder of [`Finra`] instances. Use it when the defaults used by [`Finra::new`] are not
+/// enough.
+pub struct FinraBuilder {
+    client_builder: Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
+    credentials: Credentials,
+    scopes: Vec<String>,
+    dataset_scopes: HashMap<(String, String), Vec<String>>,
+    clock_skew: Duration,
+    trust_server_time: bool,
+    auth_retry_policy: RetryPolicy,
+    token_store: Option<Arc<dyn TokenStore>>,
+    oauth2_url: String,
+    refresh_margin: Duration,
+    refresh_jitter: Duration,
+    config: Config,
+}
+
+const DEFAULT_CLOCK_SKEW: Duration = Duration::seconds(30);
+const DEFAULT_REFRESH_MARGIN: Duration = Duration::minutes(1);
+const CLIENT_ID_VAR: &str = "FINRA_CLIENT_ID";
+const CLIENT_SECRET_VAR: &str = "FINRA_CLIENT_SECRET";
+const API_URL_VAR: &str = "FINRA_API_URL";
+const OAUTH2_URL_VAR: &str = "FINRA_OAUTH2_URL";
+
+enum Credentials {
+    ClientSecret {
+        client_id: String,
+        client_secret: String,
+    },
+    Token(Token),
+}
+
+impl FinraBuilder {
+    /// Creates a builder of an instance authenticating using the provided client credentials.
+    pub fn new(client_id: String, client_secret: String) -> Self {
+        Self::with_credentials(Credentials::ClientSecret {
+            client_id,
+            client_secret,
+        })
+    }
+
+    /// Creates a builder of an instance authenticating using the client credentials from the
+    /// `FINRA_CLIENT_ID` and `FINRA_CLIENT_SECRET` environment variables. The optional
+    /// `FINRA_API_URL` and `FINRA_OAUTH2_URL` variables override the URLs of FINRA, see
+    /// [`FinraBuilder::api_url`] and [`FinraBuilder::oauth2_url`].
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let required = |name: &str| var(name).ok_or_else(|| Error::MissingEnvVar(name.to_string()));
+
+        let mut builder = Self::new(required(CLIENT_ID_VAR)?, required(CLIENT_SECRET_VAR)?);
+        if let Some(url) = var(API_URL_VAR) {
+            builder = builder.api_url(url);
+        }
+        if let Some(url) = var(OAUTH2_URL_VAR) {
+            builder = builder.oauth2_url(url);
+        }
+        Ok(builder)
+    }
+
+    /// Creates a builder of an instance using an access token obtained outside of this crate.
+    /// See [`Finra::with_token`].
+    pub fn with_token(access_token: String, expires_at: OffsetDateTime) -> Self {
+        Self::with_credentials(Credentials::Token(Token {
+            access_token,
+            scope: None,
+            issued_at: OffsetDateTime::now_utc(),
+            expires_at,
+        }))
+    }
+
+    fn with_credentials(credentials: Credentials) -> Self {
+        Self {
+            client_builder: Arc::new(ClientBuilder::new),
+            credentials,
+            scopes: vec![],
+            dataset_scopes: HashMap::new(),
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            trust_server_time: false,
+            auth_retry_policy: RetryPolicy::default(),
+            token_store: None,
+            oauth2_url: OAUTH2_ENDPOINT.to_string(),
+            refresh_margin: DEFAULT_REFRESH_MARGIN,
+            refresh_jitter: Duration::ZERO,
+            config: Config::default(),
+        }
+    }
+
+    /// Sets the function for obtaining new reqwest client builders. You can use this to set up
+    /// a builder with a proxy or whatever other requirements you have.
+    pub fn client_builder(
+        mut self,
+        client_builder: Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
+    ) -> Self {
+        self.client_builder = client_builder;
+        self
+    }
+
+    /// Whether to query the mock datasets instead of the real ones. Defaults to `false`.
+    ///
+    /// Not available with the `no-mock-datasets` feature, which guarantees that the real
+    /// datasets are always queried.
+    #[cfg(not(feature = "no-mock-datasets"))]
+    pub fn use_mock_datasets(mut self, use_mock_datasets: bool) -> Self {
+        self.config.use_mock_datasets = use_mock_datasets;
+        self
+    }
+
+    /// The OAuth2 scopes to request when authenticating. If the token issued by FINRA doesn't
+    /// include all of them, the authentication fails with [`crate::Error::InsufficientScope`].
+    /// By default, no specific scopes are requested.
+    ///
+    /// Has no effect on instances using an externally obtained token.
+    pub fn scopes(mut self, scopes: Vec<String>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// The OAuth2 scopes to request instead of [`FinraBuilder::scopes`] for querying the records
+    /// of the dataset `name` in the data `group`, e.g. if the dataset needs a scope the other
+    /// datasets don't. The group is matched regardless of the case, like FINRA does.
+    ///
+    /// The records of the dataset are then queried using a separate token, obtained when the
+    /// dataset is first queried and refreshed, or re-obtained when FINRA rejects it, the same way
+    /// as the default token. These tokens are not shared using the
+    /// [`FinraBuilder::token_store`], which is keyed just by the client ID.
+    ///
+    /// Without the scopes set here, a dataset is still queried if FINRA names the scopes it is
+    /// missing when rejecting the token: the client then authenticates again with them added and
+    /// keeps using that token for the dataset. A request FINRA still forbids fails with
+    /// [`crate::Error::InsufficientScope`], as do the forbidden requests of the instances using an
+    /// externally obtained token.
+    ///
+    /// Has no effect on instances using an externally obtained token.
+    pub fn dataset_scopes(
+        mut self,
+        group: impl Into<String>,
+        name: impl Into<String>,
+        scopes: Vec<String>,
+    ) -> Self {
+        self.dataset_scopes
+            .insert((group.into().to_lowercase(), name.into()), scopes);
+        self
+    }
+
+    /// Shares the tokens with the other instances or processes using the same store, instead of
+    /// always authenticating on the first request. By default, the tokens are not shared.
+    ///
+    /// Has no effect on instances using an externally obtained token.
+    pub fn token_store(mut self, store: impl TokenStore + 'static) -> Self {
+        self.token_store = Some(Arc::new(store));
+        self
+    }
+
+    /// How much earlier than reported by FINRA to consider the token expired, to account for the
+    /// differences between the local and server clocks. Defaults to 30 seconds.
+    pub fn clock_skew(mut self, clock_skew: Duration) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    /// If `true`, the `Date` header of the login response is used to estimate the difference
+    /// between the local and server clocks, and the token expiry is computed from the issue time
+    /// reported by the server. Use this on machines with drifting clocks. Defaults to `false`.
+    pub fn trust_server_time(mut self, trust_server_time: bool) -> Self {
+        self.trust_server_time = trust_server_time;
+        self
+    }
+
+    /// How to retry the authentication when it fails due to a transient error like a network
+    /// failure or a server error. If all the attempts fail, the authentication fails with
+    /// [`crate::Error::AuthenticationRetriesExhausted`]. Defaults to [`RetryPolicy::default`].
+    pub fn auth_retry_policy(mut self, auth_retry_policy: RetryPolicy) -> Self {
+        self.auth_retry_policy = auth_retry_policy;
+        self
+    }
+
+    /// How to retry the requests for the pages of the results when they fail due to a transient
+    /// error like a network failure, a server error or exceeding the request quota. The delay
+    /// requested by FINRA in the `Retry-After` header is honored. Defaults to
+    /// [`RetryPolicy::default`].
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config.retry_policy = retry_policy;
+        self
+    }
+
+    /// Limits the retries of the page requests of all the queries by the `budget` on top of the
+    /// [`FinraBuilder::retry_policy`] of each request. There is no limit by default.
+    pub fn retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.config.retry_budget = Some(budget);
+        self
+    }
+
+    /// Fails the requests for the pages of the results that don't finish within the `timeout`,
+    /// including the reading of the page. A timed out request is retried according to the
+    /// [`FinraBuilder::retry_policy`] and fails with [`crate::Error::Timeout`] once the retries
+    /// are exhausted. A page whose reading times out or is interrupted is fetched again from its
+    /// start, skipping the records already returned. By default, the requests never time out.
+    pub fn page_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.page_timeout = Some(timeout);
+        self
+    }
+
+    /// Stores the pages of the query results in the `cache` and reads them from there when the
+    /// same query is repeated, instead of downloading them from FINRA again. See
+    /// [`crate::FileCache`] for a cache on the disk.
+    #[cfg(feature = "cache")]
+    pub fn cache(mut self, cache: impl crate::CacheBackend + 'static) -> Self {
+        self.config.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Fails the stream of the results of a query with [`crate::Error::Timeout`] if the results
+    /// are not read whole within the `deadline`, measured from when the reading starts. By
+    /// default, there is no deadline.
+    pub fn query_deadline(mut self, deadline: std::time::Duration) -> Self {
+        self.config.query_deadline = Some(deadline);
+        self
+    }
+
+    /// Limits the rate of the requests for the pages of the results, shared by all the queries of
+    /// the instance. By default, the rate is not limited.
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.config.rate_limiter = Arc::new(RateLimiter::new(&rate_limit));
+        self
+    }
+
+    /// Refresh each token when it is valid for less than `refresh_margin`, so that it doesn't
+    /// expire while a request is being made with it. The token is checked before each page of the
+    /// results, so long streams keep using fresh tokens. Defaults to 1 minute.
+    ///
+    /// The margin is limited to half of the lifetime of the tokens.
+    pub fn refresh_margin(mut self, refresh_margin: Duration) -> Self {
+        self.refresh_margin = refresh_margin;
+        self
+    }
+
+    /// Refresh each token at a random moment up to `refresh_jitter` before the refresh margin.
+    /// Use this when many instances share the same credentials so that they don't all hit the
+    /// FINRA authentication endpoint at the same time. Defaults to zero.
+    pub fn refresh_jitter(mut self, refresh_jitter: Duration) -> Self {
+        self.refresh_jitter = refresh_jitter;
+        self
+    }
+
+    /// How far back into the history to query when a query doesn't specify any date range. This
+    /// protects against accidentally requesting the full history, which is huge. E.g. a month
+    /// covers the last 2 settlement periods of the short interest data.
+    ///
+    /// By default, the full history is queried.
+    pub fn default_lookback(mut self, lookback: Duration) -> Self {
+        self.config.default_lookback = Some(lookback);
+        self
+    }
+
+    /// The format of the responses to request from FINRA. Defaults to [`ResponseFormat::Csv`].
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.config.response_format = response_format;
+        self
+    }
+
+    /// If `true`, a row of the results that cannot be deserialized fails the stream with
+    /// [`crate::Error::RowDeserialization`]. Otherwise, such rows are skipped and reported as
+    /// [`Warning::DroppedRows`]. Defaults to `false`.
+    ///
+    /// The records fill the fields missing from the results with their defaults, e.g. zero
+    /// quantities, which are indistinguishable from the real values. If `true`, the results
+    /// missing any of the fields requested by the query also fail the stream with
+    /// [`crate::Error::MissingFields`]. A blank or malformed number is never read as zero - the row
+    /// with it cannot be deserialized.
+    pub fn strict_rows(mut self, strict_rows: bool) -> Self {
+        self.config.strict_rows = strict_rows;
+        self
+    }
+
+    /// Decides what happens with the quantities in the results that don't fit into the numeric
+    /// types of their fields. Defaults to failing the query with
+    /// [`crate::Error::NumericOverflow`], regardless of [`FinraBuilder::strict_rows`].
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.config.overflow_policy = policy;
+        self
+    }
+
+    /// Sets up a function to be called with the non-fatal conditions encountered while talking to
+    /// FINRA, like skipped malformed rows or a soon to expire token. By default, these are ignored.
+    pub fn warnings(mut self, sink: impl Fn(Warning) + Send + Sync + 'static) -> Self {
+        self.config.warnings = Warnings::new(Arc::new(sink));
+        self
+    }
+
+    /// Sets the URL to obtain the OAuth2 tokens from instead of the FINRA one, e.g. to use the
+    /// FINRA test environment or a mock server in the integration tests. The URL must include the
+    /// `grant_type=client_credentials` query parameter if the server requires it.
+    ///
+    /// Has no effect on the instances using an externally obtained token.
+    pub fn oauth2_url(mut self, url: impl Into<String>) -> Self {
+        self.oauth2_url = url.into();
+        self
+    }
+
+    /// Sets the base URL of the FINRA API to use instead of `https://api.finra.org`, e.g. to use
+    /// the FINRA test environment or a mock server in the integration tests. This applies to all
+    /// the requests except the authentication, see [`FinraBuilder::oauth2_url`] for that. Call
+    /// [`FinraBuilder::base_urls`] after this to set up the data API separately.
+    pub fn api_url(mut self, url: impl Into<String>) -> Self {
+        let url = url.into();
+        let data_url = format!("{}/{}", url.trim_end_matches('/'), endpoints::DATA_PATH);
+        self.config.endpoints = Arc::new(Endpoints::new(vec![data_url], FailoverPolicy::default()));
+        self.config.api_url = Some(url);
+        self
+    }
+
+    /// Sets the base URLs of the FINRA data API to use instead of
+    /// `https://api.finra.org/data/group`, e.g. to send the requests through an internal caching
+    /// gateway. The requests are sent to the `primary` unless it fails repeatedly, in which case
+    /// they fail over to the first healthy of the `fallbacks`. The primary is tried again after a
+    /// while so that the requests return back to it once it recovers. See [`FailoverPolicy`] for
+    /// the details.
+    pub fn base_urls(
+        mut self,
+        primary: impl Into<String>,
+        fallbacks: impl IntoIterator<Item = String>,
+        policy: FailoverPolicy,
+    ) -> Self {
+        let base_urls = std::iter::once(primary.into()).chain(fallbacks).collect();
+        self.config.endpoints = Arc::new(Endpoints::new(base_urls, policy));
+        self
+    }
+
+    /// Adds the `middleware` to customize the requests sent to FINRA, e.g. to add custom headers
+    /// or to sign the requests. The middlewares see the requests in the order they are added in.
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.config.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sets up a function to be called with the [`Progress`] of reading the results of the
+    /// queries, after each page of the results. By default, the progress is not reported.
+    pub fn progress(mut self, sink: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.config.progress = ProgressSink::new(Arc::new(sink));
+        self
+    }
+
+    /// Shows the progress of reading the results of all the queries in the `bar`. See
+    /// [`FinraBuilder::progress`].
+    ///
+    /// The bar can be shared by concurrently running queries - its length grows by the number of
+    /// the records of each query. The number of downloaded bytes is set as the message of the
+    /// bar, so use `{msg}` in its template to show it.
+    #[cfg(feature = "indicatif")]
+    pub fn progress_bar(self, bar: indicatif::ProgressBar) -> Self {
+        self.progress(crate::progress::progress_bar_sink(bar))
+    }
+
+    /// Maps the symbols used in the queries to the symbols used by FINRA before they are
+    /// validated by the [`FinraBuilder::symbol_validator`], if any.
+    pub fn symbol_map(mut self, map: SymbolMap) -> Self {
+        self.config.symbol_map = Some(map);
+        self
+    }
+
+    /// Sets up the normalization and validation of the symbols used in the queries. The `policy`
+    /// decides whether an unknown symbol is just reported as a [`Warning`] or fails the query.
+    pub fn symbol_validator(
+        mut self,
+        validator: impl SymbolValidator + 'static,
+        policy: UnknownSymbolPolicy,
+    ) -> Self {
+        self.config.symbol_validator = Some((Arc::new(validator), policy));
+        self
+    }
+
+    pub fn build(self) -> Finra {
+        let authenticator = |client_getter| {
+            Arc::new(Authenticator::new(
+                client_getter,
+                self.refresh_margin,
+                self.refresh_jitter,
+                self.config.warnings.clone(),
+            ))
+        };
+
+        let (default, datasets) = match self.credentials {
+            Credentials::ClientSecret {
+                client_id,
+                client_secret,
+            } => {
+                let login_data = LoginData {
+                    client_builder: self.client_builder,
+                    client_id,
+                    client_secret,
+                    scopes: self.scopes,
+                    clock_skew: self.clock_skew,
+                    trust_server_time: self.trust_server_time,
+                    retry_policy: self.auth_retry_policy,
+                    metrics: self.config.metrics.clone(),
+                    token_store: self.token_store,
+                    oauth2_url: self.oauth2_url,
+                };
+                let datasets = self
+                    .dataset_scopes
+                    .into_iter()
+                    .map(|(dataset, scopes)| {
+                        let login_data = LoginData {
+                            scopes,
+                            token_store: None,
+                            ..login_data.clone()
+                        };
+                        (
+                            dataset,
+                            authenticator(ClientGetter::Unauthenticated { login_data }),
+                        )
+                    })
+                    .collect();
+                (
+                    authenticator(ClientGetter::Unauthenticated { login_data }),
+                    datasets,
+                )
+            }
+            Credentials::Token(token) => (
+                authenticator(ClientGetter::External {
+                    client_builder: self.client_builder,
+                    client: None,
+                    token,
+                }),
+                HashMap::new(),
+            ),
+        };
+
+        Finra::from_parts(default, datasets, self.config)
+    }
+}
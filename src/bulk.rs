@@ -0,0 +1,147 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    hash::Hash,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+
+use crate::{Error, Result};
+
+/// How many subtasks of a bulk operation, e.g. one per symbol, may fail before the rest of them
+/// is cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorTolerance {
+    /// Cancel the remaining subtasks on the first failure.
+    #[default]
+    FailFast,
+    /// Cancel the remaining subtasks once more than this many failed.
+    AtMost(usize),
+    /// Run all the subtasks regardless of the failures.
+    All,
+}
+
+impl ErrorTolerance {
+    fn tolerates(&self, failures: usize) -> bool {
+        match self {
+            Self::FailFast => failures == 0,
+            Self::AtMost(n) => failures <= *n,
+            Self::All => true,
+        }
+    }
+}
+
+/// The outcome of each subtask of a bulk operation, identified by its key, e.g. the symbol.
+#[derive(Debug)]
+pub struct BulkResult<K, V> {
+    /// The results of the successful subtasks.
+    pub ok: HashMap<K, V>,
+    /// The errors of the failed subtasks.
+    pub failed: HashMap<K, Error>,
+    /// The subtasks that were cancelled or not started at all because too many subtasks failed.
+    pub cancelled: Vec<K>,
+}
+
+impl<K, V> Default for BulkResult<K, V> {
+    fn default() -> Self {
+        Self {
+            ok: HashMap::new(),
+            failed: HashMap::new(),
+            cancelled: Vec::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> BulkResult<K, V> {
+    /// Whether all the subtasks succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.cancelled.is_empty()
+    }
+
+    /// The results of all the subtasks, or the error of any of the failed ones.
+    pub fn into_result(self) -> Result<HashMap<K, V>> {
+        match self.failed.into_values().next() {
+            Some(e) => Err(e),
+            None => Ok(self.ok),
+        }
+    }
+}
+
+/// Runs the `task` for each of the `keys`, at most `concurrency` of them at the same time. The
+/// subtasks are polled by the returned future, so none of them outlives it - once too many of
+/// them fail according to the `tolerance`, or the future is dropped, the running ones are
+/// cancelled.
+pub(crate) async fn fan_out<K, V, F, Fut>(
+    keys: impl IntoIterator<Item = K>,
+    concurrency: usize,
+    tolerance: ErrorTolerance,
+    task: F,
+) -> BulkResult<K, V>
+where
+    K: Clone + Eq + Hash,
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<V>>,
+{
+    let mut keys = keys.into_iter();
+    let mut running = FuturesUnordered::new();
+    let mut in_flight = HashSet::new();
+    let mut result = BulkResult::default();
+
+    loop {
+        while running.len() < concurrency.max(1) {
+            let Some(key) = keys.next() else {
+                break;
+            };
+            in_flight.insert(key.clone());
+            let subtask = task(key.clone());
+            running.push(async move { (key, subtask.await) });
+        }
+
+        let Some((key, outcome)) = running.next().await else {
+            break;
+        };
+        in_flight.remove(&key);
+        match outcome {
+            Ok(value) => {
+                result.ok.insert(key, value);
+            }
+            Err(e) => {
+                result.failed.insert(key, e);
+                if !tolerance.tolerates(result.failed.len()) {
+                    result.cancelled.extend(in_flight.into_iter().chain(keys));
+                    break;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn cancelled_after_tolerated_failures() {
+        let run = |tolerance| {
+            fan_out(1..=5, 1, tolerance, |n: u32| async move {
+                if n.is_multiple_of(2) {
+                    Err(Error::UnknownSymbol(n.to_string()))
+                } else {
+                    Ok(n)
+                }
+            })
+        };
+
+        let result = run(ErrorTolerance::FailFast).await;
+        assert_eq!(result.ok.len(), 1);
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.cancelled, vec![3, 4, 5]);
+
+        let result = run(ErrorTolerance::All).await;
+        assert_eq!(result.ok.len(), 3);
+        assert_eq!(result.failed.len(), 2);
+        assert!(result.cancelled.is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+//! Readers of the files published on finra.org for manual download, so that the history predating
+//! the API access can be read into the same records as the API results.
+//!
+//! Each [`BulkFileFormat`] maps the columns of a file format onto the fields of the records of the
+//! API and normalizes the values to the form the API returns them in. The records of a dataset
+//! are then the same regardless of whether they come from the API or from a file.
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    marker::PhantomData,
+};
+
+use csv::StringRecord;
+use serde::de::DeserializeOwned;
+use time::{Date, Month};
+
+use crate::{dates, DailyShortSaleVolume, Error, Result, ThresholdListRecord};
+
+/// A format of the files published on finra.org, mapped onto the records of a dataset.
+pub trait BulkFileFormat {
+    /// The records of the dataset, the same as returned by the API.
+    type Record: DeserializeOwned;
+
+    /// The API name of the field in the `column` of the file, or `None` if the column is to be
+    /// ignored.
+    fn field(&self, column: &str) -> Option<String>;
+
+    /// Converts the `value` of the API `field` into the form the API returns it in. Fails with
+    /// the explanation why the value is invalid.
+    fn normalize(&self, _field: &str, value: &str) -> std::result::Result<String, String> {
+        Ok(value.to_string())
+    }
+
+    /// The values of the API fields that are not in the files, e.g. because they are only given
+    /// by the file names.
+    fn extra_fields(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
+
+/// The files whose columns are named as the API fields of `T`, e.g. the short interest files.
+pub struct ApiFields<T>(PhantomData<T>);
+
+impl<T> Default for ApiFields<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: DeserializeOwned> BulkFileFormat for ApiFields<T> {
+    type Record = T;
+
+    fn field(&self, column: &str) -> Option<String> {
+        Some(column.to_string())
+    }
+}
+
+/// The Reg SHO daily short sale volume files of a single reporting facility, e.g.
+/// `CNMSshvol20240102.txt` of the consolidated NMS data. The files don't say which facility they
+/// come from, only their names do, so the `reporting_facility_code` needs to be provided, e.g.
+/// `"CNMS"`.
+///
+/// The dates are converted to the format used by the API and the fractional volumes found in the
+/// newer files are rounded.
+pub struct DailyShortSaleVolumeFile {
+    pub reporting_facility_code: String,
+}
+
+impl BulkFileFormat for DailyShortSaleVolumeFile {
+    type Record = DailyShortSaleVolume;
+
+    fn field(&self, column: &str) -> Option<String> {
+        let field = match column {
+            "Date" => "tradeReportDate",
+            "Symbol" => "securitiesInformationProcessorSymbolIdentifier",
+            "ShortVolume" => "shortParQuantity",
+            "ShortExemptVolume" => "shortExemptParQuantity",
+            "TotalVolume" => "totalParQuantity",
+            "Market" => "marketCode",
+            _ => return None,
+        };
+        Some(field.to_string())
+    }
+
+    fn normalize(&self, field: &str, value: &str) -> std::result::Result<String, String> {
+        match field {
+            "tradeReportDate" => compact_date(value),
+            "shortParQuantity" | "shortExemptParQuantity" | "totalParQuantity" => volume(value),
+            _ => Ok(value.to_string()),
+        }
+    }
+
+    fn extra_fields(&self) -> Vec<(String, String)> {
+        vec![(
+            "reportingFacilityCode".to_string(),
+            self.reporting_facility_code.clone(),
+        )]
+    }
+}
+
+/// The daily Reg SHO threshold list files of the OTC equities, e.g. `otcthr20240102.txt`. The
+/// files don't include the trade date, only their names do, so the `trade_date` needs to be
+/// provided.
+pub struct ThresholdListFile {
+    pub trade_date: Date,
+}
+
+impl BulkFileFormat for ThresholdListFile {
+    type Record = ThresholdListRecord;
+
+    fn field(&self, column: &str) -> Option<String> {
+        let field = match column {
+            "Symbol" => "issueSymbolIdentifier",
+            "Security Name" => "issueName",
+            "Market Category" => "marketClassCode",
+            "Reg SHO Threshold Flag" => "regShoThresholdFlag",
+            "Rule 4320" => "rule4320Flag",
+            _ => return None,
+        };
+        Some(field.to_string())
+    }
+
+    fn extra_fields(&self) -> Vec<(String, String)> {
+        vec![(
+            "tradeDate".to_string(),
+            dates::format_date(&self.trade_date),
+        )]
+    }
+}
+
+/// Reads the Reg SHO daily short sale volume file of a single reporting facility. See
+/// [`DailyShortSaleVolumeFile`].
+pub fn read_daily_short_sale_volume_file<R: Read>(
+    reader: R,
+    reporting_facility_code: &str,
+) -> Result<impl Iterator<Item = Result<DailyShortSaleVolume>>> {
+    read_bulk_file_as(
+        reader,
+        DailyShortSaleVolumeFile {
+            reporting_facility_code: reporting_facility_code.to_string(),
+        },
+    )
+}
+
+/// Reads the records from a file published on finra.org whose columns are named as the fields of
+/// `T`. See [`ApiFields`].
+pub fn read_bulk_file<T: DeserializeOwned, R: Read>(
+    reader: R,
+) -> Result<impl Iterator<Item = Result<T>>> {
+    read_bulk_file_as(reader, ApiFields::<T>::default())
+}
+
+/// Reads the records from a file published on finra.org in the `format`. The columns can be
+/// separated either by commas or by the pipe characters. The trailer line with the number of the
+/// records some files end with is skipped.
+pub fn read_bulk_file_as<F: BulkFileFormat, R: Read>(
+    reader: R,
+    format: F,
+) -> Result<impl Iterator<Item = Result<F::Record>>> {
+    let mut reader = BufReader::new(reader);
+    let header = reader.fill_buf().map_err(csv::Error::from)?;
+    let header = header.split(|b| *b == b'\n').next().unwrap_or_default();
+    let delimiter = if header.contains(&b'|') { b'|' } else { b',' };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+
+    let columns: Vec<Option<String>> = reader
+        .headers()?
+        .iter()
+        .map(|c| format.field(c.trim()))
+        .collect();
+    let extra = format.extra_fields();
+    let fields: StringRecord = columns
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .chain(extra.iter().map(|(f, _)| f.as_str()))
+        .collect();
+
+    Ok(reader
+        .into_records()
+        .filter(|record| !matches!(record, Ok(r) if is_trailer(r)))
+        .map(move |record| {
+            let record = record?;
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            let mut values = StringRecord::new();
+            for (field, value) in columns.iter().zip(record.iter()) {
+                if let Some(field) = field {
+                    let value = format
+                        .normalize(field, value.trim())
+                        .map_err(|message| Error::BulkFile { line, message })?;
+                    values.push_field(&value);
+                }
+            }
+            for (_, value) in &extra {
+                values.push_field(value);
+            }
+
+            Ok(values.deserialize(Some(&fields))?)
+        }))
+}
+
+/// Whether the `record` is the trailer line with the number of the records in the file.
+fn is_trailer(record: &StringRecord) -> bool {
+    record.len() == 1 && record[0].trim().bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Converts the dates in the `YYYYMMDD` format used in the files into the `YYYY-MM-DD` format of
+/// the API.
+fn compact_date(s: &str) -> std::result::Result<String, String> {
+    (s.len() == 8)
+        .then(|| {
+            let month: u8 = s[4..6].parse().ok()?;
+            Date::from_calendar_date(
+                s[..4].parse().ok()?,
+                Month::try_from(month).ok()?,
+                s[6..].parse().ok()?,
+            )
+            .ok()
+        })
+        .flatten()
+        .map(|date| dates::format_date(&date))
+        .ok_or_else(|| format!("invalid date: {}", s))
+}
+
+/// Rounds the fractional volumes.
+fn volume(s: &str) -> std::result::Result<String, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v >= 0.0 && v.is_finite() => Ok((v.round() as u64).to_string()),
+        _ => Err(format!("invalid volume: {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn daily_short_sale_volume_file_normalized() {
+        let file = "Date|Symbol|ShortVolume|ShortExemptVolume|TotalVolume|Market\n\
+                    20240102|A|300918|1110|679226|B,Q,N\n\
+                    20240102|AA|4633.962|0|10227.4|B,Q,N\n\
+                    2\n";
+
+        let records = read_daily_short_sale_volume_file(file.as_bytes(), "CNMS")
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!(
+            Date::from_calendar_date(2024, Month::January, 2).ok(),
+            records[0].trade_report_date
+        );
+        assert_eq!("B,Q,N", records[0].market_code);
+        assert_eq!("CNMS", records[0].reporting_facility_code);
+        assert_eq!(4634, records[1].short_volume);
+        assert_eq!(10227, records[1].total_volume);
+
+        let invalid = "Date|Symbol|ShortVolume|ShortExemptVolume|TotalVolume|Market\n\
+                       2024-01-02|A|1|0|1|Q\n";
+        assert!(matches!(
+            read_daily_short_sale_volume_file(invalid.as_bytes(), "CNMS")
+                .unwrap()
+                .next(),
+            Some(Err(Error::BulkFile { line: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn threshold_list_file_mapped_onto_api_fields() {
+        let file = "Symbol|Security Name|Market Category|Reg SHO Threshold Flag|Rule 4320|Filler\n\
+                    ABCD|ABCD Inc. Common Stock|u|Y|N|\n";
+
+        let records = read_bulk_file_as(
+            file.as_bytes(),
+            ThresholdListFile {
+                trade_date: Date::from_calendar_date(2024, Month::January, 2).unwrap(),
+            },
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+
+        assert_eq!(
+            vec![ThresholdListRecord {
+                trade_date: Date::from_calendar_date(2024, Month::January, 2).ok(),
+                issue_symbol_identifier: "ABCD".to_string(),
+                issue_name: "ABCD Inc. Common Stock".to_string(),
+                market_class_code: "u".to_string(),
+                reg_sho_threshold_flag: Some("Y".to_string()),
+                rule_4320_flag: Some("N".to_string()),
+            }],
+            records
+        );
+    }
+}
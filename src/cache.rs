@@ -0,0 +1,501 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::files::{long_path, write_atomically};
+
+/// A page of the results stored in a [`CacheBackend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedPage {
+    /// The total number of the records matching the query, as reported by FINRA.
+    pub total: Option<u64>,
+    /// The body of the page as returned by FINRA.
+    pub body: Vec<u8>,
+}
+
+/// Stores the pages of the query results, so that repeating the same query doesn't download them
+/// from FINRA again. Use [`crate::FinraBuilder::cache`] to set it up.
+///
+/// The pages are keyed by the dataset, the response format and the serialized query, including
+/// its offset and limit, so each page of the results has its own key. The cache is best-effort -
+/// a page that cannot be read or stored is fetched from FINRA.
+pub trait CacheBackend: Send + Sync {
+    /// The page stored under the `key`, if it is present and still valid.
+    fn get(&self, key: &str) -> Option<CachedPage>;
+
+    /// Stores the `page` under the `key`, replacing any page stored under it before.
+    fn put(&self, key: &str, page: &CachedPage);
+
+    /// Removes the expired pages and any other pages the cache doesn't have room for. Called by
+    /// [`Storage::gc`]. Does nothing by default.
+    fn gc(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The local storage of a [`crate::Finra`] instance, returned by [`crate::Finra::storage`]. Only
+/// the [`CacheBackend`] set up using [`crate::FinraBuilder::cache`] is stored locally - the
+/// checkpoints of the syncs and the exported files are stored by the caller, wherever it chooses.
+#[derive(Clone)]
+pub struct Storage {
+    pub(crate) cache: Option<Arc<dyn CacheBackend>>,
+}
+
+impl Storage {
+    /// Removes the expired and the excess pages from the cache, see [`CacheBackend::gc`].
+    pub fn gc(&self) -> std::io::Result<()> {
+        match &self.cache {
+            Some(cache) => cache.gc(),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the [`Storage::gc`] every `interval` until dropped. Spawn it on the runtime, e.g.
+    /// using `tokio::spawn`, to collect the garbage in the background of a long-running process.
+    /// The failures are only logged.
+    ///
+    /// The collection uses the blocking standard library I/O, which may stall the executor for a
+    /// while on a large cache.
+    pub fn gc_every(&self, interval: Duration) -> impl Future<Output = ()> + Send + 'static {
+        let storage = self.clone();
+        async move {
+            loop {
+                crate::rt::sleep(interval).await;
+                if let Err(e) = storage.gc() {
+                    tracing::warn!(error = %e, "could not collect the garbage in the cache");
+                }
+            }
+        }
+    }
+}
+
+/// The compression of the bodies of the pages stored in a [`FileCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum CacheCompression {
+    /// The bodies are stored as returned by FINRA.
+    #[default]
+    None,
+    /// gzip with the level from 0 (no compression) to 9 (the best compression). Requires the
+    /// `cache-gzip` feature.
+    #[cfg(feature = "cache-gzip")]
+    Gzip(u32),
+    /// zstd with the level from 1 (the fastest) to 22 (the best compression), 0 is the zstd
+    /// default. Requires the `cache-zstd` feature.
+    #[cfg(feature = "cache-zstd")]
+    Zstd(i32),
+}
+
+impl CacheCompression {
+    /// The extension of the files of the bodies compressed this way, which tells how to
+    /// decompress them regardless of the compression the cache is configured with.
+    fn extension(self) -> &'static str {
+        match self {
+            CacheCompression::None => "body",
+            #[cfg(feature = "cache-gzip")]
+            CacheCompression::Gzip(_) => "gz",
+            #[cfg(feature = "cache-zstd")]
+            CacheCompression::Zstd(_) => "zst",
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CacheCompression::None => Ok(body.to_vec()),
+            #[cfg(feature = "cache-gzip")]
+            CacheCompression::Gzip(level) => {
+                use std::io::Write;
+
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "cache-zstd")]
+            CacheCompression::Zstd(level) => zstd::encode_all(body, level),
+        }
+    }
+
+    fn decompress(extension: &str, data: Vec<u8>) -> Option<Vec<u8>> {
+        match extension {
+            "body" => Some(data),
+            #[cfg(feature = "cache-gzip")]
+            "gz" => {
+                use std::io::Read;
+
+                let mut body = Vec::new();
+                flate2::read::GzDecoder::new(&data[..])
+                    .read_to_end(&mut body)
+                    .ok()?;
+                Some(body)
+            }
+            #[cfg(feature = "cache-zstd")]
+            "zst" => zstd::decode_all(&data[..]).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The age after which a temporary file of a page, or a body no page refers to, is considered
+/// left behind by a writer that crashed, rather than being written right now.
+const STALE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A [`CacheBackend`] storing each page in a file in a directory. The pages older than the `ttl`
+/// are considered expired and are fetched again.
+///
+/// The files are named by a hash of the key and also contain the key itself, so that a hash
+/// collision is a cache miss rather than a wrong page. The bodies of the pages are stored
+/// separately, compressed using the [`CacheCompression`] and named by the SHA-256 of their
+/// content, so that the identical pages of the overlapping queries are stored only once.
+///
+/// The expired pages are overwritten when fetched again, but are removed from the directory only
+/// by the [`CacheBackend::gc`], together with the oldest pages over the
+/// [`FileCache::max_size`], if set. The pages are written to temporary files first, the ones left
+/// behind by the processes that crashed while writing them are removed when the cache is created
+/// and by the [`CacheBackend::gc`].
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    dir: PathBuf,
+    ttl: Duration,
+    compression: CacheCompression,
+    max_size: Option<u64>,
+}
+
+impl FileCache {
+    /// Creates the cache in the `dir`, which is created if it doesn't exist, and removes the
+    /// stale temporary files from it.
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> std::io::Result<Self> {
+        let dir = long_path(&dir.into());
+        fs::create_dir_all(&dir)?;
+        remove_stale_tmp(&dir, STALE_AGE)?;
+        Ok(Self {
+            dir,
+            ttl,
+            compression: CacheCompression::None,
+            max_size: None,
+        })
+    }
+
+    /// Limits the size of the files in the cache to the `bytes`. The limit is enforced by the
+    /// [`CacheBackend::gc`], which removes the oldest pages until the rest fits in it, so the
+    /// cache can grow past it in between. By default, the size is not limited.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Compresses the bodies of the pages stored from now on using the `compression`. The pages
+    /// stored before stay readable. By default, the bodies are not compressed.
+    pub fn compression(mut self, compression: CacheCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        self.dir.join(format!("{:016x}.page", hash))
+    }
+}
+
+impl CacheBackend for FileCache {
+    fn get(&self, key: &str) -> Option<CachedPage> {
+        let path = self.path(key);
+        let age = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age >= self.ttl {
+            return None;
+        }
+
+        let content = fs::read(&path).ok()?;
+        let (stored_key, total, body_file) = parse_page(&content)?;
+        if stored_key != key.as_bytes() {
+            return None;
+        }
+        let (digest, extension) = body_file.split_once('.')?;
+        let data = fs::read(self.dir.join(body_file)).ok()?;
+        let body = CacheCompression::decompress(extension, data)?;
+        // a body overwritten or corrupted on the disk is a cache miss
+        if hex_digest(&body) != digest {
+            return None;
+        }
+        Some(CachedPage { total, body })
+    }
+
+    fn put(&self, key: &str, page: &CachedPage) {
+        let body_file = format!(
+            "{}.{}",
+            hex_digest(&page.body),
+            self.compression.extension()
+        );
+        let mut content = Vec::with_capacity(key.len() + body_file.len() + 22);
+        content.extend_from_slice(key.as_bytes());
+        content.push(b'\n');
+        if let Some(total) = page.total {
+            content.extend_from_slice(total.to_string().as_bytes());
+        }
+        content.push(b'\n');
+        content.extend_from_slice(body_file.as_bytes());
+
+        // the body first, so that the page never refers to a missing one
+        let body_path = self.dir.join(&body_file);
+        let path = self.path(key);
+        let stored = if body_path.exists() {
+            Ok(())
+        } else {
+            self.compression
+                .compress(&page.body)
+                .and_then(|data| write_atomically(&body_path, &data))
+        };
+        if let Err(e) = stored.and_then(|_| write_atomically(&path, &content)) {
+            tracing::warn!(path = %path.display(), error = %e, "could not cache the page");
+        }
+    }
+
+    fn gc(&self) -> std::io::Result<()> {
+        remove_stale_tmp(&self.dir, STALE_AGE)?;
+
+        let now = SystemTime::now();
+        let mut pages = Vec::new();
+        let mut bodies = HashMap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let age = metadata
+                .modified()
+                .map(|modified| now.duration_since(modified).unwrap_or_default())
+                .unwrap_or(Duration::MAX);
+            let path = entry.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("page") => pages.push((age, metadata.len(), path)),
+                Some("tmp") | None => {}
+                Some(_) => {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    bodies.insert(name, (age, metadata.len(), 0usize));
+                }
+            }
+        }
+
+        let body_of = |path: &Path| {
+            let content = fs::read(path).ok()?;
+            parse_page(&content).map(|(_, _, body)| body.to_string())
+        };
+        let mut size: u64 = pages.iter().map(|(_, len, _)| len).sum::<u64>()
+            + bodies.values().map(|(_, len, _)| len).sum::<u64>();
+        let mut referenced = Vec::with_capacity(pages.len());
+        for (age, len, path) in pages {
+            let body = body_of(&path);
+            if let Some((_, _, references)) = body.as_ref().and_then(|b| bodies.get_mut(b)) {
+                *references += 1;
+            }
+            referenced.push((age, len, path, body));
+        }
+        // the bodies no page refers to can still be being written along with their page
+        for (name, (age, _, references)) in &bodies {
+            if *references == 0 && *age >= STALE_AGE {
+                let _ = fs::remove_file(self.dir.join(name));
+            }
+        }
+
+        // the oldest pages first, until the rest is neither expired nor over the maximum size
+        referenced.sort_by_key(|(age, ..)| std::cmp::Reverse(*age));
+        for (age, len, path, body) in referenced {
+            if age < self.ttl && self.max_size.is_none_or(|max| size <= max) {
+                break;
+            }
+            if fs::remove_file(&path).is_err() {
+                continue;
+            }
+            size -= len;
+            let Some(body) = body else {
+                continue;
+            };
+            if let Some((_, body_len, references)) = bodies.get_mut(&body) {
+                *references -= 1;
+                if *references == 0 && fs::remove_file(self.dir.join(&body)).is_ok() {
+                    size -= *body_len;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The key, the total and the name of the file of the body of a page stored in a [`FileCache`].
+fn parse_page(content: &[u8]) -> Option<(&[u8], Option<u64>, &str)> {
+    // the key on the first line, the total on the second and the file of the body after that
+    let mut parts = content.splitn(3, |b| *b == b'\n');
+    let key = parts.next()?;
+    let total = match std::str::from_utf8(parts.next()?).ok()? {
+        "" => None,
+        total => Some(total.parse().ok()?),
+    };
+    let body_file = std::str::from_utf8(parts.next()?).ok()?;
+    Some((key, total, body_file))
+}
+
+/// The hex-encoded SHA-256 of the `body`.
+fn hex_digest(body: &[u8]) -> String {
+    Sha256::digest(body)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Removes the temporary files in the `dir` older than the `age`. The younger ones may still be
+/// being written by the other instances of the cache.
+fn remove_stale_tmp(dir: &Path, age: Duration) -> std::io::Result<()> {
+    let now = SystemTime::now();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "tmp") {
+            continue;
+        }
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() >= age);
+        if stale {
+            let _ = fs::remove_file(&path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pages_stored_until_expired() {
+        let dir = std::env::temp_dir().join(format!("finra-cache-{}", std::process::id()));
+        let cache = FileCache::new(&dir, Duration::from_secs(60)).unwrap();
+        let page = CachedPage {
+            total: Some(3),
+            body: b"id\n1\n2\n3\n".to_vec(),
+        };
+
+        assert_eq!(None, cache.get("page"));
+        cache.put("page", &page);
+        assert_eq!(Some(page.clone()), cache.get("page"));
+        assert_eq!(None, cache.get("other page"));
+
+        let expired = FileCache::new(&dir, Duration::ZERO).unwrap();
+        assert_eq!(None, expired.get("page"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stale_tmp_files_removed() {
+        let dir = std::env::temp_dir().join(format!("finra-cache-tmp-{}", std::process::id()));
+        let cache = FileCache::new(&dir, Duration::from_secs(60)).unwrap();
+        let tmp = dir.join("0000000000000000.page.0000abcd.tmp");
+        fs::write(&tmp, b"partial").unwrap();
+        cache.put(
+            "page",
+            &CachedPage {
+                total: None,
+                body: b"id\n".to_vec(),
+            },
+        );
+
+        remove_stale_tmp(&dir, Duration::from_secs(60)).unwrap();
+        assert!(tmp.exists());
+        remove_stale_tmp(&dir, Duration::ZERO).unwrap();
+        assert!(!tmp.exists());
+        assert!(cache.get("page").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn identical_bodies_stored_once() {
+        let compressions = [
+            CacheCompression::None,
+            #[cfg(feature = "cache-gzip")]
+            CacheCompression::Gzip(6),
+            #[cfg(feature = "cache-zstd")]
+            CacheCompression::Zstd(3),
+        ];
+        for compression in compressions {
+            let dir = std::env::temp_dir().join(format!(
+                "finra-cache-{}-{}",
+                compression.extension(),
+                std::process::id()
+            ));
+            let cache = FileCache::new(&dir, Duration::from_secs(60))
+                .unwrap()
+                .compression(compression);
+            let page = CachedPage {
+                total: Some(3),
+                body: b"id\n1\n2\n3\n".repeat(100),
+            };
+
+            cache.put("page", &page);
+            cache.put("same page of another query", &page);
+            assert_eq!(Some(page.clone()), cache.get("page"));
+            assert_eq!(Some(page), cache.get("same page of another query"));
+            let bodies = fs::read_dir(&dir)
+                .unwrap()
+                .filter(|e| {
+                    let path = e.as_ref().unwrap().path();
+                    path.extension().unwrap() == compression.extension()
+                })
+                .count();
+            assert_eq!(1, bodies);
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn expired_and_oldest_pages_collected() {
+        let dir = std::env::temp_dir().join(format!("finra-cache-gc-{}", std::process::id()));
+        let page = |n: u8| CachedPage {
+            total: None,
+            body: vec![n; 100],
+        };
+        let cache = FileCache::new(&dir, Duration::from_secs(60)).unwrap();
+        cache.put("old", &page(1));
+        cache.put("same as old", &page(1));
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("new", &page(2));
+        let files = || fs::read_dir(&dir).unwrap().count();
+        assert_eq!(5, files());
+
+        cache.gc().unwrap();
+        assert_eq!(5, files());
+
+        // only the newest page and its body fit
+        let limited = FileCache::new(&dir, Duration::from_secs(60))
+            .unwrap()
+            .max_size(300);
+        limited.gc().unwrap();
+        assert_eq!(2, files());
+        assert_eq!(Some(page(2)), cache.get("new"));
+
+        let expired = FileCache::new(&dir, Duration::ZERO).unwrap();
+        Storage {
+            cache: Some(Arc::new(expired)),
+        }
+        .gc()
+        .unwrap();
+        assert_eq!(0, files());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use serde::Deserialize;
+
+/// A dataset available in the FINRA API, as listed by [`crate::Finra::catalog`].
+///
+/// Only some of the datasets have typed support in this crate. The rest can be queried using
+/// [`crate::Finra::dataset`] with the `group` and `name`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct DatasetInfo {
+    /// The data group of the dataset, e.g. `otcMarket`.
+    pub group: String,
+
+    /// The name of the dataset in the group, e.g. `weeklySummary`.
+    pub name: String,
+
+    pub description: Option<String>,
+}
+
+/// The description of a field of a dataset, as returned by [`crate::Finra::field_metadata`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct FieldMetadata {
+    /// The FINRA name of the field, as used in the queries.
+    pub name: String,
+
+    /// The FINRA name of the type of the field, e.g. `String`, `Number` or `Date`.
+    #[serde(rename = "type")]
+    pub field_type: String,
+
+    pub description: Option<String>,
+}
+
+/// The metadata of a dataset as returned by FINRA. Only the fields are of interest.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct DatasetMetadata {
+    pub(crate) fields: Vec<FieldMetadata>,
+}
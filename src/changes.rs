@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use futures::{stream, StreamExt, TryStream, TryStreamExt};
+use time::Date;
+
+use crate::{group_by_date, ConsolidatedShortInterest};
+
+/// The change of the short interest of a symbol from its previous settlement date, as computed by
+/// [`short_interest_changes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortInterestChange {
+    pub symbol: String,
+    pub market_class: String,
+    pub settlement_date: Date,
+    /// The short position on the settlement date.
+    pub short_interest: u64,
+    /// The previous settlement date of the symbol in the stream, if any.
+    pub previous_settlement_date: Option<Date>,
+    /// The short position on the previous settlement date.
+    pub previous_short_interest: Option<u64>,
+    /// The absolute change of the short position. `None` without the previous short position or
+    /// if the change doesn't fit into `i64`.
+    pub change: Option<i64>,
+    /// The change of the short position in percent of the previous one. `None` if the previous
+    /// short position was zero.
+    pub change_percent: Option<f64>,
+    /// Whether the stock split between the settlement dates, as flagged by the `stockSplitFlag` of
+    /// the record or reported by the [`CorporateActions`].
+    pub split: bool,
+    /// The split ratio the previous short position was multiplied by before computing the change
+    /// with [`SplitHandling::Adjust`].
+    pub split_ratio: Option<f64>,
+    /// The rank of the percent change among the symbols of the same market class on the
+    /// settlement date, starting from 1 for the largest increase. `None` without the percent
+    /// change.
+    pub rank: Option<usize>,
+}
+
+/// A source of the stock splits used to adjust the changes of the short interest.
+pub trait CorporateActions {
+    /// The number of the shares after the splits of the `symbol` effective after the `from` date
+    /// and on or before the `to` date per a share before them, or `None` if there were none.
+    fn split_ratio(&self, symbol: &str, from: Date, to: Date) -> Option<f64>;
+}
+
+/// The list of the known stock splits.
+#[derive(Debug, Clone, Default)]
+pub struct StockSplits(Vec<(String, Date, f64)>);
+
+impl StockSplits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the split of the `symbol` effective on the `date` that turned each share into `ratio`
+    /// shares, e.g. 4 for a 4-for-1 split or 0.1 for a 1-for-10 reverse split.
+    pub fn with_split(mut self, symbol: impl Into<String>, date: Date, ratio: f64) -> Self {
+        self.0.push((symbol.into(), date, ratio));
+        self
+    }
+}
+
+impl CorporateActions for StockSplits {
+    fn split_ratio(&self, symbol: &str, from: Date, to: Date) -> Option<f64> {
+        self.0
+            .iter()
+            .filter(|(s, date, _)| s == symbol && from < *date && *date <= to)
+            .map(|(_, _, ratio)| *ratio)
+            .reduce(|a, b| a * b)
+    }
+}
+
+/// How the changes of the short interest across the stock splits are computed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SplitHandling {
+    /// The changes are computed from the short positions as reported and only marked as
+    /// [`ShortInterestChange::split`].
+    #[default]
+    Annotate,
+    /// The changes across the splits are left out, i.e. they have no change and no rank.
+    Exclude,
+    /// The previous short position is multiplied by the split ratio from the
+    /// [`CorporateActions`] before computing the change. The changes across the splits with an
+    /// unknown ratio are left out like with [`SplitHandling::Exclude`].
+    Adjust,
+}
+
+/// Computes the period-over-period changes of the short interest of each symbol from the stream
+/// of the consolidated short interest, yielding the changes of each settlement date once all its
+/// records are read.
+///
+/// The records are expected to be ordered by the settlement date, e.g. by sorting the query by
+/// [`crate::ConsolidatedShortInterestField::SettlementDate`]. The changes are computed from the
+/// previous record of the symbol in the stream, so the first settlement date of each symbol has
+/// no change. The records without the settlement date are skipped.
+///
+/// The changes across the stock splits are only marked, see [`short_interest_changes_with`] to
+/// adjust them.
+pub fn short_interest_changes<S>(
+    stream: S,
+) -> impl TryStream<Ok = ShortInterestChange, Error = S::Error>
+where
+    S: TryStream<Ok = ConsolidatedShortInterest>,
+{
+    short_interest_changes_with(stream, SplitHandling::Annotate, StockSplits::new())
+}
+
+/// Like [`short_interest_changes`], but handles the changes across the stock splits, flagged by
+/// FINRA or reported by the `actions`, according to the `splits`.
+pub fn short_interest_changes_with<S, A>(
+    stream: S,
+    splits: SplitHandling,
+    actions: A,
+) -> impl TryStream<Ok = ShortInterestChange, Error = S::Error>
+where
+    S: TryStream<Ok = ConsolidatedShortInterest>,
+    A: CorporateActions,
+{
+    let mut previous: HashMap<String, (Date, u64)> = HashMap::new();
+    group_by_date(stream)
+        .map_ok(move |(date, records)| {
+            let changes = period_changes(&mut previous, splits, &actions, date, records);
+            stream::iter(changes).map(Ok)
+        })
+        .try_flatten()
+}
+
+/// Computes the changes of a single settlement date and remembers its short positions as the
+/// previous ones of the symbols.
+fn period_changes(
+    previous: &mut HashMap<String, (Date, u64)>,
+    splits: SplitHandling,
+    actions: &impl CorporateActions,
+    date: Date,
+    records: Vec<ConsolidatedShortInterest>,
+) -> Vec<ShortInterestChange> {
+    let mut changes: Vec<ShortInterestChange> = records
+        .into_iter()
+        .map(|r| {
+            let short_interest = r.current_short_position_quantity;
+            let before = previous
+                .insert(r.symbol_code.clone(), (date, short_interest))
+                .filter(|(previous_date, _)| *previous_date < date);
+            let known_ratio =
+                before.and_then(|(d, _)| actions.split_ratio(&r.symbol_code, d, date));
+            let flagged = r
+                .stock_split_flag
+                .as_deref()
+                .is_some_and(|flag| !flag.trim().is_empty());
+            let split = before.is_some() && (flagged || known_ratio.is_some());
+
+            // the previous short position in the shares of the settlement date, if comparable
+            let (compared, split_ratio) = match (splits, split) {
+                (SplitHandling::Exclude, true) => (None, None),
+                (SplitHandling::Adjust, true) => match known_ratio {
+                    Some(ratio) => (
+                        before.map(|(_, q)| (q as f64 * ratio).round() as u64),
+                        known_ratio,
+                    ),
+                    None => (None, None),
+                },
+                _ => (before.map(|(_, q)| q), None),
+            };
+            let change = compared.and_then(|q| {
+                let change = i128::from(short_interest) - i128::from(q);
+                i64::try_from(change).ok()
+            });
+            let change_percent = compared
+                .zip(change)
+                .filter(|(q, _)| *q > 0)
+                .map(|(q, change)| change as f64 / q as f64 * 100.0);
+
+            ShortInterestChange {
+                symbol: r.symbol_code,
+                market_class: r.market_class_code,
+                settlement_date: date,
+                short_interest,
+                previous_settlement_date: before.map(|(d, _)| d),
+                previous_short_interest: before.map(|(_, q)| q),
+                change,
+                change_percent,
+                split,
+                split_ratio,
+                rank: None,
+            }
+        })
+        .collect();
+
+    let mut ranked: Vec<(usize, f64)> = changes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| c.change_percent.map(|p| (i, p)))
+        .collect();
+    ranked.sort_by(|(a, a_percent), (b, b_percent)| {
+        let (a, b) = (&changes[*a], &changes[*b]);
+        a.market_class
+            .cmp(&b.market_class)
+            .then(b_percent.total_cmp(a_percent))
+    });
+    let mut rank = 0;
+    for (n, (i, _)) in ranked.iter().enumerate() {
+        let same_class = n > 0 && changes[ranked[n - 1].0].market_class == changes[*i].market_class;
+        rank = if same_class { rank + 1 } else { 1 };
+        changes[*i].rank = Some(rank);
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    fn record(symbol: &str, class: &str, date: Date, quantity: u64) -> ConsolidatedShortInterest {
+        ConsolidatedShortInterest {
+            symbol_code: symbol.to_string(),
+            market_class_code: class.to_string(),
+            settlement_date: Some(date),
+            current_short_position_quantity: quantity,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn changes_ranked_within_market_class() {
+        let (first, second) = (date!(2024 - 01 - 15), date!(2024 - 01 - 31));
+        let records = [
+            record("A", "NYSE", first, 100),
+            record("B", "NYSE", first, 100),
+            record("C", "NNM", first, 0),
+            record("A", "NYSE", second, 150),
+            record("B", "NYSE", second, 50),
+            record("C", "NNM", second, 10),
+        ];
+
+        let changes: Vec<_> = short_interest_changes(stream::iter(records.map(Ok::<_, ()>)))
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(6, changes.len());
+        assert!(changes[..3].iter().all(|c| c.change.is_none()));
+        let changes: Vec<_> = changes[3..]
+            .iter()
+            .map(|c| (c.symbol.as_str(), c.change, c.change_percent, c.rank))
+            .collect();
+        assert_eq!(
+            vec![
+                ("A", Some(50), Some(50.0), Some(1)),
+                ("B", Some(-50), Some(-50.0), Some(2)),
+                ("C", Some(10), None, None),
+            ],
+            changes
+        );
+    }
+
+    #[tokio::test]
+    async fn changes_adjusted_for_splits() {
+        let (first, second) = (date!(2024 - 01 - 15), date!(2024 - 01 - 31));
+        let flagged = |symbol, quantity| ConsolidatedShortInterest {
+            stock_split_flag: Some("S".to_string()),
+            ..record(symbol, "NYSE", second, quantity)
+        };
+        let records = [
+            record("A", "NYSE", first, 100),
+            record("B", "NYSE", first, 100),
+            record("C", "NYSE", first, 100),
+            flagged("A", 450),
+            flagged("B", 450),
+            record("C", "NYSE", second, 120),
+        ];
+        let splits = StockSplits::new().with_split("A", date!(2024 - 01 - 20), 4.0);
+        let changes = |handling| {
+            short_interest_changes_with(
+                stream::iter(records.clone().map(Ok::<_, ()>)),
+                handling,
+                splits.clone(),
+            )
+            .map_ok(|c| (c.symbol, c.split, c.change, c.rank))
+            .try_collect::<Vec<_>>()
+        };
+
+        let annotated = changes(SplitHandling::Annotate).await.unwrap();
+        let adjusted = changes(SplitHandling::Adjust).await.unwrap();
+
+        assert_eq!(
+            vec![
+                ("A".to_string(), true, Some(350), Some(1)),
+                ("B".to_string(), true, Some(350), Some(2)),
+                ("C".to_string(), false, Some(20), Some(3)),
+            ],
+            annotated[3..]
+        );
+        assert_eq!(
+            vec![
+                ("A".to_string(), true, Some(50), Some(2)),
+                ("B".to_string(), true, None, None),
+                ("C".to_string(), false, Some(20), Some(1)),
+            ],
+            adjusted[3..]
+        );
+    }
+
+    #[tokio::test]
+    async fn overflowing_change_left_out() {
+        let (first, second) = (date!(2024 - 01 - 15), date!(2024 - 01 - 31));
+        let records = [
+            record("A", "NYSE", first, 0),
+            record("A", "NYSE", second, u64::MAX),
+        ];
+
+        let changes: Vec<_> = short_interest_changes(stream::iter(records.map(Ok::<_, ()>)))
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(Some(0), changes[1].previous_short_interest);
+        assert_eq!(None, changes[1].change);
+        assert_eq!(None, changes[1].rank);
+    }
+}
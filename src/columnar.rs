@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use futures::{TryStream, TryStreamExt};
+use time::Date;
+
+use crate::{ConsolidatedShortInterest, YearMonth};
+
+/// The consolidated short interest records stored column by column.
+///
+/// This needs much less memory than a `Vec<ConsolidatedShortInterest>` for large results - the
+/// strings repeating from record to record, like the symbols or the market classes, are stored
+/// only once per column, and there's no padding between the fields. Scanning a single field of all
+/// the records is also faster, because the values of the field are next to each other.
+#[derive(Debug, Clone, Default)]
+pub struct ShortInterestColumns {
+    stock_split_flag: StringColumn,
+    previous_short_position_quantity: Vec<u64>,
+    average_daily_volume_quantity: Vec<u64>,
+    issue_name: StringColumn,
+    current_short_position_quantity: Vec<u64>,
+    change_previous_number: Vec<i64>,
+    accounting_year_month_number: Vec<Option<YearMonth>>,
+    settlement_date: Vec<Option<Date>>,
+    market_class_code: StringColumn,
+    symbol_code: StringColumn,
+    days_to_cover_quantity: Vec<f64>,
+    issuer_services_group_exchange_code: StringColumn,
+    revision_flag: StringColumn,
+    change_percent: Vec<f64>,
+}
+
+/// A column of strings. Each distinct string is stored only once and the rows refer to it.
+#[derive(Debug, Clone, Default)]
+pub struct StringColumn {
+    /// The index of the value of each row, or `MISSING`.
+    codes: Vec<u32>,
+    values: Vec<String>,
+    index: HashMap<String, u32>,
+}
+
+const MISSING: u32 = u32::MAX;
+
+impl StringColumn {
+    fn push(&mut self, value: Option<String>) {
+        let code = match value {
+            None => MISSING,
+            Some(value) => match self.index.get(&value) {
+                Some(code) => *code,
+                None => {
+                    let code = self.values.len() as u32;
+                    self.values.push(value.clone());
+                    self.index.insert(value, code);
+                    code
+                }
+            },
+        };
+        self.codes.push(code);
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// The value in the `row`. `None` if the row doesn't exist or the value is missing, which can
+    /// only happen in the columns of optional fields.
+    pub fn get(&self, row: usize) -> Option<&str> {
+        self.codes
+            .get(row)
+            .filter(|code| **code != MISSING)
+            .map(|code| self.values[*code as usize].as_str())
+    }
+
+    /// The values of all the rows.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.codes
+            .iter()
+            .map(|code| (*code != MISSING).then(|| self.values[*code as usize].as_str()))
+    }
+
+    /// The distinct values in the column, in the order of their first appearance.
+    pub fn distinct(&self) -> &[String] {
+        &self.values
+    }
+}
+
+impl ShortInterestColumns {
+    /// Collects the records of the `stream` into columns.
+    pub async fn from_stream<S>(stream: S) -> Result<Self, S::Error>
+    where
+        S: TryStream<Ok = ConsolidatedShortInterest>,
+    {
+        stream
+            .try_fold(Self::default(), |mut columns, record| async move {
+                columns.push(record);
+                Ok(columns)
+            })
+            .await
+    }
+
+    /// Appends the `record` as the last row.
+    pub fn push(&mut self, record: ConsolidatedShortInterest) {
+        self.stock_split_flag.push(record.stock_split_flag);
+        self.previous_short_position_quantity
+            .push(record.previous_short_position_quantity);
+        self.average_daily_volume_quantity
+            .push(record.average_daily_volume_quantity);
+        self.issue_name.push(Some(record.issue_name));
+        self.current_short_position_quantity
+            .push(record.current_short_position_quantity);
+        self.change_previous_number
+            .push(record.change_previous_number);
+        self.accounting_year_month_number
+            .push(record.accounting_year_month_number);
+        self.settlement_date.push(record.settlement_date);
+        self.market_class_code.push(Some(record.market_class_code));
+        self.symbol_code.push(Some(record.symbol_code));
+        self.days_to_cover_quantity
+            .push(record.days_to_cover_quantity);
+        self.issuer_services_group_exchange_code
+            .push(Some(record.issuer_services_group_exchange_code));
+        self.revision_flag.push(record.revision_flag);
+        self.change_percent.push(record.change_percent);
+    }
+
+    /// The number of the rows.
+    pub fn len(&self) -> usize {
+        self.symbol_code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbol_code.is_empty()
+    }
+
+    /// Reconstructs the record in the `row`, if it exists.
+    pub fn row(&self, row: usize) -> Option<ConsolidatedShortInterest> {
+        if row >= self.len() {
+            return None;
+        }
+
+        let string = |column: &StringColumn| column.get(row).map(str::to_string);
+        Some(ConsolidatedShortInterest {
+            stock_split_flag: string(&self.stock_split_flag),
+            previous_short_position_quantity: self.previous_short_position_quantity[row],
+            average_daily_volume_quantity: self.average_daily_volume_quantity[row],
+            issue_name: string(&self.issue_name).unwrap_or_default(),
+            current_short_position_quantity: self.current_short_position_quantity[row],
+            change_previous_number: self.change_previous_number[row],
+            accounting_year_month_number: self.accounting_year_month_number[row],
+            settlement_date: self.settlement_date[row],
+            market_class_code: string(&self.market_class_code).unwrap_or_default(),
+            symbol_code: string(&self.symbol_code).unwrap_or_default(),
+            days_to_cover_quantity: self.days_to_cover_quantity[row],
+            issuer_services_group_exchange_code: string(&self.issuer_services_group_exchange_code)
+                .unwrap_or_default(),
+            revision_flag: string(&self.revision_flag),
+            change_percent: self.change_percent[row],
+        })
+    }
+
+    /// Reconstructs all the records, in the order of the rows.
+    pub fn rows(&self) -> impl Iterator<Item = ConsolidatedShortInterest> + '_ {
+        (0..self.len()).filter_map(|row| self.row(row))
+    }
+
+    pub fn stock_split_flag(&self) -> &StringColumn {
+        &self.stock_split_flag
+    }
+
+    pub fn previous_short_position_quantity(&self) -> &[u64] {
+        &self.previous_short_position_quantity
+    }
+
+    pub fn average_daily_volume_quantity(&self) -> &[u64] {
+        &self.average_daily_volume_quantity
+    }
+
+    pub fn issue_name(&self) -> &StringColumn {
+        &self.issue_name
+    }
+
+    pub fn current_short_position_quantity(&self) -> &[u64] {
+        &self.current_short_position_quantity
+    }
+
+    pub fn change_previous_number(&self) -> &[i64] {
+        &self.change_previous_number
+    }
+
+    pub fn accounting_year_month_number(&self) -> &[Option<YearMonth>] {
+        &self.accounting_year_month_number
+    }
+
+    pub fn settlement_date(&self) -> &[Option<Date>] {
+        &self.settlement_date
+    }
+
+    pub fn market_class_code(&self) -> &StringColumn {
+        &self.market_class_code
+    }
+
+    pub fn symbol_code(&self) -> &StringColumn {
+        &self.symbol_code
+    }
+
+    pub fn days_to_cover_quantity(&self) -> &[f64] {
+        &self.days_to_cover_quantity
+    }
+
+    pub fn issuer_services_group_exchange_code(&self) -> &StringColumn {
+        &self.issuer_services_group_exchange_code
+    }
+
+    pub fn revision_flag(&self) -> &StringColumn {
+        &self.revision_flag
+    }
+
+    pub fn change_percent(&self) -> &[f64] {
+        &self.change_percent
+    }
+}
+
+impl Extend<ConsolidatedShortInterest> for ShortInterestColumns {
+    fn extend<I: IntoIterator<Item = ConsolidatedShortInterest>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|r| self.push(r));
+    }
+}
+
+impl FromIterator<ConsolidatedShortInterest> for ShortInterestColumns {
+    fn from_iter<I: IntoIterator<Item = ConsolidatedShortInterest>>(iter: I) -> Self {
+        let mut columns = Self::default();
+        columns.extend(iter);
+        columns
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn rows_survive_the_round_trip() {
+        let records = vec![
+            ConsolidatedShortInterest {
+                symbol_code: "AAPL".to_string(),
+                settlement_date: Some(date!(2024 - 01 - 31)),
+                current_short_position_quantity: 10,
+                revision_flag: Some("R".to_string()),
+                ..Default::default()
+            },
+            ConsolidatedShortInterest {
+                symbol_code: "AAPL".to_string(),
+                current_short_position_quantity: 20,
+                ..Default::default()
+            },
+        ];
+
+        let columns: ShortInterestColumns = records.clone().into_iter().collect();
+
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns.symbol_code().distinct(), ["AAPL"]);
+        assert_eq!(columns.current_short_position_quantity(), [10, 20]);
+        assert_eq!(
+            columns.revision_flag().iter().collect::<Vec<_>>(),
+            [Some("R"), None]
+        );
+        assert_eq!(columns.rows().collect::<Vec<_>>(), records);
+    }
+}
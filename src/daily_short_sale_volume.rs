@@ -0,0 +1,187 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration};
+
+use crate::{
+    dates::finra_date,
+    numbers,
+    query::{
+        dataset_fields, CompareFilter, DateRangeFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    PaginationStrategy,
+};
+
+/// Represents the Reg SHO daily short sale volume of a single symbol reported by a single
+/// reporting facility on a single day.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct DailyShortSaleVolume {
+    #[serde(rename = "tradeReportDate", with = "finra_date")]
+    pub trade_report_date: Option<Date>,
+
+    #[serde(rename = "securitiesInformationProcessorSymbolIdentifier")]
+    pub symbol: String,
+
+    #[serde(rename = "shortParQuantity", deserialize_with = "numbers::deserialize")]
+    pub short_volume: u64,
+
+    #[serde(
+        rename = "shortExemptParQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub short_exempt_volume: u64,
+
+    #[serde(rename = "totalParQuantity", deserialize_with = "numbers::deserialize")]
+    pub total_volume: u64,
+
+    #[serde(rename = "marketCode")]
+    pub market_code: String,
+
+    #[serde(rename = "reportingFacilityCode")]
+    pub reporting_facility_code: String,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the daily short sale volume query
+    /// results.
+    DailyShortSaleVolumeField {
+        TradeReportDate => "tradeReportDate",
+        Symbol => "securitiesInformationProcessorSymbolIdentifier",
+        ShortVolume => "shortParQuantity",
+        ShortExemptVolume => "shortExemptParQuantity",
+        TotalVolume => "totalParQuantity",
+        MarketCode => "marketCode",
+        ReportingFacilityCode => "reportingFacilityCode",
+    }
+}
+
+/// The query of the Reg SHO daily short sale volume.
+#[derive(Debug, Clone)]
+pub struct DailyShortSaleVolumeQuery {
+    /// If `None`, all fields are included.
+    pub fields: Option<Vec<DailyShortSaleVolumeField>>,
+    /// The range of the trade report dates. If `None`, the full available history is included.
+    pub date_range: Option<Range<Date>>,
+    /// If `None` the data for all symbols is included.
+    pub symbol: Option<String>,
+    /// Any additional filters the records need to match.
+    pub compare_filters: Vec<CompareFilter>,
+    /// The order of the results. If empty, the order is decided by FINRA.
+    pub sort: Vec<SortField>,
+    /// How the results are fetched from FINRA.
+    pub pagination: PaginationStrategy,
+
+    // These are internally used for paging...
+    limit: u64,
+    offset: u64,
+}
+
+impl DailyShortSaleVolumeQuery {
+    pub fn new(
+        fields: Option<Vec<DailyShortSaleVolumeField>>,
+        date_range: Option<Range<Date>>,
+        symbol: Option<String>,
+    ) -> Self {
+        Self {
+            fields,
+            date_range,
+            symbol,
+            compare_filters: Vec::new(),
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl Query for DailyShortSaleVolumeQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(self, by: u64) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, date_range: Range<Date>) {
+        if self.date_range.is_none() {
+            self.date_range = Some(date_range);
+        }
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        DailyShortSaleVolumeField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        DailyShortSaleVolumeField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        self.symbol.iter_mut().collect()
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref()
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        self.date_range = Some(date_range);
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+
+    fn default_partition(&self) -> Duration {
+        Duration::weeks(1)
+    }
+}
+
+impl Serialize for DailyShortSaleVolumeQuery {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|r| {
+                    DateRangeFilter::new(DailyShortSaleVolumeField::TradeReportDate.as_str(), r)
+                })
+                .collect(),
+            compare_filters: self
+                .symbol
+                .iter()
+                .map(|s| CompareFilter::equal(DailyShortSaleVolumeField::Symbol, s.as_str()))
+                .chain(self.compare_filters.iter().cloned())
+                .collect(),
+            domain_filters: Vec::new(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
@@ -0,0 +1,147 @@
+use arrow_array::{
+    cast::AsArray,
+    types::{Date32Type, Float64Type, Int64Type, UInt64Type},
+    RecordBatch,
+};
+use arrow_schema::DataType as ArrowType;
+use futures::{future, TryStream, TryStreamExt};
+use polars::prelude::{DataFrame, DataType, NamedFrom, Series};
+
+use crate::{record_batches, ArrowRecord, Error, Result};
+
+fn polars_error(e: polars::error::PolarsError) -> Error {
+    Error::Export(e.to_string())
+}
+
+/// Converts the Arrow record batch of the records into a `polars` data frame with the same
+/// columns.
+pub fn to_data_frame(batch: &RecordBatch) -> Result<DataFrame> {
+    let columns = batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, array)| {
+            let name = field.name().as_str().into();
+            let series = match field.data_type() {
+                ArrowType::Utf8 => {
+                    Series::new(name, array.as_string::<i32>().iter().collect::<Vec<_>>())
+                }
+                ArrowType::UInt64 => Series::new(
+                    name,
+                    array
+                        .as_primitive::<UInt64Type>()
+                        .iter()
+                        .collect::<Vec<_>>(),
+                ),
+                ArrowType::Int64 => Series::new(
+                    name,
+                    array.as_primitive::<Int64Type>().iter().collect::<Vec<_>>(),
+                ),
+                ArrowType::Float64 => Series::new(
+                    name,
+                    array
+                        .as_primitive::<Float64Type>()
+                        .iter()
+                        .collect::<Vec<_>>(),
+                ),
+                ArrowType::Date32 => Series::new(
+                    name,
+                    array
+                        .as_primitive::<Date32Type>()
+                        .iter()
+                        .collect::<Vec<_>>(),
+                )
+                .cast(&DataType::Date)
+                .map_err(polars_error)?,
+                other => {
+                    return Err(Error::Export(format!(
+                        "the column {} of type {} is not supported by polars",
+                        field.name(),
+                        other
+                    )))
+                }
+            };
+            Ok(series.into())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    DataFrame::new(columns).map_err(polars_error)
+}
+
+/// Converts the records of the stream into the `polars` data frames of `chunk_size` records, like
+/// [`record_batches`].
+///
+/// # Panics
+///
+/// If `chunk_size` is zero.
+pub fn data_frames<S, T>(
+    stream: S,
+    chunk_size: usize,
+) -> impl TryStream<Ok = DataFrame, Error = Error>
+where
+    S: TryStream<Ok = T, Error = Error>,
+    T: ArrowRecord,
+{
+    record_batches(stream, chunk_size).and_then(|batch| future::ready(to_data_frame(&batch)))
+}
+
+/// Collects all the records of the stream into a single `polars` data frame, converting them in
+/// chunks of `chunk_size` records.
+///
+/// # Panics
+///
+/// If `chunk_size` is zero.
+pub async fn collect_data_frame<S, T>(stream: S, chunk_size: usize) -> Result<DataFrame>
+where
+    S: TryStream<Ok = T, Error = Error>,
+    T: ArrowRecord,
+{
+    let mut frames = Box::pin(data_frames(stream, chunk_size).into_stream());
+    let mut all = match frames.try_next().await? {
+        Some(frame) => frame,
+        None => return to_data_frame(&RecordBatch::new_empty(T::schema())),
+    };
+    while let Some(frame) = frames.try_next().await? {
+        all.vstack_mut(&frame).map_err(polars_error)?;
+    }
+    Ok(all)
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+    use time::macros::date;
+
+    use crate::ConsolidatedShortInterest;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn records_collected_in_chunks() {
+        let records = ["AAPL", "MSFT", "TSLA"].into_iter().map(|symbol| {
+            Ok(ConsolidatedShortInterest {
+                symbol_code: symbol.to_string(),
+                current_short_position_quantity: 42,
+                settlement_date: Some(date!(2024 - 01 - 15)),
+                ..Default::default()
+            })
+        });
+
+        let frame = collect_data_frame(stream::iter(records), 2).await.unwrap();
+
+        assert_eq!((3, 14), frame.shape());
+        let symbols = frame.column("symbolCode").unwrap().str().unwrap();
+        assert_eq!(Some("TSLA"), symbols.get(2));
+        assert_eq!(
+            &DataType::Date,
+            frame.column("settlementDate").unwrap().dtype()
+        );
+        let quantities = frame
+            .column("currentShortPositionQuantity")
+            .unwrap()
+            .u64()
+            .unwrap();
+        assert_eq!(Some(42), quantities.get(0));
+    }
+}
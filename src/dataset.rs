@@ -0,0 +1,260 @@
+use std::ops::Range;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::Date;
+
+use futures::{stream, StreamExt, TryStream, TryStreamExt};
+
+use crate::{
+    endpoints,
+    query::{
+        CompareFilter, DateRangeFilter, DomainFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    Error, Finra, PaginationStrategy, Result,
+};
+
+/// The query of an arbitrary FINRA dataset. Unlike the queries of the datasets known to this
+/// crate, the fields are identified just by their FINRA names.
+#[derive(Debug, Clone)]
+pub struct DatasetQuery {
+    fields: Option<Vec<String>>,
+    date_range: Option<(String, Range<Date>)>,
+    compare_filters: Vec<CompareFilter>,
+    domain_filters: Vec<DomainFilter>,
+    sort: Vec<SortField>,
+    pagination: PaginationStrategy,
+    limit: u64,
+    offset: u64,
+}
+
+impl DatasetQuery {
+    /// Creates a query of all the data of all the fields.
+    pub fn new() -> Self {
+        Self {
+            fields: None,
+            date_range: None,
+            compare_filters: vec![],
+            domain_filters: vec![],
+            sort: vec![],
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+
+    /// Limits the fields included in the results.
+    pub fn fields<F: Into<String>>(mut self, fields: impl IntoIterator<Item = F>) -> Self {
+        self.fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Limits the results to those with the date `field` within the `date_range`.
+    pub fn date_range(mut self, field: impl Into<String>, date_range: Range<Date>) -> Self {
+        self.date_range = Some((field.into(), date_range));
+        self
+    }
+
+    /// Limits the results to those with the `field` equal to the `value`. Multiple filters can
+    /// be combined.
+    pub fn equal(self, field: impl AsRef<str>, value: impl Into<String>) -> Self {
+        self.compare(CompareFilter::equal(field, value.into()))
+    }
+
+    /// Limits the results to those matching the `filter`. Multiple filters can be combined.
+    pub fn compare(mut self, filter: CompareFilter) -> Self {
+        self.compare_filters.push(filter);
+        self
+    }
+
+    /// Limits the results to those with the field of the `filter` having one of its values.
+    /// Multiple filters can be combined.
+    pub fn domain(mut self, filter: DomainFilter) -> Self {
+        self.domain_filters.push(filter);
+        self
+    }
+
+    /// Orders the results by the field. When called multiple times, the results are ordered by
+    /// the fields in the order of the calls.
+    pub fn sort(mut self, sort: SortField) -> Self {
+        self.sort.push(sort);
+        self
+    }
+
+    /// Limits the results to the `partition` of the dataset, as listed by [`Finra::partitions`].
+    pub fn partition(self, partition: &Partition) -> Self {
+        partition.values.iter().fold(self, |query, (field, value)| {
+            query.equal(field, value.as_str())
+        })
+    }
+
+    /// Sets how the results are fetched from FINRA. The date range of the query is partitioned
+    /// by the date field of [`DatasetQuery::date_range`].
+    pub fn pagination(mut self, pagination: PaginationStrategy) -> Self {
+        self.pagination = pagination;
+        self
+    }
+}
+
+impl Default for DatasetQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Query for DatasetQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(self, by: u64) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, _date_range: Range<Date>) {
+        // we don't know which field is the date field of the dataset
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        // we don't know which field contains the symbols
+        vec![]
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref().map(|(_, range)| range)
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        if let Some((_, range)) = &mut self.date_range {
+            *range = date_range;
+        }
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+}
+
+impl Serialize for DatasetQuery {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|(field, range)| DateRangeFilter::new(field.as_str(), range))
+                .collect(),
+            compare_filters: self.compare_filters.clone(),
+            domain_filters: self.domain_filters.clone(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Finra {
+    /// Queries an arbitrary dataset `name` in the `group` and deserializes the records into `T`.
+    /// Use this for the datasets that are not (yet) directly supported by this crate.
+    ///
+    /// Note that the default date range and the symbol validation configured on the builder are
+    /// not applied to these queries, because it is not known which fields they would apply to.
+    pub async fn dataset<T: DeserializeOwned>(
+        &self,
+        group: &str,
+        name: &str,
+        query: DatasetQuery,
+    ) -> Result<impl TryStream<Ok = T, Error = Error>> {
+        self.fetch(group, name, query).await
+    }
+
+    /// Lists the partitions of the dataset `name` in the `group`. FINRA splits the large datasets
+    /// into partitions, e.g. by the date, that can be fetched separately using
+    /// [`DatasetQuery::partition`]. Empty if the dataset is not partitioned.
+    pub async fn partitions(&self, group: &str, name: &str) -> Result<Vec<Partition>> {
+        let url = self.config.api_url(&format!(
+            "{}/{}/name/{}",
+            endpoints::PARTITIONS_PATH,
+            group,
+            name
+        ));
+        let authenticator = self.authenticator_for(group, name).current();
+        let response: PartitionsResponse = self.get_json(&authenticator, url).await?;
+        Ok(response
+            .available_partitions
+            .into_iter()
+            .map(|p| Partition {
+                values: response
+                    .partition_fields
+                    .iter()
+                    .cloned()
+                    .zip(p.partitions)
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Queries all the partitions of the dataset `name` in the `group` separately and streams
+    /// their records as one stream. Up to `concurrency` partitions are read at the same time, so
+    /// if it is more than 1, the records of the partitions are interleaved. This avoids the deep
+    /// offsets of paging through a huge dataset as a whole.
+    ///
+    /// The partitions are read by the returned stream itself, so dropping it, e.g. after an
+    /// error, cancels the partitions being read.
+    pub async fn dataset_by_partitions<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        group: &'a str,
+        name: &'a str,
+        query: DatasetQuery,
+        concurrency: usize,
+    ) -> Result<impl TryStream<Ok = T, Error = Error> + 'a> {
+        let partitions = self.partitions(group, name).await?;
+        Ok(stream::iter(partitions)
+            .then(move |partition| {
+                self.dataset::<T>(group, name, query.clone().partition(&partition))
+            })
+            .map_ok(|records| Box::pin(records.into_stream()))
+            .try_flatten_unordered(concurrency.max(1)))
+    }
+}
+
+/// A partition of a FINRA dataset, e.g. the data of a single day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// The values of the partition fields identifying the partition as `(field, value)` pairs.
+    pub values: Vec<(String, String)>,
+}
+
+/// The partitions of a dataset as returned by FINRA.
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct PartitionsResponse {
+    partition_fields: Vec<String>,
+    available_partitions: Vec<AvailablePartition>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct AvailablePartition {
+    /// The values of the partition fields, in the order of the fields.
+    partitions: Vec<String>,
+}
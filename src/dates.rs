@@ -0,0 +1,134 @@
+use std::fmt::Display;
+
+use serde::{de, Deserialize, Deserializer};
+use time::{Date, Month};
+
+/// Parses the dates in the `YYYY-MM-DD` format used by FINRA.
+pub(crate) fn parse_date(s: &str) -> Option<Date> {
+    let mut parts = s.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+/// Formats the date in the `YYYY-MM-DD` format used by FINRA.
+pub(crate) fn format_date(date: &Date) -> String {
+    format!(
+        "{}-{:02}-{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
+
+/// A month of a year, e.g. the accounting period of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct YearMonth {
+    pub year: i32,
+    pub month: Month,
+}
+
+impl YearMonth {
+    /// The first day of the month.
+    pub fn first_day(&self) -> Date {
+        // this fails only for the years out of the range supported by `time`
+        Date::from_calendar_date(self.year, self.month, 1).unwrap_or(Date::MIN)
+    }
+
+    /// Parses the numbers in the `YYYYMM` or `YYYYMMDD` formats used by FINRA.
+    fn from_number(n: u64) -> Option<Self> {
+        let n = if n > 999_999 { n / 100 } else { n };
+        Some(Self {
+            year: i32::try_from(n / 100).ok()?,
+            month: Month::try_from(u8::try_from(n % 100).ok()?).ok()?,
+        })
+    }
+}
+
+impl PartialOrd for YearMonth {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for YearMonth {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.year, self.month as u8).cmp(&(other.year, other.month as u8))
+    }
+}
+
+impl Display for YearMonth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{:02}", self.year, self.month as u8)
+    }
+}
+
+impl<'de> Deserialize<'de> for YearMonth {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let n = u64::deserialize(deserializer)?;
+        Self::from_number(n)
+            .ok_or_else(|| de::Error::custom(format!("invalid year and month: {}", n)))
+    }
+}
+
+/// Serialized as the `YYYYMM` number.
+#[cfg(feature = "serialize")]
+impl serde::Serialize for YearMonth {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u64(self.year as u64 * 100 + self.month as u64)
+    }
+}
+
+/// (De)serializes the optional dates in the format used by FINRA.
+pub(crate) mod finra_date {
+    use serde::{de, Deserialize, Deserializer};
+    use time::Date;
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Date>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            None => Ok(None),
+            Some(s) if s.trim().is_empty() => Ok(None),
+            Some(s) => super::parse_date(&s)
+                .map(Some)
+                .ok_or_else(|| de::Error::custom(format!("invalid date: {}", s))),
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    pub(crate) fn serialize<S>(date: &Option<Date>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match date {
+            Some(date) => serializer.serialize_some(&super::format_date(date)),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn year_month_parsed_from_finra_numbers() {
+        let expected = Some(YearMonth {
+            year: 2024,
+            month: Month::January,
+        });
+        assert_eq!(expected, YearMonth::from_number(202401));
+        assert_eq!(expected, YearMonth::from_number(20240131));
+        assert_eq!(None, YearMonth::from_number(202413));
+    }
+}
@@ -0,0 +1,352 @@
+use std::{error::Error, io};
+
+use csv::{ByteRecord, Position};
+use csv_core::{ReadRecordResult, Reader};
+use serde::de::DeserializeOwned;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// The format of the responses requested from FINRA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseFormat {
+    /// Comma separated values with a header line. This is the default.
+    #[default]
+    Csv,
+    /// An array of JSON objects. This avoids the quirks of the CSV, like the quoting of the commas
+    /// in the issue names.
+    Json,
+}
+
+impl ResponseFormat {
+    /// The media type to accept in the responses.
+    pub(crate) fn media_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/plain",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+/// A record decoded from a page, not yet deserialized.
+pub(crate) enum Row {
+    Csv(ByteRecord),
+    Json { index: u64, value: Vec<u8> },
+}
+
+impl Row {
+    /// The line a CSV row ends on, or the position of a JSON record in the array, counting from 1.
+    pub(crate) fn position(&self) -> u64 {
+        match self {
+            Self::Csv(record) => record.position().map_or(0, |p| p.line()),
+            Self::Json { index, .. } => *index,
+        }
+    }
+}
+
+/// Decodes the records of a page in the format of the response as the chunks of its body arrive.
+pub(crate) enum Decoder {
+    Csv(Box<CsvDecoder>),
+    Json(JsonDecoder),
+}
+
+impl Decoder {
+    pub(crate) fn new(format: ResponseFormat) -> Self {
+        match format {
+            ResponseFormat::Csv => Self::Csv(Box::new(CsvDecoder::new())),
+            ResponseFormat::Json => Self::Json(JsonDecoder::default()),
+        }
+    }
+
+    /// Decodes the rows completed by the `chunk` of the input into `rows`.
+    pub(crate) fn feed(&mut self, chunk: &[u8], rows: &mut Vec<Row>) {
+        match self {
+            Self::Csv(decoder) => {
+                let mut records = Vec::new();
+                decoder.feed(chunk, &mut records);
+                rows.extend(records.into_iter().map(Row::Csv));
+            }
+            Self::Json(decoder) => decoder.feed(chunk, rows),
+        }
+    }
+
+    /// Decodes the last row after the whole input was fed.
+    pub(crate) fn finish(&mut self, rows: &mut Vec<Row>) {
+        if let Self::Csv(decoder) = self {
+            let mut records = Vec::new();
+            decoder.finish(&mut records);
+            rows.extend(records.into_iter().map(Row::Csv));
+        }
+    }
+
+    /// The names of the fields of the records, once known.
+    pub(crate) fn fields(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Csv(decoder) => decoder.headers().map(|headers| {
+                headers
+                    .iter()
+                    .map(|h| String::from_utf8_lossy(h).into_owned())
+                    .collect()
+            }),
+            Self::Json(decoder) => decoder.fields.clone(),
+        }
+    }
+
+    /// Deserializes the `row` decoded by this decoder.
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        &self,
+        row: &Row,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        match (self, row) {
+            (Self::Csv(decoder), Row::Csv(record)) => {
+                let headers = decoder.headers().ok_or("no CSV header")?;
+                if record.len() != headers.len() {
+                    return Err(format!(
+                        "the row has {} fields but the header has {}",
+                        record.len(),
+                        headers.len()
+                    )
+                    .into());
+                }
+                Ok(record.deserialize(Some(headers))?)
+            }
+            (_, Row::Json { value, .. }) => Ok(serde_json::from_slice(value)?),
+            (_, Row::Csv(_)) => Err(io::Error::from(io::ErrorKind::InvalidData).into()),
+        }
+    }
+}
+
+/// Decodes the CSV records from the chunks of the response body as they arrive, so that a page
+/// doesn't need to be held in memory as a whole. The first record is the header. The positions of
+/// the records contain their line numbers in the input.
+pub(crate) struct CsvDecoder {
+    reader: Reader,
+    /// The fields of the record being decoded.
+    output: Vec<u8>,
+    output_len: usize,
+    /// The end offsets of the fields of the record being decoded.
+    ends: Vec<usize>,
+    ends_len: usize,
+    headers: Option<ByteRecord>,
+}
+
+impl CsvDecoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 32],
+            ends_len: 0,
+            headers: None,
+        }
+    }
+
+    /// The header of the CSV, once decoded.
+    pub(crate) fn headers(&self) -> Option<&ByteRecord> {
+        self.headers.as_ref()
+    }
+
+    /// Decodes the records completed by the `chunk` of the input into `records`.
+    pub(crate) fn feed(&mut self, chunk: &[u8], records: &mut Vec<ByteRecord>) {
+        if !chunk.is_empty() {
+            self.decode(chunk, records);
+        }
+    }
+
+    /// Decodes the last record after the whole input was fed.
+    pub(crate) fn finish(&mut self, records: &mut Vec<ByteRecord>) {
+        self.decode(&[], records);
+    }
+
+    /// Empty `input` means the end of the input to the CSV reader.
+    fn decode(&mut self, mut input: &[u8], records: &mut Vec<ByteRecord>) {
+        loop {
+            let (result, read, written, ended) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            // the line terminator of a record is counted in the line number of the reader
+            let terminated = read > 0 && input[read - 1] == b'\n';
+            input = &input[read..];
+            self.output_len += written;
+            self.ends_len += ended;
+
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    let mut record = ByteRecord::with_capacity(self.output_len, self.ends_len);
+                    let mut start = 0;
+                    for end in &self.ends[..self.ends_len] {
+                        let field = &self.output[start..*end];
+                        if self.headers.is_none() && start == 0 {
+                            record.push_field(field.strip_prefix(BOM).unwrap_or(field));
+                        } else {
+                            record.push_field(field);
+                        }
+                        start = *end;
+                    }
+                    self.output_len = 0;
+                    self.ends_len = 0;
+
+                    let mut position = Position::new();
+                    position.set_line(self.reader.line() - u64::from(terminated));
+                    record.set_position(Some(position));
+
+                    if self.headers.is_none() {
+                        self.headers = Some(record);
+                    } else {
+                        records.push(record);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits the JSON array of the records into the individual records as the chunks of the response
+/// body arrive. The records are only deserialized later.
+#[derive(Default)]
+pub(crate) struct JsonDecoder {
+    /// The nesting depth of the arrays and objects, 1 inside the top-level array.
+    depth: u32,
+    in_string: bool,
+    escaped: bool,
+    /// The bytes of the record being decoded.
+    value: Vec<u8>,
+    records: u64,
+    /// The names of the fields of the first record.
+    fields: Option<Vec<String>>,
+}
+
+impl JsonDecoder {
+    fn feed(&mut self, chunk: &[u8], rows: &mut Vec<Row>) {
+        for b in chunk {
+            if self.in_string {
+                self.value.push(*b);
+                if self.escaped {
+                    self.escaped = false;
+                } else if *b == b'\\' {
+                    self.escaped = true;
+                } else if *b == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match b {
+                b'[' | b'{' => {
+                    self.depth += 1;
+                    if self.depth > 1 {
+                        self.value.push(*b);
+                    }
+                }
+                b']' | b'}' => {
+                    if self.depth > 1 {
+                        self.value.push(*b);
+                    } else {
+                        self.emit(rows);
+                    }
+                    self.depth = self.depth.saturating_sub(1);
+                }
+                b',' if self.depth == 1 => self.emit(rows),
+                b if b.is_ascii_whitespace() && self.depth == 1 => {}
+                b => {
+                    if self.depth > 0 {
+                        self.value.push(*b);
+                    }
+                    if *b == b'"' {
+                        self.in_string = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Emits the record decoded so far, if any.
+    fn emit(&mut self, rows: &mut Vec<Row>) {
+        if self.value.is_empty() {
+            return;
+        }
+
+        let value = std::mem::take(&mut self.value);
+        if self.fields.is_none() {
+            self.fields =
+                serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&value)
+                    .ok()
+                    .map(|record| record.keys().cloned().collect());
+        }
+        self.records += 1;
+        rows.push(Row::Json {
+            index: self.records,
+            value,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_split_across_chunks() {
+        let csv = "\u{feff}id,name\r\n1,\"a\nb\"\r\n\n2,c";
+
+        for chunk_size in 1..csv.len() {
+            let mut decoder = CsvDecoder::new();
+            let mut records = Vec::new();
+            for chunk in csv.as_bytes().chunks(chunk_size) {
+                decoder.feed(chunk, &mut records);
+            }
+            decoder.finish(&mut records);
+
+            assert_eq!(
+                decoder.headers(),
+                Some(&ByteRecord::from(vec!["id", "name"]))
+            );
+            assert_eq!(
+                records,
+                vec![
+                    ByteRecord::from(vec!["1", "a\nb"]),
+                    ByteRecord::from(vec!["2", "c"]),
+                ]
+            );
+            assert_eq!(
+                records
+                    .iter()
+                    .map(|r| r.position().map(|p| p.line()))
+                    .collect::<Vec<_>>(),
+                [Some(3), Some(5)]
+            );
+        }
+    }
+
+    #[test]
+    fn json_records_split_across_chunks() {
+        let json = r#"[{"id": 1, "name": "a, \"b\" [c]"}, {"id": 2, "name": "}"}]"#;
+
+        for chunk_size in 1..json.len() {
+            let mut decoder = Decoder::new(ResponseFormat::Json);
+            let mut rows = Vec::new();
+            for chunk in json.as_bytes().chunks(chunk_size) {
+                decoder.feed(chunk, &mut rows);
+            }
+            decoder.finish(&mut rows);
+
+            let records: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|r| decoder.deserialize(r).unwrap())
+                .collect();
+            assert_eq!(
+                records,
+                vec![
+                    serde_json::json!({"id": 1, "name": "a, \"b\" [c]"}),
+                    serde_json::json!({"id": 2, "name": "}"}),
+                ]
+            );
+            assert_eq!(decoder.fields(), Some(vec!["id".into(), "name".into()]));
+        }
+    }
+}
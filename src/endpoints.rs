@@ -0,0 +1,178 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The base URL of the FINRA API.
+pub(crate) const API_URL: &str = "https://api.finra.org";
+
+/// The base URL of the FINRA data API.
+pub(crate) const DATA_ENDPOINT: &str = "https://api.finra.org/data/group";
+
+/// The path of the data API relative to the base URL of the FINRA API.
+pub(crate) const DATA_PATH: &str = "data/group";
+
+/// The path of the list of the datasets available in the FINRA API.
+pub(crate) const CATALOG_PATH: &str = "datasets";
+
+/// The path of the metadata of the datasets in the FINRA API.
+pub(crate) const METADATA_PATH: &str = "metadata/group";
+
+/// The path of the asynchronous requests in the FINRA API.
+#[cfg(feature = "experimental-async-requests")]
+pub(crate) const ASYNC_PATH: &str = "async";
+
+/// The path of the partitions of the datasets in the FINRA API.
+pub(crate) const PARTITIONS_PATH: &str = "partitions/group";
+
+/// Describes when to fail over from an unhealthy base URL to the next one and when to try it
+/// again.
+#[derive(Debug, Clone)]
+pub struct FailoverPolicy {
+    /// How many requests to a base URL need to fail in a row to consider it unhealthy.
+    pub failure_threshold: u32,
+    /// How long to wait before trying an unhealthy base URL again.
+    pub recovery_after: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 3,
+            recovery_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The health of one of the configured base URLs, as seen by this client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointHealth {
+    pub base_url: String,
+    /// `false` if the base URL is currently avoided because of the failed requests.
+    pub healthy: bool,
+    /// The number of the requests that failed in a row.
+    pub consecutive_failures: u32,
+}
+
+/// The base URLs of the data API in the order of preference, together with their health. The
+/// requests are sent to the first healthy base URL. Unhealthy base URLs are tried again after the
+/// recovery period, so that the client returns back to the primary once it recovers.
+#[derive(Debug)]
+pub(crate) struct Endpoints {
+    policy: FailoverPolicy,
+    health: Mutex<Vec<Health>>,
+}
+
+#[derive(Debug)]
+struct Health {
+    base_url: String,
+    consecutive_failures: u32,
+    down_since: Option<Instant>,
+}
+
+impl Endpoints {
+    pub(crate) fn new(base_urls: Vec<String>, policy: FailoverPolicy) -> Self {
+        Self {
+            policy,
+            health: Mutex::new(
+                base_urls
+                    .into_iter()
+                    .map(|base_url| Health {
+                        base_url: base_url.trim_end_matches('/').to_string(),
+                        consecutive_failures: 0,
+                        down_since: None,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Chooses the base URL to send the next request to. Returns its index to report the outcome
+    /// of the request with, and the URL of the `path` on it.
+    pub(crate) fn select(&self, path: &str) -> (usize, String) {
+        let Ok(health) = self.health.lock() else {
+            return (0, format!("{}/{}", DATA_ENDPOINT, path));
+        };
+
+        let now = Instant::now();
+        let index = health
+            .iter()
+            .position(|h| match h.down_since {
+                None => true,
+                Some(since) => now.duration_since(since) >= self.policy.recovery_after,
+            })
+            // if everything is down, there's nothing better than the primary
+            .unwrap_or(0);
+
+        (index, format!("{}/{}", health[index].base_url, path))
+    }
+
+    /// Records the outcome of a request sent to the base URL at the `index`.
+    pub(crate) fn report(&self, index: usize, success: bool) {
+        let Ok(mut health) = self.health.lock() else {
+            return;
+        };
+        let Some(h) = health.get_mut(index) else {
+            return;
+        };
+
+        if success {
+            h.consecutive_failures = 0;
+            h.down_since = None;
+        } else {
+            h.consecutive_failures += 1;
+            if h.consecutive_failures >= self.policy.failure_threshold {
+                // this also restarts the recovery period when the retry after it fails
+                h.down_since = Some(Instant::now());
+            }
+        }
+    }
+
+    pub(crate) fn health(&self) -> Vec<EndpointHealth> {
+        self.health
+            .lock()
+            .map(|health| {
+                health
+                    .iter()
+                    .map(|h| EndpointHealth {
+                        base_url: h.base_url.clone(),
+                        healthy: h.down_since.is_none(),
+                        consecutive_failures: h.consecutive_failures,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self::new(vec![DATA_ENDPOINT.to_string()], FailoverPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fails_over_and_recovers() {
+        let endpoints = Endpoints::new(
+            vec![
+                "https://primary/".to_string(),
+                "https://fallback".to_string(),
+            ],
+            FailoverPolicy {
+                failure_threshold: 2,
+                recovery_after: Duration::from_secs(3600),
+            },
+        );
+        endpoints.report(0, false);
+        endpoints.report(0, false);
+        assert_eq!((1, "https://fallback/a".to_string()), endpoints.select("a"));
+        assert!(!endpoints.health()[0].healthy);
+
+        endpoints.report(0, true);
+        assert_eq!((0, "https://primary/a".to_string()), endpoints.select("a"));
+    }
+}
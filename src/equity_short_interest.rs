@@ -0,0 +1,248 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    dates::finra_date,
+    numbers,
+    query::{
+        dataset_fields, CompareFilter, DateRangeFilter, DomainFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    PaginationStrategy, YearMonth,
+};
+
+/// Represents the short interest in a single stock symbol as reported by the exchange the symbol
+/// is listed on, identified by the market class.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct EquityShortInterest {
+    #[serde(rename = "stockSplitFlag")]
+    pub stock_split_flag: Option<String>,
+
+    #[serde(
+        rename = "previousShortPositionQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub previous_short_position_quantity: u64,
+
+    #[serde(
+        rename = "averageDailyVolumeQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub average_daily_volume_quantity: u64,
+
+    #[serde(rename = "issueName")]
+    pub issue_name: String,
+
+    #[serde(
+        rename = "currentShortPositionQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub current_short_position_quantity: u64,
+
+    #[serde(
+        rename = "changePreviousNumber",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub change_previous_number: i64,
+
+    #[serde(rename = "accountingYearMonthNumber")]
+    pub accounting_year_month_number: Option<YearMonth>,
+
+    /// `None` if not included in the query results.
+    #[serde(rename = "settlementDate", with = "finra_date")]
+    pub settlement_date: Option<Date>,
+
+    /// The exchange the symbol is listed on.
+    #[serde(rename = "marketClassCode")]
+    pub market_class_code: String,
+
+    #[serde(rename = "symbolCode")]
+    pub symbol_code: String,
+
+    #[serde(rename = "daysToCoverQuantity")]
+    pub days_to_cover_quantity: f64,
+
+    #[serde(rename = "issuerServicesGroupExchangeCode")]
+    pub issuer_services_group_exchange_code: String,
+
+    #[serde(rename = "revisionFlag")]
+    pub revision_flag: Option<String>,
+
+    #[serde(rename = "changePercent")]
+    pub change_percent: f64,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the equity short interest query
+    /// results.
+    EquityShortInterestField {
+        StockSplitFlag => "stockSplitFlag",
+        PreviousShortPositionQuantity => "previousShortPositionQuantity",
+        AverageDailyVolumeQuantity => "averageDailyVolumeQuantity",
+        IssueName => "issueName",
+        CurrentShortPositionQuantity => "currentShortPositionQuantity",
+        ChangePreviousNumber => "changePreviousNumber",
+        AccountingYearMonthNumber => "accountingYearMonthNumber",
+        SettlementDate => "settlementDate",
+        MarketClassCode => "marketClassCode",
+        SymbolCode => "symbolCode",
+        DaysToCoverQuantity => "daysToCoverQuantity",
+        IssuerServicesGroupExchangeCode => "issuerServicesGroupExchangeCode",
+        RevisionFlag => "revisionFlag",
+        ChangePercent => "changePercent",
+    }
+}
+
+/// The query of the equity short interest. It supports the same filtering as
+/// [`ConsolidatedShortInterestQuery`](crate::ConsolidatedShortInterestQuery).
+#[derive(Debug, Clone)]
+pub struct EquityShortInterestQuery {
+    /// If `None`, all fields are included.
+    pub fields: Option<Vec<EquityShortInterestField>>,
+    /// If `None`, the full available history is included.
+    pub date_range: Option<Range<Date>>,
+    /// If empty, the data for all symbols is included.
+    pub symbols: Vec<String>,
+    /// Any additional filters the records need to match.
+    pub compare_filters: Vec<CompareFilter>,
+    /// Any additional sets of values the fields of the records need to be in.
+    pub domain_filters: Vec<DomainFilter>,
+    /// The order of the results. If empty, the order is decided by FINRA.
+    pub sort: Vec<SortField>,
+    /// How the results are fetched from FINRA.
+    pub pagination: PaginationStrategy,
+
+    // These are internally used for paging...
+    limit: u64,
+    offset: u64,
+}
+
+impl EquityShortInterestQuery {
+    /// Creates a query for the data of a single symbol or, if it is `None`, all symbols.
+    pub fn new(
+        fields: Option<Vec<EquityShortInterestField>>,
+        date_range: Option<Range<Date>>,
+        symbol: Option<String>,
+    ) -> Self {
+        Self::with_symbols(fields, date_range, symbol.into_iter().collect())
+    }
+
+    /// Creates a query for the data of any of the provided symbols, or all symbols if `symbols`
+    /// is empty. All the symbols are fetched in a single paged stream.
+    pub fn with_symbols(
+        fields: Option<Vec<EquityShortInterestField>>,
+        date_range: Option<Range<Date>>,
+        symbols: Vec<String>,
+    ) -> Self {
+        Self {
+            fields,
+            date_range,
+            symbols,
+            compare_filters: Vec::new(),
+            domain_filters: Vec::new(),
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl Query for EquityShortInterestQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(self, by: u64) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, date_range: Range<Date>) {
+        if self.date_range.is_none() {
+            self.date_range = Some(date_range);
+        }
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        EquityShortInterestField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        EquityShortInterestField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        self.symbols.iter_mut().collect()
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref()
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        self.date_range = Some(date_range);
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+}
+
+impl Serialize for EquityShortInterestQuery {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|r| DateRangeFilter::new(EquityShortInterestField::SettlementDate.as_str(), r))
+                .collect(),
+            compare_filters: match self.symbols.as_slice() {
+                [symbol] => vec![CompareFilter::equal(
+                    EquityShortInterestField::SymbolCode,
+                    symbol.as_str(),
+                )],
+                _ => Vec::new(),
+            }
+            .into_iter()
+            .chain(self.compare_filters.iter().cloned())
+            .collect(),
+            domain_filters: match self.symbols.as_slice() {
+                [] | [_] => Vec::new(),
+                symbols => vec![DomainFilter::new(
+                    EquityShortInterestField::SymbolCode,
+                    symbols.iter().map(String::as_str),
+                )],
+            }
+            .into_iter()
+            .chain(self.domain_filters.iter().cloned())
+            .collect(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
@@ -3,22 +3,198 @@ use thiserror::Error;
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("http error: {0}")]
-    HttpError(#[from] reqwest::Error),
+    HttpError(reqwest::Error),
+
+    /// A request to FINRA or the reading of the results of a query took longer than allowed, see
+    /// [`crate::FinraBuilder::page_timeout`] and [`crate::FinraBuilder::query_deadline`].
+    #[error("timed out: {0}")]
+    Timeout(String),
+
+    #[error(
+        "FINRA responded with {status} to {url}{}",
+        .message.as_deref().map(|m| format!(": {}", m)).unwrap_or_default()
+    )]
+    Api {
+        status: reqwest::StatusCode,
+        url: String,
+        /// The explanation of the error sent by FINRA, if any.
+        message: Option<String>,
+        /// The FINRA code of the error, if any.
+        code: Option<String>,
+    },
 
     #[error("invalid headers: {0}")]
     InvalidHeaders(#[from] reqwest::header::InvalidHeaderValue),
 
+    #[error("FINRA responded with no content to {0}")]
+    NoContent(String),
+
+    /// FINRA could not process an asynchronous request, see [`crate::Finra::poll`].
+    #[cfg(feature = "experimental-async-requests")]
+    #[error(
+        "FINRA could not process the asynchronous request {id}{}",
+        .message.as_deref().map(|m| format!(": {}", m)).unwrap_or_default()
+    )]
+    AsyncRequestFailed { id: String, message: Option<String> },
+
     #[error("cannot construct client due to previous error in initialization")]
     CannotConstructHttpClient,
 
+    #[error("the externally provided token expired and cannot be refreshed")]
+    TokenExpired,
+
+    #[error("the token was granted scope '{granted}' which doesn't include all of the requested scopes {requested:?}")]
+    InsufficientScope {
+        requested: Vec<String>,
+        granted: String,
+    },
+
+    #[error("authentication failed after {attempts} attempts, last error: {last_error}")]
+    AuthenticationRetriesExhausted {
+        attempts: u32,
+        last_error: Box<Error>,
+    },
+
+    /// See [`crate::RetryBudget`].
+    #[error("the retry budget of the job is exhausted, last error: {0}")]
+    RetryBudgetExhausted(Box<Error>),
+
     #[error("cannot login: {0}")]
     CannotLogin(String),
 
+    #[error("the environment variable {0} is not set or is not valid unicode")]
+    MissingEnvVar(String),
+
+    #[error("invalid value on line {line} of the bulk file: {message}")]
+    BulkFile { line: u64, message: String },
+
+    #[error("could not export the records: {0}")]
+    Export(String),
+
+    #[error("unknown tenant: {0}")]
+    UnknownTenant(String),
+
+    #[error("unknown symbol: {0}")]
+    UnknownSymbol(String),
+
     #[error("could not compose the query: {0}")]
     QuerySerialization(#[from] serde_json::Error),
 
+    #[error("the results from {url} are missing the fields {fields:?}")]
+    MissingFields { url: String, fields: Vec<String> },
+
     #[error("could not deserialize response: {0}")]
     Deserialization(#[from] csv::Error),
+
+    #[error(
+        "could not deserialize the row at position {position} of the page at offset {offset}: {source}"
+    )]
+    RowDeserialization {
+        /// The offset of the page of the results with the row.
+        offset: u64,
+        /// The line the row ends on in a CSV page, counting from 1 for the header, or the position
+        /// of the record in a JSON page, counting from 1.
+        position: u64,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("a quantity in the row at position {position} of the page at offset {offset} overflows its type: {source}")]
+    NumericOverflow {
+        /// The offset of the page of the results with the row.
+        offset: u64,
+        /// The position of the row as in [`Error::RowDeserialization`].
+        position: u64,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(match e.url() {
+                Some(url) => format!("the request to {}", url),
+                None => "the request to FINRA".to_string(),
+            })
+        } else {
+            Self::HttpError(e)
+        }
+    }
+}
+
+impl Error {
+    /// Reads the error reported by FINRA in the body of the failed `response`.
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let url = response.url().to_string();
+        let body = response.text().await.unwrap_or_default();
+        Self::api(status, url, &body)
+    }
+
+    /// The error with the details from the `body` of the response, which is usually a JSON
+    /// object, but can be just a plain text message.
+    fn api(status: reqwest::StatusCode, url: String, body: &str) -> Self {
+        let json: Option<serde_json::Value> = serde_json::from_str(body).ok();
+        let field = |names: &[&str]| {
+            let value = names
+                .iter()
+                .find_map(|n| json.as_ref()?.get(n).filter(|v| !v.is_null()))?;
+            Some(match value.as_str() {
+                Some(s) => s.to_string(),
+                None => value.to_string(),
+            })
+        };
+
+        let message = match json {
+            Some(_) => field(&["message", "error_description", "error"]),
+            None => Some(body.trim().to_string()).filter(|b| !b.is_empty()),
+        };
+
+        Self::Api {
+            status,
+            url,
+            message,
+            code: field(&["code", "errorCode"]),
+        }
+    }
+}
+
+/// Fails with [`Error::Api`] if the `response` has a client or server error status.
+pub(crate) async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        Err(Error::from_response(response).await)
+    } else {
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn api_error_details_read_from_body() {
+        let url = "https://api.finra.org/data/group/otcMarket/name/x".to_string();
+        let error = Error::api(
+            StatusCode::BAD_REQUEST,
+            url.clone(),
+            r#"{"message": "invalid compare filter", "code": 4001}"#,
+        );
+        assert!(matches!(
+            &error,
+            Error::Api { status: StatusCode::BAD_REQUEST, message: Some(m), code: Some(c), .. }
+                if m == "invalid compare filter" && c == "4001"
+        ));
+        assert!(error.to_string().ends_with(": invalid compare filter"));
+
+        let error = Error::api(StatusCode::BAD_GATEWAY, url, " upstream failed\n");
+        assert!(matches!(
+            &error,
+            Error::Api { message: Some(m), code: None, .. } if m == "upstream failed"
+        ));
+    }
+}
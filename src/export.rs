@@ -0,0 +1,199 @@
+use std::{collections::HashMap, fmt};
+
+use futures::{future, TryStream, TryStreamExt};
+use serde::Serialize;
+
+use crate::{Error, Result};
+
+/// A record flattened into its fields for an export, as pairs of the FINRA names of the fields and
+/// their values, in the order the fields are serialized in.
+pub type ExportRow = Vec<(String, String)>;
+
+type MapFn = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// What happens to the values of a field in an export.
+pub enum FieldTransform {
+    /// The field is left out of the export.
+    Drop,
+    /// The numeric values are rounded to the number of decimal places. The non-numeric values are
+    /// left as they are.
+    Round(usize),
+    /// The values are replaced by a salted 64-bit FNV-1a hash. The same values hash to the same
+    /// hash, so the records can still be joined on the field, but this is a pseudonymization, not
+    /// a cryptographic hash. Use [`FieldTransform::Map`] with a proper hash function if the values
+    /// need to stay secret.
+    Hash { salt: String },
+    /// The values are replaced by the result of the function.
+    Map(MapFn),
+}
+
+impl fmt::Debug for FieldTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Drop => f.write_str("Drop"),
+            Self::Round(decimals) => f.debug_tuple("Round").field(decimals).finish(),
+            Self::Hash { .. } => f.write_str("Hash"),
+            Self::Map(_) => f.write_str("Map"),
+        }
+    }
+}
+
+impl FieldTransform {
+    /// Applies the transform to the value, returning `None` if the field is dropped.
+    fn apply(&self, value: String) -> Option<String> {
+        match self {
+            Self::Drop => None,
+            Self::Round(decimals) => Some(match value.parse::<f64>() {
+                Ok(number) => format!("{:.*}", decimals, number),
+                Err(_) => value,
+            }),
+            Self::Hash { salt } => Some(format!("{:016x}", fnv1a(salt, &value))),
+            Self::Map(f) => Some(f(&value)),
+        }
+    }
+}
+
+fn fnv1a(salt: &str, value: &str) -> u64 {
+    salt.bytes()
+        .chain(value.bytes())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+fn export_error(e: csv::Error) -> Error {
+    Error::Export(e.to_string())
+}
+
+/// The transforms applied to the fields of the records when exporting them, e.g. to drop the
+/// columns or to hash the symbols before sharing an extract outside of the organization.
+///
+/// The fields are identified by their FINRA names, so the field enums of the datasets can be used,
+/// e.g. `ExportTransforms::new().hash(ConsolidatedShortInterestField::SymbolCode, "salt")`. The
+/// fields without a transform are exported as they are.
+#[derive(Debug, Default)]
+pub struct ExportTransforms {
+    fields: HashMap<String, FieldTransform>,
+}
+
+impl ExportTransforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the transform of the field, replacing any previous one.
+    pub fn field(mut self, field: impl AsRef<str>, transform: FieldTransform) -> Self {
+        self.fields.insert(field.as_ref().to_string(), transform);
+        self
+    }
+
+    /// Leaves the field out of the export.
+    pub fn drop(self, field: impl AsRef<str>) -> Self {
+        self.field(field, FieldTransform::Drop)
+    }
+
+    /// Rounds the values of the field to the number of decimal places.
+    pub fn round(self, field: impl AsRef<str>, decimals: usize) -> Self {
+        self.field(field, FieldTransform::Round(decimals))
+    }
+
+    /// Replaces the values of the field by their salted hashes. See [`FieldTransform::Hash`].
+    pub fn hash(self, field: impl AsRef<str>, salt: impl Into<String>) -> Self {
+        self.field(field, FieldTransform::Hash { salt: salt.into() })
+    }
+
+    /// Replaces the values of the field by the result of the function.
+    pub fn map<F>(self, field: impl AsRef<str>, f: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.field(field, FieldTransform::Map(Box::new(f)))
+    }
+
+    /// Flattens the record into its fields and applies the transforms to them. The record must
+    /// serialize into a flat struct or map of the fields, which all the records of this crate do.
+    pub fn apply<T: Serialize>(&self, record: &T) -> Result<ExportRow> {
+        // the CSV serializer flattens the record the same way as the CSV pages of FINRA, with the
+        // missing values empty and the fields in the order of declaration
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(record).map_err(export_error)?;
+        let csv = writer
+            .into_inner()
+            .map_err(|e| Error::Export(e.to_string()))?;
+
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        let names = reader.headers().map_err(export_error)?.clone();
+        let values = reader
+            .records()
+            .next()
+            .transpose()
+            .map_err(export_error)?
+            .unwrap_or_default();
+
+        Ok(names
+            .iter()
+            .zip(values.iter())
+            .filter_map(|(name, value)| {
+                let value = match self.fields.get(name) {
+                    Some(transform) => transform.apply(value.to_string())?,
+                    None => value.to_string(),
+                };
+                Some((name.to_string(), value))
+            })
+            .collect())
+    }
+
+    /// Applies the transforms to all the records of the stream.
+    pub fn apply_stream<'a, S, T>(
+        &'a self,
+        stream: S,
+    ) -> impl TryStream<Ok = ExportRow, Error = Error> + 'a
+    where
+        S: TryStream<Ok = T, Error = Error> + 'a,
+        T: Serialize,
+    {
+        stream.and_then(move |record| future::ready(self.apply(&record)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ConsolidatedShortInterest, ConsolidatedShortInterestField};
+
+    use super::*;
+
+    #[test]
+    fn fields_transformed() {
+        let record = ConsolidatedShortInterest {
+            symbol_code: "AAPL".to_string(),
+            issue_name: "Apple Inc.".to_string(),
+            days_to_cover_quantity: 1.23456,
+            ..Default::default()
+        };
+
+        let transforms = ExportTransforms::new()
+            .hash(ConsolidatedShortInterestField::SymbolCode, "salt")
+            .drop(ConsolidatedShortInterestField::IssueName)
+            .round(ConsolidatedShortInterestField::DaysToCoverQuantity, 2)
+            .map(ConsolidatedShortInterestField::MarketClassCode, |_| {
+                "X".to_string()
+            });
+
+        let row = transforms.apply(&record).unwrap();
+        let get = |name: &str| row.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+
+        assert_eq!(None, get("issueName"));
+        assert_eq!(Some("1.23"), get("daysToCoverQuantity"));
+        assert_eq!(Some("X"), get("marketClassCode"));
+        let hashed = get("symbolCode").unwrap();
+        assert_ne!("AAPL", hashed);
+        assert_eq!(
+            hashed,
+            get_symbol(&transforms.apply(&record.clone()).unwrap())
+        );
+    }
+
+    fn get_symbol(row: &ExportRow) -> &str {
+        &row.iter().find(|(n, _)| n == "symbolCode").unwrap().1
+    }
+}
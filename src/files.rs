@@ -0,0 +1,171 @@
+//! The local files written by the cache and the sinks, handled the same way on all the platforms.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The `path` in the form that works even if it is longer than the 260 characters allowed by the
+/// Windows APIs, i.e. absolute and prefixed with `\\?\`. Elsewhere, the `path` as it is.
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        use std::{
+            ffi::OsString,
+            path::{Component, Prefix},
+        };
+
+        // the directories are limited to 248 characters, to leave room for the 8.3 file names
+        if path.as_os_str().len() < 248 {
+            return path.to_path_buf();
+        }
+        let Ok(absolute) = std::path::absolute(path) else {
+            return path.to_path_buf();
+        };
+        let mut components = absolute.components();
+        let long = match components.next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::Disk(_) => {
+                    let mut long = OsString::from(r"\\?\");
+                    long.push(absolute.as_os_str());
+                    Some(PathBuf::from(long))
+                }
+                Prefix::UNC(server, share) => {
+                    let mut long = OsString::from(r"\\?\UNC\");
+                    long.push(server);
+                    long.push(r"\");
+                    long.push(share);
+                    let mut long = PathBuf::from(long);
+                    long.extend(components.filter(|c| !matches!(c, Component::RootDir)));
+                    Some(long)
+                }
+                // already verbatim or a device
+                _ => None,
+            },
+            _ => None,
+        };
+        long.unwrap_or(absolute)
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_path_buf()
+    }
+}
+
+/// A unique temporary file next to the `path`, with the `tmp` extension.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{:08x}.tmp", fastrand::u32(..)));
+    tmp.into()
+}
+
+/// Writes the `content` to a temporary file first and renames it to the `path`, so that the
+/// concurrent readers never see a partially written file.
+#[cfg(feature = "cache")]
+pub(crate) fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    let result = fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// A file written to a temporary file next to its `path` and renamed to it only once complete,
+/// so that a failed write never replaces the existing file and the readers never see a partially
+/// written one. The temporary file is removed if dropped before [`PendingFile::commit`].
+#[cfg(any(feature = "serialize", feature = "parquet"))]
+pub(crate) struct PendingFile {
+    path: PathBuf,
+    tmp: PathBuf,
+    file: Option<io::BufWriter<fs::File>>,
+}
+
+#[cfg(any(feature = "serialize", feature = "parquet"))]
+impl PendingFile {
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        let path = long_path(path);
+        let tmp = tmp_path(&path);
+        let file = fs::File::create(&tmp)?;
+        Ok(Self {
+            path,
+            tmp,
+            file: Some(io::BufWriter::new(file)),
+        })
+    }
+
+    /// The path the file is renamed to once committed.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the rest of the file to the disk and renames it to its `path`, replacing any
+    /// existing file.
+    pub(crate) fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(&self.tmp, &self.path)
+    }
+
+    fn file(&mut self) -> io::Result<&mut io::BufWriter<fs::File>> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the file is already committed"))
+    }
+}
+
+#[cfg(any(feature = "serialize", feature = "parquet"))]
+impl io::Write for PendingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(self.file()?, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(self.file()?)
+    }
+}
+
+#[cfg(any(feature = "serialize", feature = "parquet"))]
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        // closed first, Windows doesn't remove the open files, and already renamed if committed
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+#[cfg(all(test, any(feature = "serialize", feature = "parquet")))]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn file_replaced_only_when_committed() {
+        let path = std::env::temp_dir().join(format!("finra-pending-{}.csv", std::process::id()));
+        fs::write(&path, "old").unwrap();
+
+        let mut abandoned = PendingFile::create(&path).unwrap();
+        abandoned.write_all(b"partial").unwrap();
+        drop(abandoned);
+        assert_eq!("old", fs::read_to_string(&path).unwrap());
+
+        let mut file = PendingFile::create(&path).unwrap();
+        file.write_all(b"new").unwrap();
+        assert_eq!("old", fs::read_to_string(&path).unwrap());
+        file.commit().unwrap();
+        assert_eq!("new", fs::read_to_string(&path).unwrap());
+
+        let leftovers = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(&format!("finra-pending-{}.csv.", std::process::id()))
+            })
+            .count();
+        assert_eq!(0, leftovers);
+        fs::remove_file(&path).unwrap();
+    }
+}
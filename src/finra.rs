@@ -1,60 +1,158 @@
-use crate::{pager, ConsolidatedShortInterestQuery, Error, Result};
-use base64::Engine;
-use futures::{stream, StreamExt, TryStream, TryStreamExt};
-use reqwest::{
-    header::{self, HeaderValue},
-    Client, ClientBuilder, StatusCode,
+#[cfg(feature = "prometheus")]
+use crate::metrics::MetricsCollector;
+use crate::{
+    auth::{Authenticator, DatasetAuthenticator, DatasetAuthenticators},
+    bulk,
+    catalog::DatasetMetadata,
+    dates::finra_date,
+    endpoints::{self, Endpoints},
+    error,
+    metrics::Metrics,
+    middleware::Middlewares,
+    numbers, pager,
+    pager::HttpPages,
+    progress::ProgressSink,
+    rate_limit::RateLimiter,
+    sync,
+    warning::Warnings,
+    BlocksSummaryQuery, BlocksSummaryRecord, BulkResult, ConsolidatedShortInterestField,
+    ConsolidatedShortInterestQuery, DailyShortSaleVolume, DailyShortSaleVolumeQuery, DatasetInfo,
+    EndpointHealth, EquityShortInterest, EquityShortInterestQuery, Error, ErrorTolerance,
+    FieldMetadata, FinraBuilder, MonthlySummaryQuery, MonthlySummaryRecord, OverflowPolicy,
+    PaginationStrategy, Projection, Query, ResponseFormat, Result, RetryBudget, RetryPolicy,
+    SymbolMap, SymbolValidator, SyncCheckpoint, ThresholdListQuery, ThresholdListRecord, Token,
+    UnknownSymbolPolicy, Usage, UsageTracker, Warning, WeeklySummaryQuery, WeeklySummaryRecord,
+    YearMonth,
 };
-use serde::Deserialize;
-use time::{Duration, OffsetDateTime};
+use futures::{future::Either, stream, StreamExt, TryStream, TryStreamExt};
+use reqwest::{header, Client, ClientBuilder};
+use serde::{de::DeserializeOwned, Deserialize};
+use time::{Date, Duration, OffsetDateTime};
 
-#[cfg(not(feature = "tokio"))]
-use std::sync::Mutex;
-use std::{mem, sync::Arc};
+use std::{collections::HashMap, ops::Range, sync::Arc};
 
-#[cfg(feature = "tokio")]
-use tokio::sync::Mutex;
-
-const OAUTH2_ENDPOINT: &str =
-    "https://ews.fip.finra.org/fip/rest/ews/oauth2/access_token?grant_type=client_credentials";
-const SHORT_INTEREST_ENDPOINT: &str =
-    "https://api.finra.org/data/group/otcmarket/name/consolidatedShortInterest";
-const MOCK_SHORT_INTEREST_ENDPOINT: &str =
-    "https://api.finra.org/data/group/otcmarket/name/consolidatedShortInterestMock";
+#[cfg(not(feature = "no-mock-datasets"))]
+const MOCK_DATASET_SUFFIX: &str = "Mock";
+/// How many requests to send at the same time when querying multiple things at once.
+const MAX_CONCURRENT_REQUESTS: usize = 4;
 
 /// The main entry-point to access the Finra data.
 pub struct Finra {
-    use_mock_datasets: bool,
-    client_getter: Mutex<ClientGetter>,
+    pub(crate) config: Config,
+    pub(crate) authenticator: Arc<Authenticator>,
+    dataset_authenticators: DatasetAuthenticators,
+}
+
+/// The configuration of a [`Finra`] instance, set up using the [`FinraBuilder`].
+#[derive(Default)]
+pub(crate) struct Config {
+    pub(crate) symbol_map: Option<SymbolMap>,
+    pub(crate) symbol_validator: Option<(Arc<dyn SymbolValidator>, UnknownSymbolPolicy)>,
+    #[cfg(not(feature = "no-mock-datasets"))]
+    pub(crate) use_mock_datasets: bool,
+    pub(crate) default_lookback: Option<Duration>,
+    pub(crate) warnings: Warnings,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) progress: ProgressSink,
+    pub(crate) endpoints: Arc<Endpoints>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) retry_budget: Option<RetryBudget>,
+    pub(crate) rate_limiter: Arc<RateLimiter>,
+    pub(crate) strict_rows: bool,
+    pub(crate) overflow_policy: OverflowPolicy,
+    pub(crate) response_format: ResponseFormat,
+    pub(crate) middlewares: Middlewares,
+    pub(crate) api_url: Option<String>,
+    pub(crate) page_timeout: Option<std::time::Duration>,
+    pub(crate) query_deadline: Option<std::time::Duration>,
+    #[cfg(feature = "cache")]
+    pub(crate) cache: Option<Arc<dyn crate::CacheBackend>>,
+}
+
+impl Config {
+    /// The URL of the `path` relative to the base URL of the FINRA API.
+    pub(crate) fn api_url(&self, path: &str) -> String {
+        let base = self.api_url.as_deref().unwrap_or(endpoints::API_URL);
+        format!("{}/{}", base.trim_end_matches('/'), path)
+    }
+
+    /// The date range to use for queries that don't specify any.
+    fn default_date_range(&self) -> Option<Range<Date>> {
+        let today = OffsetDateTime::now_utc().date();
+        self.default_lookback
+            .map(|lookback| today.saturating_sub(lookback)..today)
+    }
+}
+
+/// Applies the `use_mock_datasets` argument of the constructors of [`Finra`].
+#[cfg(not(feature = "no-mock-datasets"))]
+fn mock_datasets(builder: FinraBuilder, use_mock_datasets: bool) -> FinraBuilder {
+    builder.use_mock_datasets(use_mock_datasets)
+}
+
+/// Applies the `use_mock_datasets` argument of the constructors of [`Finra`]. The mock datasets
+/// are compiled out, so the argument is only reported if it asks for them.
+#[cfg(feature = "no-mock-datasets")]
+fn mock_datasets(builder: FinraBuilder, use_mock_datasets: bool) -> FinraBuilder {
+    if use_mock_datasets {
+        tracing::warn!("the mock datasets are disabled by the no-mock-datasets feature");
+    }
+    builder
+}
+
+/// The metadata of the results of a query, as reported by FINRA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResultsMeta {
+    /// The total number of the records matching the query, from the `Record-Total` header. `None`
+    /// if FINRA didn't report it.
+    pub total: Option<u64>,
 }
 
 /// Represents the short interest data obtained from Finra for a single stock symbol.
-#[derive(Debug, Deserialize, Default)]
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
 #[serde(default)]
 pub struct ConsolidatedShortInterest {
     #[serde(rename = "stockSplitFlag")]
     pub stock_split_flag: Option<String>,
 
-    #[serde(rename = "previousShortPositionQuantity")]
-    pub previous_short_position_quantity: usize,
+    #[serde(
+        rename = "previousShortPositionQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub previous_short_position_quantity: u64,
 
-    #[serde(rename = "averageDailyVolumeQuantity")]
-    pub average_daily_volume_quantity: usize,
+    #[serde(
+        rename = "averageDailyVolumeQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub average_daily_volume_quantity: u64,
 
     #[serde(rename = "issueName")]
     pub issue_name: String,
 
-    #[serde(rename = "currentShortPositionQuantity")]
-    pub current_short_position_quantity: usize,
+    #[serde(
+        rename = "currentShortPositionQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub current_short_position_quantity: u64,
 
-    #[serde(rename = "changePreviousNumber")]
-    pub change_previous_number: isize,
+    #[serde(
+        rename = "changePreviousNumber",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub change_previous_number: i64,
 
     #[serde(rename = "accountingYearMonthNumber")]
-    pub accounting_year_month_number: usize,
+    pub accounting_year_month_number: Option<YearMonth>,
 
-    #[serde(rename = "settlementDate")]
-    pub settlement_date: String,
+    /// `None` if not included in the query results.
+    #[serde(rename = "settlementDate", with = "finra_date")]
+    pub settlement_date: Option<Date>,
 
     #[serde(rename = "marketClassCode")]
     pub market_class_code: String,
@@ -75,199 +173,554 @@ pub struct ConsolidatedShortInterest {
     pub change_percent: f64,
 }
 
-#[derive(Clone)]
-struct LoginData {
-    client_builder: Arc<dyn Fn() -> ClientBuilder>,
-    client_id: String,
-    client_secret: String,
-}
-
-enum ClientGetter {
-    Unauthenticated {
-        login_data: LoginData,
-    },
-    Authenticated {
-        login_data: LoginData,
-        client: Client,
-        valid_until: OffsetDateTime,
-    },
-}
-
 impl Finra {
     /// Creates a new instance. `client_builder` is a function for obtaining new reqwest clients
     /// from builders. You can use this to set up a builder with a proxy or whatever other
     /// requirements you have. The Authorization header will be set based on the tokens obtained
     /// using the provided `client_id` and `client_secret`.
+    ///
+    /// Use [`Finra::builder`] for more configuration options.
+    ///
+    /// With the `no-mock-datasets` feature, `use_mock_datasets` is ignored.
     pub fn new(
-        client_builder: Arc<dyn Fn() -> ClientBuilder>,
+        client_builder: Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
         client_id: String,
         client_secret: String,
         use_mock_datasets: bool,
+    ) -> Self {
+        let builder = FinraBuilder::new(client_id, client_secret).client_builder(client_builder);
+        mock_datasets(builder, use_mock_datasets).build()
+    }
+
+    /// Creates a new instance using an access token obtained outside of this crate, e.g. from a
+    /// token broker. The token cannot be refreshed by this crate so once it reaches `expires_at`,
+    /// all the calls fail with [`Error::TokenExpired`] and a new instance needs to be created.
+    ///
+    /// With the `no-mock-datasets` feature, `use_mock_datasets` is ignored.
+    pub fn with_token(
+        client_builder: Arc<dyn Fn() -> ClientBuilder + Send + Sync>,
+        access_token: String,
+        expires_at: OffsetDateTime,
+        use_mock_datasets: bool,
+    ) -> Self {
+        let builder =
+            FinraBuilder::with_token(access_token, expires_at).client_builder(client_builder);
+        mock_datasets(builder, use_mock_datasets).build()
+    }
+
+    /// Creates a new instance authenticating using the client credentials from the environment
+    /// variables, see [`FinraBuilder::from_env`]. The mock datasets are used if
+    /// `FINRA_USE_MOCK_DATASETS` is set to `true` or `1`.
+    ///
+    /// With the `no-mock-datasets` feature, `FINRA_USE_MOCK_DATASETS` is ignored.
+    pub fn from_env() -> Result<Self> {
+        let use_mock_datasets = std::env::var("FINRA_USE_MOCK_DATASETS")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        Ok(mock_datasets(FinraBuilder::from_env()?, use_mock_datasets).build())
+    }
+
+    /// Returns a builder of a new instance authenticating using the provided client credentials.
+    pub fn builder(client_id: String, client_secret: String) -> FinraBuilder {
+        FinraBuilder::new(client_id, client_secret)
+    }
+
+    pub(crate) fn from_parts(
+        authenticator: Arc<Authenticator>,
+        dataset_authenticators: HashMap<(String, String), Arc<Authenticator>>,
+        config: Config,
     ) -> Self {
         Self {
-            client_getter: Mutex::new(ClientGetter::Unauthenticated {
-                login_data: LoginData {
-                    client_builder,
-                    client_id,
-                    client_secret,
-                },
-            }),
-            use_mock_datasets,
+            authenticator,
+            dataset_authenticators: DatasetAuthenticators::new(dataset_authenticators),
+            config,
         }
     }
 
+    /// The authenticator to use for querying the dataset `name` in the `group`.
+    pub(crate) fn authenticator_for(&self, group: &str, name: &str) -> DatasetAuthenticator {
+        DatasetAuthenticator::new(
+            self.authenticator.clone(),
+            self.dataset_authenticators.clone(),
+            group,
+            name,
+        )
+    }
+
     /// Queries the consolidated short interest from finra.org. Use the `query` parameter to limit
     /// the size of the data. The full dataset is humongous.
     pub async fn consolidated_short_interest(
         &self,
         query: ConsolidatedShortInterestQuery,
     ) -> Result<impl TryStream<Ok = ConsolidatedShortInterest, Error = Error>> {
-        let endpoint = if self.use_mock_datasets {
-            MOCK_SHORT_INTEREST_ENDPOINT
-        } else {
-            SHORT_INTEREST_ENDPOINT
-        };
+        self.fetch("otcMarket", "consolidatedShortInterest", query)
+            .await
+    }
 
-        let cl = self
-            .get_client()
-            .await?
-            .ok_or(Error::CannotConstructHttpClient)?;
+    /// Queries only the fields of the [`Projection`] `P` of the consolidated short interest.
+    /// Unlike limiting the `fields` of the query of [`Self::consolidated_short_interest`], which
+    /// leaves the other fields at their defaults, the records only hold the requested fields and a
+    /// record missing any of them is not returned. The `fields` of the `query` are replaced by the
+    /// fields of the projection.
+    pub async fn consolidated_short_interest_projected<P>(
+        &self,
+        mut query: ConsolidatedShortInterestQuery,
+    ) -> Result<impl TryStream<Ok = P, Error = Error>>
+    where
+        P: Projection<Field = ConsolidatedShortInterestField>,
+    {
+        query.fields = Some(P::fields());
+        self.fetch("otcMarket", "consolidatedShortInterest", query)
+            .await
+    }
 
-        Ok(
-            pager::all_results::<ConsolidatedShortInterest, ConsolidatedShortInterestQuery>(
-                cl, endpoint, query,
-            )
-            .await?
-            .map_ok(|vs| stream::iter(vs).map(Ok::<ConsolidatedShortInterest, Error>))
-            .try_flatten(),
-        )
+    /// Fetches the consolidated short interest settled after `since`, the latest settlement date
+    /// of the records already stored, e.g. in a local database. The date range of the `query` is
+    /// limited to the dates after `since`, or left as it is if `since` is `None`. Returns the new
+    /// records together with the checkpoint to store for the next sync once they are all read. If
+    /// the date range of the `query` ends on or before `since`, nothing is fetched and there are
+    /// no new records.
+    pub async fn sync_consolidated_short_interest(
+        &self,
+        since: Option<Date>,
+        mut query: ConsolidatedShortInterestQuery,
+    ) -> Result<(
+        impl TryStream<Ok = ConsolidatedShortInterest, Error = Error>,
+        SyncCheckpoint,
+    )> {
+        if let Some(since) = since {
+            let today = OffsetDateTime::now_utc().date();
+            match sync::date_range_since(since, query.date_range.as_ref(), today) {
+                Some(date_range) => query.date_range = Some(date_range),
+                None => return Ok(sync::track(Either::Left(stream::empty()), Some(since))),
+            }
+        }
+        if let Some(fields) = &mut query.fields {
+            if !fields.contains(&ConsolidatedShortInterestField::SettlementDate) {
+                fields.push(ConsolidatedShortInterestField::SettlementDate);
+            }
+        }
+
+        let records = self.consolidated_short_interest(query).await?;
+        Ok(sync::track(Either::Right(records.into_stream()), since))
     }
 
-    async fn get_client(&self) -> Result<Option<Client>> {
-        #[cfg(feature = "tokio")]
-        let mut clg = self.client_getter.lock().await;
+    /// Queries the short interest reported by the individual exchanges from finra.org. Use the
+    /// `query` parameter to limit the size of the data.
+    pub async fn equity_short_interest(
+        &self,
+        query: EquityShortInterestQuery,
+    ) -> Result<impl TryStream<Ok = EquityShortInterest, Error = Error>> {
+        self.fetch("otcMarket", "equityShortInterest", query).await
+    }
 
-        #[cfg(not(feature = "tokio"))]
-        let mut clg = self.client_getter.lock().unwrap();
+    /// Queries the Reg SHO daily short sale volume from finra.org. Use the `query` parameter to
+    /// limit the size of the data.
+    pub async fn daily_short_sale_volume(
+        &self,
+        query: DailyShortSaleVolumeQuery,
+    ) -> Result<impl TryStream<Ok = DailyShortSaleVolume, Error = Error>> {
+        self.fetch("otcMarket", "regShoDaily", query).await
+    }
 
-        clg.ensure_authenticated().await?;
+    /// Queries the OTC transparency weekly summary of the ATS and non-ATS trading from
+    /// finra.org. Use the `query` parameter to limit the size of the data.
+    pub async fn weekly_summary(
+        &self,
+        query: WeeklySummaryQuery,
+    ) -> Result<impl TryStream<Ok = WeeklySummaryRecord, Error = Error>> {
+        self.fetch("otcMarket", "weeklySummary", query).await
+    }
 
-        Ok(clg.get_client())
+    /// Queries the OTC transparency monthly summary of the ATS and non-ATS trading from
+    /// finra.org. Use the `query` parameter to limit the size of the data.
+    pub async fn monthly_summary(
+        &self,
+        query: MonthlySummaryQuery,
+    ) -> Result<impl TryStream<Ok = MonthlySummaryRecord, Error = Error>> {
+        self.fetch("otcMarket", "monthlySummary", query).await
+    }
+
+    /// Queries the OTC blocks summary of the block trading statistics from finra.org. Use the
+    /// `query` parameter to limit the size of the data.
+    pub async fn blocks_summary(
+        &self,
+        query: BlocksSummaryQuery,
+    ) -> Result<impl TryStream<Ok = BlocksSummaryRecord, Error = Error>> {
+        self.fetch("otcMarket", "otcBlocksSummary", query).await
+    }
+
+    /// Queries the Reg SHO threshold list from finra.org. Use the `query` parameter to limit the
+    /// size of the data.
+    pub async fn threshold_list(
+        &self,
+        query: ThresholdListQuery,
+    ) -> Result<impl TryStream<Ok = ThresholdListRecord, Error = Error>> {
+        self.fetch("otcMarket", "thresholdList", query).await
     }
-}
 
-impl ClientGetter {
-    async fn ensure_authenticated(&mut self) -> Result<()> {
-        match self {
-            Self::Unauthenticated { login_data } => {
-                let ld = login_data.clone();
-                self._authenticated_self(ld).await?;
-                Ok(())
+    /// Counts the consolidated short interest records of each of the provided symbols in the
+    /// `date_range`. Only a single record is requested for each symbol so this is a cheap way of
+    /// finding out for which symbols there are any data.
+    pub async fn counts_by_symbol(
+        &self,
+        symbols: impl IntoIterator<Item = String>,
+        date_range: Option<Range<Date>>,
+    ) -> Result<HashMap<String, u64>> {
+        self.bulk_counts_by_symbol(symbols, date_range, ErrorTolerance::FailFast)
+            .await
+            .into_result()
+    }
+
+    /// Like [`Finra::counts_by_symbol`], but reports the outcome for each symbol separately.
+    /// The counting stops once more symbols fail than the `tolerance` allows.
+    pub async fn bulk_counts_by_symbol(
+        &self,
+        symbols: impl IntoIterator<Item = String>,
+        date_range: Option<Range<Date>>,
+        tolerance: ErrorTolerance,
+    ) -> BulkResult<String, u64> {
+        bulk::fan_out(symbols, MAX_CONCURRENT_REQUESTS, tolerance, |symbol| {
+            let query = ConsolidatedShortInterestQuery::new(
+                Some(vec![ConsolidatedShortInterestField::SymbolCode]),
+                date_range.clone(),
+                Some(symbol),
+            )
+            .with_limit(1);
+            async move {
+                let (source, query) = self
+                    .prepare("otcMarket", "consolidatedShortInterest", query)
+                    .await?;
+                pager::record_total(&source, &query, &self.config.warnings).await
             }
-            Self::Authenticated {
-                login_data,
-                client: _,
-                valid_until,
-            } => {
-                if time::OffsetDateTime::now_utc() < *valid_until {
-                    Ok(())
-                } else {
-                    let ld = login_data.clone();
-                    self._authenticated_self(ld).await?;
-                    Ok(())
-                }
+        })
+        .await
+    }
+
+    /// Fetches the consolidated short interest records of each of the provided symbols in the
+    /// `date_range`, querying the symbols concurrently. A symbol that fails, e.g. because it is
+    /// not known to FINRA, doesn't fail the others - the outcome is reported for each symbol
+    /// separately. The fetching stops once more symbols fail than the `tolerance` allows.
+    pub async fn bulk_consolidated_short_interest(
+        &self,
+        symbols: impl IntoIterator<Item = String>,
+        fields: Option<Vec<ConsolidatedShortInterestField>>,
+        date_range: Option<Range<Date>>,
+        tolerance: ErrorTolerance,
+    ) -> BulkResult<String, Vec<ConsolidatedShortInterest>> {
+        bulk::fan_out(symbols, MAX_CONCURRENT_REQUESTS, tolerance, |symbol| {
+            let query = ConsolidatedShortInterestQuery::new(
+                fields.clone(),
+                date_range.clone(),
+                Some(symbol),
+            );
+            async move {
+                self.consolidated_short_interest(query)
+                    .await?
+                    .try_collect()
+                    .await
             }
-        }
+        })
+        .await
     }
 
-    fn get_client(&self) -> Option<Client> {
-        match self {
-            Self::Authenticated {
-                client,
-                login_data: _,
-                valid_until: _,
-            } => Some(client.clone()),
-            _ => None,
-        }
+    /// Like [`Finra::consolidated_short_interest`], but also returns the [`ResultsMeta`] reported
+    /// by FINRA, like the total number of the records, before the records are streamed. The first
+    /// page of the results is fetched before this returns. The pagination strategy of the query is
+    /// ignored - the results are paged through as a whole.
+    pub async fn consolidated_short_interest_with_meta(
+        &self,
+        query: ConsolidatedShortInterestQuery,
+    ) -> Result<(
+        ResultsMeta,
+        impl TryStream<Ok = ConsolidatedShortInterest, Error = Error>,
+    )> {
+        self.fetch_with_meta("otcMarket", "consolidatedShortInterest", query)
+            .await
     }
 
-    async fn _authenticated_self(&mut self, login_data: LoginData) -> Result<()> {
-        let (cl, validity) = Self::_authenticate_client(login_data.clone()).await?;
+    /// Streams all the results of the query of the dataset `name` in the `group`, using the
+    /// pagination strategy of the query.
+    pub(crate) async fn fetch<T, Q>(
+        &self,
+        group: &str,
+        name: &str,
+        query: Q,
+    ) -> Result<impl TryStream<Ok = T, Error = Error>>
+    where
+        T: DeserializeOwned,
+        Q: Query,
+    {
+        #[cfg(feature = "experimental-async-requests")]
+        if let PaginationStrategy::AsyncDownload { poll_interval } = query.pagination() {
+            let records = self.fetch_async(group, name, query, poll_interval).await?;
+            return Ok(Either::Left(pager::with_deadline(
+                records,
+                self.config.query_deadline,
+            )));
+        }
 
-        let valid_until = time::OffsetDateTime::now_utc() + validity;
-        let login_data = login_data.clone();
-        mem::swap(
-            self,
-            &mut Self::Authenticated {
-                login_data,
-                client: cl,
-                valid_until,
-            },
-        );
+        let (source, query) = self.prepare(group, name, query).await?;
+
+        let mut pagination = query.pagination();
+        let mut total = 0;
+        if let PaginationStrategy::Auto { .. } | PaginationStrategy::Sampled { .. } = pagination {
+            let count_query = query.clone().with_limit(1);
+            total = pager::record_total(&source, &count_query, &self.config.warnings).await?;
+            pagination = pagination.for_total(total);
+        }
 
-        Ok(())
+        // the parts of the results paged through separately, with the number of their records
+        // to read
+        let segments: Vec<(Q, u64)> = if let PaginationStrategy::Sampled { .. } = pagination {
+            pagination
+                .sample_pages(total)
+                .into_iter()
+                .map(|(offset, records)| {
+                    let limit = records.min(query.limit());
+                    (query.clone().move_cursor(offset).with_limit(limit), records)
+                })
+                .collect()
+        } else {
+            pagination
+                .partitions(query.date_range(), query.default_partition())
+                .into_iter()
+                .map(|date_range| {
+                    let mut query = query.clone();
+                    if let Some(date_range) = date_range {
+                        query.set_date_range(date_range);
+                    }
+                    (query, u64::MAX)
+                })
+                .collect()
+        };
+        let warnings = self.config.warnings.clone();
+        let metrics = self.config.metrics.clone();
+        let progress = self.config.progress.clone();
+        let strict_rows = self.config.strict_rows;
+        let overflow_policy = self.config.overflow_policy;
+
+        let records = stream::iter(segments)
+            .map(move |(query, records)| {
+                Box::pin(
+                    pager::paginate::<T, Q, _>(
+                        source.clone(),
+                        query,
+                        warnings.clone(),
+                        metrics.clone(),
+                        progress.clone(),
+                        strict_rows,
+                        overflow_policy,
+                    )
+                    .map_ok(|vs| stream::iter(vs).map(Ok::<T, Error>))
+                    .try_flatten()
+                    .into_stream()
+                    .take(usize::try_from(records).unwrap_or(usize::MAX)),
+                )
+            })
+            .flatten_unordered(pagination.concurrency());
+
+        let records = pager::with_deadline(records, self.config.query_deadline);
+        #[cfg(feature = "experimental-async-requests")]
+        let records = Either::Right(records);
+        Ok(records)
     }
 
-    async fn _authenticate_client(login_data: LoginData) -> Result<(Client, time::Duration)> {
-        let auth_header = "Basic ".to_string()
-            + &base64::prelude::BASE64_STANDARD
-                .encode(login_data.client_id + ":" + &login_data.client_secret);
+    /// Like [`Finra::fetch`], but fetches the first page right away to return the metadata of the
+    /// results before streaming them. The results are always paged through as a whole, so that
+    /// the total covers all of them.
+    pub(crate) async fn fetch_with_meta<T, Q>(
+        &self,
+        group: &str,
+        name: &str,
+        query: Q,
+    ) -> Result<(ResultsMeta, impl TryStream<Ok = T, Error = Error>)>
+    where
+        T: DeserializeOwned,
+        Q: Query,
+    {
+        let (source, query) = self.prepare(group, name, query).await?;
+
+        let (total, pages) = pager::paginate_with_total::<T, Q, _>(
+            source,
+            query,
+            self.config.warnings.clone(),
+            self.config.metrics.clone(),
+            self.config.progress.clone(),
+            self.config.strict_rows,
+            self.config.overflow_policy,
+        )
+        .await?;
+
+        let records = pages
+            .map_ok(|vs| stream::iter(vs).map(Ok::<T, Error>))
+            .try_flatten();
+        Ok((
+            ResultsMeta { total },
+            pager::with_deadline(records, self.config.query_deadline),
+        ))
+    }
+
+    /// Applies the configured defaults and validations to the query of the dataset `name` in the
+    /// `group` and returns it together with the source of its pages. Also makes sure we can
+    /// authenticate, so that authentication failures are reported before any data are read.
+    pub(crate) async fn prepare<Q: Query>(
+        &self,
+        group: &str,
+        name: &str,
+        mut query: Q,
+    ) -> Result<(HttpPages, Q)> {
+        if let Some(date_range) = self.config.default_date_range() {
+            query.default_date_range(date_range);
+        }
 
-        let login_client = (login_data.client_builder)().build()?;
-        let login_req = login_client.post(OAUTH2_ENDPOINT);
-        let login_req = login_req.header(header::AUTHORIZATION, auth_header);
+        if let Some(map) = &self.config.symbol_map {
+            for symbol in query.symbols_mut() {
+                *symbol = map.to_finra(symbol);
+            }
+        }
 
-        let login_response = login_req.send().await?;
-        let login_status = login_response.status();
-        if login_status != StatusCode::OK {
-            return Err(Error::CannotLogin(format!(
-                "login attempt failed with status code {}",
-                login_status
-            )));
+        if let Some((validator, policy)) = &self.config.symbol_validator {
+            for symbol in query.symbols_mut() {
+                *symbol = validator.normalize(symbol);
+                if !validator.is_known(symbol) {
+                    match policy {
+                        UnknownSymbolPolicy::Warn => {
+                            self.config.warnings.emit(Warning::UnknownSymbol {
+                                symbol: symbol.clone(),
+                            })
+                        }
+                        UnknownSymbolPolicy::Error => {
+                            return Err(Error::UnknownSymbol(symbol.clone()))
+                        }
+                    }
+                }
+            }
         }
 
-        let login_json: serde_json::Value = login_response.json().await?;
+        #[cfg(not(feature = "no-mock-datasets"))]
+        let suffix = if self.config.use_mock_datasets {
+            MOCK_DATASET_SUFFIX
+        } else {
+            ""
+        };
+        #[cfg(feature = "no-mock-datasets")]
+        let suffix = "";
+        let path = format!("{}/name/{}{}", group, name, suffix);
+
+        let authenticator = self.authenticator_for(group, name);
+        authenticator.current().authorized().await?;
+
+        let source = HttpPages::new(
+            authenticator,
+            self.config.endpoints.clone(),
+            path,
+            self.config.metrics.clone(),
+            self.config.retry_policy.clone(),
+            self.config.rate_limiter.clone(),
+            self.config.response_format,
+        )
+        .with_middlewares(self.config.middlewares.clone())
+        .with_timeout(self.config.page_timeout)
+        .with_retry_budget(self.config.retry_budget.clone());
+        #[cfg(feature = "cache")]
+        let source = source.with_cache(self.config.cache.clone());
 
-        let valid_until = login_json
-            .get("expires_in")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| {
-                Error::CannotLogin(
-                    "the login response didn't contain the expiry of the token".to_string(),
-                )
-            })?;
+        Ok((source, query))
+    }
 
-        let valid_until = valid_until.parse::<i64>().map_err(|e| {
-            Error::CannotLogin(format!(
-                "could not parse the token expiry as a number: {}",
-                e
-            ))
-        })?;
-
-        let bearer_header = "Bearer ".to_string()
-            + login_json
-                .get("access_token")
-                .ok_or_else(|| {
-                    Error::CannotLogin("access_token not present in the login response".to_string())
-                })?
-                .as_str()
-                .ok_or_else(|| {
-                    Error::CannotLogin(
-                        "access_token is not a string in the login response".to_string(),
-                    )
-                })?;
+    /// Lists the data groups and datasets available in the FINRA API, so that they can be
+    /// presented to the users or checked before querying them using [`Finra::dataset`].
+    pub async fn catalog(&self) -> Result<Vec<DatasetInfo>> {
+        let url = self.config.api_url(endpoints::CATALOG_PATH);
+        self.get_json(&self.authenticator, url).await
+    }
 
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::AUTHORIZATION,
-            HeaderValue::from_str(&bearer_header)?,
-        );
+    /// Describes the fields of the dataset `name` in the data `group`, as documented by FINRA.
+    /// This can be used to check the fields used in a query before running it.
+    pub async fn field_metadata(&self, group: &str, name: &str) -> Result<Vec<FieldMetadata>> {
+        let url = self.config.api_url(&format!(
+            "{}/{}/name/{}",
+            endpoints::METADATA_PATH,
+            group,
+            name
+        ));
+        let authenticator = self.authenticator_for(group, name).current();
+        let metadata: DatasetMetadata = self.get_json(&authenticator, url).await?;
+        Ok(metadata.fields)
+    }
 
-        let client = (login_data.client_builder)()
-            .default_headers(headers)
-            .build()?;
+    /// Gets the JSON document at the `url` outside of the data API, like the metadata of the
+    /// datasets, authorized by the `authenticator`.
+    pub(crate) async fn get_json<T: DeserializeOwned>(
+        &self,
+        authenticator: &Authenticator,
+        url: String,
+    ) -> Result<T> {
+        let authorized = authenticator.authorized().await?;
+        let _permit = self.config.rate_limiter.acquire().await;
+        Metrics::add(&self.config.metrics.requests, 1);
+        let request = authorized
+            .client
+            .get(url)
+            .header(header::ACCEPT, "application/json");
+        let response = self.config.middlewares.request(request).send().await?;
+        self.config.middlewares.response(&response);
+
+        Ok(error::error_for_status(response).await?.json().await?)
+    }
+
+    /// Returns the OAuth2 token used to authorize the requests to FINRA, authenticating first if
+    /// there is no valid token yet. This can be used to call the FINRA API outside of this crate.
+    pub async fn token(&self) -> Result<Token> {
+        Ok(self.authenticator.authorized().await?.token)
+    }
+
+    /// Returns the `reqwest` client with the Authorization header set to the current token,
+    /// authenticating or refreshing the token first if needed. This can be used to call the FINRA
+    /// endpoints not wrapped by this crate.
+    ///
+    /// The returned client keeps using the token it was created with, so call this again rather
+    /// than holding on to the client for longer than the token is valid.
+    pub async fn http_client(&self) -> Result<Client> {
+        Ok(self.authenticator.authorized().await?.client)
+    }
+
+    /// Returns the health of the configured base URLs of the FINRA data API, as seen by this
+    /// instance. See [`FinraBuilder::base_urls`].
+    pub fn endpoint_health(&self) -> Vec<EndpointHealth> {
+        self.config.endpoints.health()
+    }
+
+    /// How much this instance has used the FINRA API since it was created.
+    pub fn usage(&self) -> Usage {
+        self.config.metrics.usage()
+    }
+
+    /// Creates a tracker splitting the usage of this instance from now on into the windows of
+    /// the length of `window`, retaining the last `retained` of them.
+    pub fn usage_tracker(&self, window: Duration, retained: usize) -> UsageTracker {
+        UsageTracker::new(self.config.metrics.clone(), window, retained)
+    }
+
+    /// The local storage of this instance, to collect the garbage in its cache. See
+    /// [`crate::Storage`].
+    #[cfg(feature = "cache")]
+    pub fn storage(&self) -> crate::Storage {
+        crate::Storage {
+            cache: self.config.cache.clone(),
+        }
+    }
 
-        Ok((client, Duration::new(valid_until, 0)))
+    /// Registers the counters of the requests, retries, throttled requests, returned records,
+    /// downloaded bytes and obtained tokens of this instance in the prometheus `registry`. The
+    /// counters are named `finra_*_total` so only a single instance can be registered in one
+    /// registry.
+    #[cfg(feature = "prometheus")]
+    pub fn register_metrics(&self, registry: &prometheus::Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(MetricsCollector::new(
+            self.config.metrics.clone(),
+        )?))
     }
 }
 
@@ -0,0 +1,325 @@
+//! The datasets of the `fixedIncomeMarket` group, i.e. the aggregated TRACE data of the
+//! corporate bond and treasury markets.
+
+use std::ops::Range;
+
+use futures::TryStream;
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    dates::finra_date,
+    numbers,
+    query::{
+        dataset_fields, CompareFilter, DateRangeFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    Error, Finra, PaginationStrategy, Result,
+};
+
+const GROUP: &str = "fixedIncomeMarket";
+
+/// The weekly aggregates of the trading in the U.S. treasury securities.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct TreasuryWeeklyAggregates {
+    #[serde(rename = "beginningOfTheWeekDate", with = "finra_date")]
+    pub beginning_of_the_week_date: Option<Date>,
+
+    #[serde(rename = "productCategory")]
+    pub product_category: String,
+
+    #[serde(rename = "benchmark")]
+    pub benchmark: String,
+
+    #[serde(rename = "yearsToMaturity")]
+    pub years_to_maturity: String,
+
+    #[serde(
+        rename = "atsInterdealerCount",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub ats_interdealer_count: u64,
+
+    #[serde(rename = "atsInterdealerVolume")]
+    pub ats_interdealer_volume: f64,
+
+    #[serde(
+        rename = "dealerCustomerCount",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub dealer_customer_count: u64,
+
+    #[serde(rename = "dealerCustomerVolume")]
+    pub dealer_customer_volume: f64,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the treasury weekly aggregates
+    /// query results.
+    TreasuryWeeklyAggregatesField {
+        BeginningOfTheWeekDate => "beginningOfTheWeekDate",
+        ProductCategory => "productCategory",
+        Benchmark => "benchmark",
+        YearsToMaturity => "yearsToMaturity",
+        AtsInterdealerCount => "atsInterdealerCount",
+        AtsInterdealerVolume => "atsInterdealerVolume",
+        DealerCustomerCount => "dealerCustomerCount",
+        DealerCustomerVolume => "dealerCustomerVolume",
+    }
+}
+
+/// The daily market breadth of the corporate bond market, i.e. how many bonds advanced or
+/// declined, in each of the product categories.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct CorporateMarketBreadth {
+    #[serde(rename = "tradeReportDate", with = "finra_date")]
+    pub trade_report_date: Option<Date>,
+
+    #[serde(rename = "productCategory")]
+    pub product_category: String,
+
+    #[serde(
+        rename = "totalSecuritiesTradedCount",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub total_securities_traded_count: u64,
+
+    #[serde(rename = "advancesCount", deserialize_with = "numbers::deserialize")]
+    pub advances_count: u64,
+
+    #[serde(rename = "declinesCount", deserialize_with = "numbers::deserialize")]
+    pub declines_count: u64,
+
+    #[serde(rename = "unchangedCount", deserialize_with = "numbers::deserialize")]
+    pub unchanged_count: u64,
+
+    #[serde(
+        rename = "fiftyTwoWeekHighCount",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub fifty_two_week_high_count: u64,
+
+    #[serde(
+        rename = "fiftyTwoWeekLowCount",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub fifty_two_week_low_count: u64,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the corporate market breadth
+    /// query results.
+    CorporateMarketBreadthField {
+        TradeReportDate => "tradeReportDate",
+        ProductCategory => "productCategory",
+        TotalSecuritiesTradedCount => "totalSecuritiesTradedCount",
+        AdvancesCount => "advancesCount",
+        DeclinesCount => "declinesCount",
+        UnchangedCount => "unchangedCount",
+        FiftyTwoWeekHighCount => "fiftyTwoWeekHighCount",
+        FiftyTwoWeekLowCount => "fiftyTwoWeekLowCount",
+    }
+}
+
+/// The query of the treasury weekly aggregates. Use the builder methods to narrow it down.
+#[derive(Debug, Clone, Default)]
+pub struct TreasuryWeeklyAggregatesQuery {
+    inner: FixedIncomeQuery<TreasuryWeeklyAggregatesField>,
+}
+
+/// The query of the corporate market breadth. Use the builder methods to narrow it down.
+#[derive(Debug, Clone, Default)]
+pub struct CorporateMarketBreadthQuery {
+    inner: FixedIncomeQuery<CorporateMarketBreadthField>,
+}
+
+/// The common part of the queries of the fixed income datasets. They are all filtered by a date
+/// and a product category.
+#[derive(Debug, Clone)]
+struct FixedIncomeQuery<F> {
+    fields: Option<Vec<F>>,
+    date_range: Option<Range<Date>>,
+    product_category: Option<String>,
+    sort: Vec<SortField>,
+    pagination: PaginationStrategy,
+    limit: u64,
+    offset: u64,
+}
+
+impl<F> Default for FixedIncomeQuery<F> {
+    fn default() -> Self {
+        Self {
+            fields: None,
+            date_range: None,
+            product_category: None,
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+macro_rules! fixed_income_query {
+    ($query:ident, $field:ident, $date_field:ident, $category_field:ident) => {
+        impl $query {
+            /// Creates a query of the full history of all the fields.
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Limits the fields included in the results.
+            pub fn fields(mut self, fields: Vec<$field>) -> Self {
+                self.inner.fields = Some(fields);
+                self
+            }
+
+            /// Limits the results to the provided date range.
+            pub fn date_range(mut self, date_range: Range<Date>) -> Self {
+                self.inner.date_range = Some(date_range);
+                self
+            }
+
+            /// Limits the results to a single product category.
+            pub fn product_category(mut self, product_category: impl Into<String>) -> Self {
+                self.inner.product_category = Some(product_category.into());
+                self
+            }
+
+            /// Orders the results by the field. When called multiple times, the results are
+            /// ordered by the fields in the order of the calls.
+            pub fn sort(mut self, sort: SortField) -> Self {
+                self.inner.sort.push(sort);
+                self
+            }
+
+            /// Sets how the results are fetched from FINRA.
+            pub fn pagination(mut self, pagination: PaginationStrategy) -> Self {
+                self.inner.pagination = pagination;
+                self
+            }
+        }
+
+        impl Query for $query {
+            fn limit(&self) -> u64 {
+                self.inner.limit
+            }
+
+            fn offset(&self) -> u64 {
+                self.inner.offset
+            }
+
+            fn move_cursor(mut self, by: u64) -> Self {
+                self.inner.offset += by;
+                self
+            }
+
+            fn with_limit(mut self, limit: u64) -> Self {
+                self.inner.limit = limit;
+                self
+            }
+
+            fn default_date_range(&mut self, date_range: Range<Date>) {
+                if self.inner.date_range.is_none() {
+                    self.inner.date_range = Some(date_range);
+                }
+            }
+
+            fn known_fields(&self) -> &'static [&'static str] {
+                $field::NAMES
+            }
+
+            fn requested_fields(&self) -> Vec<&'static str> {
+                $field::names_of(self.inner.fields.as_deref())
+            }
+
+            fn symbols_mut(&mut self) -> Vec<&mut String> {
+                vec![]
+            }
+
+            fn date_range(&self) -> Option<&Range<Date>> {
+                self.inner.date_range.as_ref()
+            }
+
+            fn set_date_range(&mut self, date_range: Range<Date>) {
+                self.inner.date_range = Some(date_range);
+            }
+
+            fn pagination(&self) -> PaginationStrategy {
+                self.inner.pagination
+            }
+        }
+
+        impl Serialize for $query {
+            fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                QueryBody {
+                    fields: self.inner.fields.as_deref(),
+                    date_range_filters: self
+                        .inner
+                        .date_range
+                        .iter()
+                        .map(|r| DateRangeFilter::new($field::$date_field.as_str(), r))
+                        .collect(),
+                    compare_filters: self
+                        .inner
+                        .product_category
+                        .iter()
+                        .map(|c| CompareFilter::equal($field::$category_field, c.as_str()))
+                        .collect(),
+                    domain_filters: Vec::new(),
+                    sort_fields: &self.inner.sort,
+                    limit: self.inner.limit,
+                    offset: self.inner.offset,
+                }
+                .serialize(serializer)
+            }
+        }
+    };
+}
+
+fixed_income_query!(
+    TreasuryWeeklyAggregatesQuery,
+    TreasuryWeeklyAggregatesField,
+    BeginningOfTheWeekDate,
+    ProductCategory
+);
+
+fixed_income_query!(
+    CorporateMarketBreadthQuery,
+    CorporateMarketBreadthField,
+    TradeReportDate,
+    ProductCategory
+);
+
+impl Finra {
+    /// Queries the weekly aggregates of the treasury market from finra.org.
+    pub async fn treasury_weekly_aggregates(
+        &self,
+        query: TreasuryWeeklyAggregatesQuery,
+    ) -> Result<impl TryStream<Ok = TreasuryWeeklyAggregates, Error = Error>> {
+        self.fetch(GROUP, "treasuryWeeklyAggregates", query).await
+    }
+
+    /// Queries the daily market breadth of the corporate bond market from finra.org.
+    pub async fn corporate_market_breadth(
+        &self,
+        query: CorporateMarketBreadthQuery,
+    ) -> Result<impl TryStream<Ok = CorporateMarketBreadth, Error = Error>> {
+        self.fetch(GROUP, "corporateMarketBreadth", query).await
+    }
+}
@@ -1,16 +1,194 @@
 //! This is a simple wrapper around the FINRA REST API.
 //!
-//! Almost no features are currently implemented, only fetching the consolidated short interest.
+//! Only a few datasets are currently supported - the consolidated and equity short interest, the
+//! Reg SHO daily short sale volume, the Reg SHO threshold list, the OTC transparency weekly and
+//! monthly summaries, the OTC blocks summary, the treasury weekly aggregates and the corporate
+//! market breadth of the [`fixed_income`] module and the registered firms of the [`registration`]
+//! module.
 //!
 //! The basic filtering and limiting of the returned data is implemented though.
 //!
+//! The commonly used types can be imported at once using `use finra_rs::prelude::*;`.
+//!
+//! The streams of the records can be dropped at any time, e.g. on shutdown. This aborts the
+//! request in flight, including a login, without affecting the other users of the same [`Finra`]
+//! instance. To stop a stream on a signal, use `futures::StreamExt::take_until`.
+//!
+//! The library doesn't depend on a particular async executor. The waits for the retries, the rate
+//! limits and the timeouts use `futures-timer`, so they work on async-std or smol too. Note that
+//! the HTTP requests are made by `reqwest`, whose connections need the tokio reactor, though. On
+//! the other executors, run the futures and the streams of the library within the
+//! `async_compat::Compat` adapter, which provides it.
+//!
 //! The `tokio` feature makes the library use the tokio-specific replacements of the standard
-//! library's synchronization primitives but has no other functional differences.
+//! library's synchronization primitives and the tokio timers, which require the tokio runtime,
+//! but has no other functional differences.
+//!
+//! The `serialize` feature implements `serde::Serialize` for the returned records and enables the
+//! [`ExportTransforms`] of the fields of the exported records, e.g. to hash the symbols or drop
+//! the columns of an extract shared outside of the organization.
+//!
+//! The [`sink`] module writes the streams of the records into CSV files with the `serialize`
+//! feature and into Parquet files with the `parquet` feature. The `arrow` feature converts the
+//! streams of the records into Arrow record batches using [`record_batches`], and the `polars`
+//! feature further into `polars` data frames using [`data_frames`].
+//!
+//! Besides the market data, the [`registration`] module queries the registration data of the
+//! firms, e.g. to look them up by their CRD numbers.
+//!
+//! The `plotters` feature adds helpers for drawing the data into `plotters` charts.
+//!
+//! The `prometheus` feature makes it possible to register the counters of the requests, retries
+//! and other activity of [`Finra`] in a `prometheus` registry using [`Finra::register_metrics`].
+//!
+//! Every request to FINRA is made in its own `tracing` span. With the `opentelemetry` feature, the
+//! OpenTelemetry context of these spans is also propagated to FINRA in the request headers (e.g.
+//! `traceparent`), using the globally configured propagator.
+//!
+//! The `indicatif` feature makes it possible to show the progress of the queries in an
+//! `indicatif` progress bar using [`FinraBuilder::progress_bar`].
+//!
+//! The `cache` feature makes it possible to store the pages of the query results, e.g. on the
+//! disk using [`FileCache`], so that the repeated queries don't download them again. See
+//! [`FinraBuilder::cache`] and [`Finra::storage`]. The `cache-gzip` and `cache-zstd` features add
+//! the gzip and zstd [`CacheCompression`] of the pages on the disk.
+//!
+//! The `blocking` feature enables the [`blocking`] module with a synchronous API for the code that
+//! is not async.
+//!
+//! The `cli` feature builds the `finra` binary, which streams the records of a dataset into a CSV,
+//! newline-delimited JSON, aligned text table or, with the `parquet` feature, Parquet file or the
+//! standard output. Its `datasets list` and `datasets describe` commands print the
+//! [`Finra::catalog`] and the [`Finra::field_metadata`] of a dataset. Run it without arguments for
+//! the usage.
+//!
+//! The `experimental-async-requests` feature enables the asynchronous requests of the large
+//! extracts using [`Finra::submit_async_query`] and [`PaginationStrategy::AsyncDownload`]. Their
+//! endpoints have not been verified against the live FINRA API, so they can change or go away in
+//! any release.
+//!
+//! The `no-mock-datasets` feature removes the support for querying the mock datasets, so that a
+//! production build cannot query them by accident.
+//!
+//! The `test-util` feature enables the [`testing`] module with the tools for testing the code
+//! consuming the paged results without FINRA, including a local mock server of the FINRA API. It
+//! requires the tokio runtime.
+
+#[doc(hidden)]
+pub use serde as __serde;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod fixed_income;
+pub mod prelude;
+pub mod registration;
+#[cfg(any(feature = "serialize", feature = "parquet"))]
+pub mod sink;
+#[cfg(feature = "test-util")]
+pub mod testing;
 
+mod adapters;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "experimental-async-requests")]
+mod async_query;
+mod auth;
+mod blocks_summary;
+mod builder;
+mod bulk;
+mod bulk_file;
+#[cfg(feature = "cache")]
+mod cache;
+mod catalog;
+mod changes;
+mod columnar;
+mod daily_short_sale_volume;
+#[cfg(feature = "polars")]
+mod data_frame;
+mod dataset;
+mod dates;
+mod decode;
+mod endpoints;
+mod equity_short_interest;
 mod error;
+#[cfg(feature = "serialize")]
+mod export;
+#[cfg(any(feature = "cache", feature = "serialize", feature = "parquet"))]
+mod files;
 mod finra;
+mod market_class;
+mod metrics;
+mod middleware;
+mod monthly_summary;
+mod numbers;
 mod pager;
+mod pagination;
+#[cfg(feature = "plotters")]
+mod plotting;
+mod progress;
+mod projection;
 mod query;
+mod rate_limit;
+mod retry;
+mod router;
+mod rt;
+mod snapshot;
+mod summary;
+mod symbol;
+mod sync;
+mod telemetry;
+mod threshold_list;
+mod usage;
+mod warning;
+mod weekly_summary;
+pub use adapters::*;
+#[cfg(feature = "arrow")]
+pub use arrow::{record_batches, ArrowRecord};
+#[cfg(feature = "experimental-async-requests")]
+pub use async_query::{AsyncRequest, AsyncRequestStatus};
+pub use auth::*;
+pub use blocks_summary::*;
+pub use builder::*;
+pub use bulk::{BulkResult, ErrorTolerance};
+pub use bulk_file::*;
+#[cfg(feature = "cache")]
+pub use cache::{CacheBackend, CacheCompression, CachedPage, FileCache, Storage};
+pub use catalog::{DatasetInfo, FieldMetadata};
+pub use changes::{
+    short_interest_changes, short_interest_changes_with, CorporateActions, ShortInterestChange,
+    SplitHandling, StockSplits,
+};
+pub use columnar::{ShortInterestColumns, StringColumn};
+pub use daily_short_sale_volume::*;
+#[cfg(feature = "polars")]
+pub use data_frame::{collect_data_frame, data_frames, to_data_frame};
+pub use dataset::*;
+pub use dates::YearMonth;
+pub use decode::ResponseFormat;
+pub use endpoints::{EndpointHealth, FailoverPolicy};
+pub use equity_short_interest::*;
 pub use error::*;
+#[cfg(feature = "serialize")]
+pub use export::{ExportRow, ExportTransforms, FieldTransform};
 pub use finra::*;
+pub use market_class::{market_class_summaries, MarketClassSummary};
+pub use middleware::Middleware;
+pub use monthly_summary::*;
+pub use numbers::OverflowPolicy;
+pub use pagination::PaginationStrategy;
+#[cfg(feature = "plotters")]
+pub use plotting::*;
+pub use progress::Progress;
+pub use projection::Projection;
 pub use query::*;
+pub use rate_limit::RateLimit;
+pub use retry::*;
+pub use router::FinraRouter;
+pub use snapshot::ShortInterestSnapshot;
+pub use summary::{summarize, ColumnSummary, Summary};
+pub use symbol::*;
+pub use sync::SyncCheckpoint;
+pub use threshold_list::*;
+pub use usage::{Usage, UsageTracker, UsageWindow};
+pub use warning::Warning;
+pub use weekly_summary::*;
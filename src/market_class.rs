@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use futures::{stream, StreamExt, TryStream, TryStreamExt};
+use time::Date;
+
+use crate::{group_by_date, ConsolidatedShortInterest};
+
+/// The totals of the short interest of a market class on a settlement date, like in the summary
+/// tables published by FINRA, as computed by [`market_class_summaries`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketClassSummary {
+    pub settlement_date: Date,
+    pub market_class: String,
+    /// The number of the issues of the market class, i.e. of the records.
+    pub issues: u64,
+    /// The sum of the short positions of the issues.
+    pub short_interest: u64,
+    /// The mean of the days to cover of the issues.
+    pub average_days_to_cover: f64,
+}
+
+/// Aggregates the stream of the consolidated short interest into the totals of each market class
+/// on each settlement date, yielding the totals of a settlement date, ordered by the market class,
+/// once all its records are read.
+///
+/// The records are expected to be ordered by the settlement date, as in
+/// [`crate::short_interest_changes`]. The records without the settlement date are skipped.
+pub fn market_class_summaries<S>(
+    stream: S,
+) -> impl TryStream<Ok = MarketClassSummary, Error = S::Error>
+where
+    S: TryStream<Ok = ConsolidatedShortInterest>,
+{
+    group_by_date(stream)
+        .map_ok(|(date, records)| stream::iter(period_summaries(date, records)).map(Ok))
+        .try_flatten()
+}
+
+fn period_summaries(
+    date: Date,
+    records: Vec<ConsolidatedShortInterest>,
+) -> Vec<MarketClassSummary> {
+    // the number of the issues, the short interest and the sum of the days to cover
+    let mut classes: BTreeMap<String, (u64, u64, f64)> = BTreeMap::new();
+    for r in records {
+        let (issues, short_interest, days_to_cover) =
+            classes.entry(r.market_class_code).or_default();
+        *issues += 1;
+        *short_interest += r.current_short_position_quantity;
+        *days_to_cover += r.days_to_cover_quantity;
+    }
+
+    classes
+        .into_iter()
+        .map(
+            |(market_class, (issues, short_interest, days_to_cover))| MarketClassSummary {
+                settlement_date: date,
+                market_class,
+                issues,
+                short_interest,
+                average_days_to_cover: days_to_cover / issues as f64,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn totals_per_market_class_and_date() {
+        let (first, second) = (date!(2024 - 01 - 15), date!(2024 - 01 - 31));
+        let record = |class: &str, date, quantity, days_to_cover| {
+            Ok::<_, ()>(ConsolidatedShortInterest {
+                market_class_code: class.to_string(),
+                settlement_date: Some(date),
+                current_short_position_quantity: quantity,
+                days_to_cover_quantity: days_to_cover,
+                ..Default::default()
+            })
+        };
+        let records = [
+            record("NYSE", first, 100, 1.0),
+            record("NNM", first, 50, 2.0),
+            record("NYSE", first, 300, 2.0),
+            record("NYSE", second, 10, 4.0),
+        ];
+
+        let summaries: Vec<_> = market_class_summaries(stream::iter(records))
+            .map_ok(|s| {
+                (
+                    s.settlement_date,
+                    s.market_class,
+                    s.issues,
+                    s.short_interest,
+                    s.average_days_to_cover,
+                )
+            })
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                (first, "NNM".to_string(), 1, 50, 2.0),
+                (first, "NYSE".to_string(), 2, 400, 1.5),
+                (second, "NYSE".to_string(), 1, 10, 4.0),
+            ],
+            summaries
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Usage;
+
+/// The counters of what the crate has been doing. These are always kept and can be read using
+/// [`crate::Finra::usage`] or, with the `prometheus` feature, as prometheus counters.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    /// The number of the HTTP requests sent to FINRA, including the login attempts.
+    pub(crate) requests: AtomicU64,
+    /// The number of the requests that were retried after a transient failure.
+    pub(crate) retries: AtomicU64,
+    /// The number of the requests rejected by FINRA because the quota has been exceeded.
+    pub(crate) throttled: AtomicU64,
+    /// The number of the records returned by the streams.
+    pub(crate) records: AtomicU64,
+    /// The number of the bytes of the pages of the results.
+    pub(crate) bytes: AtomicU64,
+    /// The number of the successfully obtained OAuth2 tokens.
+    pub(crate) token_refreshes: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub(crate) fn usage(&self) -> Usage {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        Usage {
+            requests: get(&self.requests),
+            retries: get(&self.retries),
+            throttled: get(&self.throttled),
+            records: get(&self.records),
+            bytes: get(&self.bytes),
+            token_refreshes: get(&self.token_refreshes),
+        }
+    }
+
+    #[cfg(feature = "prometheus")]
+    fn counters(&self) -> [(&'static str, &'static str, u64); 6] {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        [
+            (
+                "finra_requests_total",
+                "The number of the HTTP requests sent to FINRA.",
+                get(&self.requests),
+            ),
+            (
+                "finra_retries_total",
+                "The number of the retried requests.",
+                get(&self.retries),
+            ),
+            (
+                "finra_throttled_requests_total",
+                "The number of the requests rejected because of an exceeded quota.",
+                get(&self.throttled),
+            ),
+            (
+                "finra_records_total",
+                "The number of the records returned from FINRA.",
+                get(&self.records),
+            ),
+            (
+                "finra_bytes_total",
+                "The number of the bytes of the pages returned from FINRA.",
+                get(&self.bytes),
+            ),
+            (
+                "finra_token_refreshes_total",
+                "The number of the obtained OAuth2 tokens.",
+                get(&self.token_refreshes),
+            ),
+        ]
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub(crate) use collector::MetricsCollector;
+
+#[cfg(feature = "prometheus")]
+mod collector {
+    use std::sync::Arc;
+
+    use prometheus::{
+        core::{Collector, Desc, Describer},
+        proto::MetricFamily,
+        IntCounter, Opts,
+    };
+
+    use super::Metrics;
+
+    /// Exposes the [`Metrics`] as prometheus counters.
+    pub(crate) struct MetricsCollector {
+        metrics: Arc<Metrics>,
+        descs: Vec<Desc>,
+    }
+
+    impl MetricsCollector {
+        pub(crate) fn new(metrics: Arc<Metrics>) -> prometheus::Result<Self> {
+            let descs = metrics
+                .counters()
+                .iter()
+                .map(|(name, help, _)| Opts::new(*name, *help).describe())
+                .collect::<prometheus::Result<_>>()?;
+
+            Ok(Self { metrics, descs })
+        }
+    }
+
+    impl Collector for MetricsCollector {
+        fn desc(&self) -> Vec<&Desc> {
+            self.descs.iter().collect()
+        }
+
+        fn collect(&self) -> Vec<MetricFamily> {
+            self.metrics
+                .counters()
+                .iter()
+                .filter_map(|(name, help, value)| {
+                    let counter = IntCounter::with_opts(Opts::new(*name, *help)).ok()?;
+                    counter.inc_by(*value);
+                    Some(counter.collect())
+                })
+                .flatten()
+                .collect()
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use reqwest::{RequestBuilder, Response};
+
+/// Customizes the requests sent to FINRA, e.g. adds custom headers, signs the requests or records
+/// them for auditing. It sees the requests for the pages of the datasets and all the other
+/// requests to the FINRA API, but not the logins.
+///
+/// Any `Fn(RequestBuilder) -> RequestBuilder` closure is a middleware that only customizes the
+/// requests. Use [`crate::FinraBuilder::middleware`] to set it up.
+pub trait Middleware: Send + Sync {
+    /// Called with each request before it is sent. Returns the request to send instead.
+    fn on_request(&self, request: RequestBuilder) -> RequestBuilder;
+
+    /// Called with each response received from FINRA, before it is read.
+    fn on_response(&self, _response: &Response) {}
+}
+
+impl<F: Fn(RequestBuilder) -> RequestBuilder + Send + Sync> Middleware for F {
+    fn on_request(&self, request: RequestBuilder) -> RequestBuilder {
+        self(request)
+    }
+}
+
+/// The middlewares in the order they were set up in.
+#[derive(Clone, Default)]
+pub(crate) struct Middlewares(Vec<Arc<dyn Middleware>>);
+
+impl Middlewares {
+    pub(crate) fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    /// Passes the `request` through all the middlewares.
+    pub(crate) fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        self.0.iter().fold(request, |r, m| m.on_request(r))
+    }
+
+    /// Shows the `response` to all the middlewares.
+    pub(crate) fn response(&self, response: &Response) {
+        for middleware in &self.0 {
+            middleware.on_response(response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Client;
+
+    use super::*;
+
+    #[test]
+    fn requests_pass_through_all_middlewares() {
+        let mut middlewares = Middlewares::default();
+        middlewares.push(Arc::new(|r: RequestBuilder| r.header("x-first", "1")));
+        middlewares.push(Arc::new(|r: RequestBuilder| r.header("x-second", "2")));
+
+        let request = middlewares
+            .request(Client::new().get("http://localhost/"))
+            .build()
+            .unwrap();
+
+        assert_eq!("1", request.headers()["x-first"]);
+        assert_eq!("2", request.headers()["x-second"]);
+    }
+}
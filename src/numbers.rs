@@ -0,0 +1,246 @@
+use std::{cell::Cell, fmt, marker::PhantomData};
+
+use serde::{de, Deserialize, Deserializer};
+
+/// What to do with a quantity in the results that doesn't fit into the numeric type of its field,
+/// e.g. a negative number in an unsigned field.
+///
+/// Use [`crate::FinraBuilder::overflow_policy`] to set it up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Fail the query with [`crate::Error::NumericOverflow`].
+    #[default]
+    Error,
+    /// Use the closest value the field can hold, i.e. its minimum or maximum.
+    Saturate,
+}
+
+/// The overflow handling of a [`with_policy`] call.
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    policy: OverflowPolicy,
+    /// Set when the deserialization failed due to an overflow.
+    overflowed: bool,
+}
+
+thread_local! {
+    /// The context of the innermost [`with_policy`] running on this thread, if any. The
+    /// deserialization outside of it fails on the overflows and doesn't record them.
+    static CONTEXT: Cell<Option<Context>> = const { Cell::new(None) };
+}
+
+/// Runs the deserialization `f` with the overflow `policy`. Also returns whether `f` encountered
+/// an overflowing quantity it failed on.
+pub(crate) fn with_policy<T>(policy: OverflowPolicy, f: impl FnOnce() -> T) -> (T, bool) {
+    /// Restores the context of the enclosing call, even if `f` panics.
+    struct Restore(Option<Context>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CONTEXT.with(|c| c.set(self.0));
+        }
+    }
+
+    let context = Context {
+        policy,
+        overflowed: false,
+    };
+    let _restore = Restore(CONTEXT.with(|c| c.replace(Some(context))));
+    let result = f();
+    let overflowed = CONTEXT.with(Cell::get).is_some_and(|c| c.overflowed);
+    (result, overflowed)
+}
+
+/// The integer types of the quantities.
+pub(crate) trait Quantity: Copy + TryFrom<i128> {
+    const MIN: Self;
+    const MAX: Self;
+}
+
+impl Quantity for u64 {
+    const MIN: Self = u64::MIN;
+    const MAX: Self = u64::MAX;
+}
+
+impl Quantity for i64 {
+    const MIN: Self = i64::MIN;
+    const MAX: Self = i64::MAX;
+}
+
+/// The quantity deserialized from a number or a string holding a number.
+struct Checked<T>(T);
+
+impl<'de, T: Quantity> Deserialize<'de> for Checked<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CheckedVisitor(PhantomData))
+    }
+}
+
+struct CheckedVisitor<T>(PhantomData<T>);
+
+impl<T: Quantity> CheckedVisitor<T> {
+    fn convert<E: de::Error>(value: i128) -> Result<Checked<T>, E> {
+        if let Ok(v) = T::try_from(value) {
+            return Ok(Checked(v));
+        }
+
+        let context = CONTEXT.with(Cell::get);
+        match context.map_or(OverflowPolicy::Error, |c| c.policy) {
+            OverflowPolicy::Saturate => Ok(Checked(if value < 0 { T::MIN } else { T::MAX })),
+            OverflowPolicy::Error => {
+                let overflowed = context.map(|c| Context {
+                    overflowed: true,
+                    ..c
+                });
+                CONTEXT.with(|c| c.set(overflowed));
+                Err(E::custom(format!(
+                    "the quantity {} is out of the range of {}",
+                    value,
+                    std::any::type_name::<T>()
+                )))
+            }
+        }
+    }
+}
+
+impl<T: Quantity> de::Visitor<'_> for CheckedVisitor<T> {
+    type Value = Checked<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Self::convert(v.into())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Self::convert(v.into())
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Self::Value, E> {
+        Self::convert(v)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Self::Value, E> {
+        Self::convert(i128::try_from(v).unwrap_or(i128::MAX))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        // the integers too large for the integer types end up here
+        if v.fract() != 0.0 || v.is_nan() {
+            return Err(E::invalid_value(de::Unexpected::Float(v), &self));
+        }
+        Self::convert(v as i128)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let v = v.trim();
+        match v.parse::<i128>() {
+            Ok(n) => Self::convert(n),
+            Err(_) => {
+                let digits = v.strip_prefix(['-', '+']).unwrap_or(v);
+                if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                    Self::convert(if v.starts_with('-') {
+                        i128::MIN
+                    } else {
+                        i128::MAX
+                    })
+                } else {
+                    Err(E::invalid_value(de::Unexpected::Str(v), &self))
+                }
+            }
+        }
+    }
+}
+
+/// Deserializes the quantity using the [`OverflowPolicy`] of the query.
+pub(crate) fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Quantity,
+{
+    Checked::deserialize(deserializer).map(|c| c.0)
+}
+
+/// Deserializes the optional quantity using the [`OverflowPolicy`] of the query.
+pub(crate) fn deserialize_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Quantity,
+{
+    Option::<Checked<T>>::deserialize(deserializer).map(|c| c.map(|c| c.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Row {
+        #[serde(deserialize_with = "deserialize")]
+        quantity: u64,
+    }
+
+    fn parse(csv: &str, policy: OverflowPolicy) -> (Option<u64>, bool) {
+        let (row, overflow) = with_policy(policy, || {
+            csv::Reader::from_reader(csv.as_bytes())
+                .deserialize::<Row>()
+                .next()
+                .unwrap()
+        });
+        (row.ok().map(|r| r.quantity), overflow)
+    }
+
+    #[test]
+    fn overflow_checked_or_saturated() {
+        let huge = "quantity\n123456789012345678901234567890\n";
+        assert_eq!(
+            parse("quantity\n42\n", OverflowPolicy::Error),
+            (Some(42), false)
+        );
+        assert_eq!(parse(huge, OverflowPolicy::Error), (None, true));
+        assert_eq!(parse("quantity\n-1\n", OverflowPolicy::Error), (None, true));
+        assert_eq!(
+            parse("quantity\nabc\n", OverflowPolicy::Error),
+            (None, false)
+        );
+        assert_eq!(
+            parse(huge, OverflowPolicy::Saturate),
+            (Some(u64::MAX), false)
+        );
+        assert_eq!(
+            parse("quantity\n-1\n", OverflowPolicy::Saturate),
+            (Some(0), false)
+        );
+    }
+
+    #[test]
+    fn overflow_scoped_to_policy() {
+        let huge = "quantity\n123456789012345678901234567890\n";
+        let ((inner, outer), overflow) = with_policy(OverflowPolicy::Error, || {
+            let outer = parse(huge, OverflowPolicy::Error);
+            (parse(huge, OverflowPolicy::Saturate), outer)
+        });
+        assert_eq!((Some(u64::MAX), false), inner);
+        assert_eq!((None, true), outer);
+        assert!(!overflow);
+
+        // outside of any policy, the overflows fail and are not recorded
+        let row = csv::Reader::from_reader(huge.as_bytes())
+            .deserialize::<Row>()
+            .next()
+            .unwrap();
+        assert!(row.is_err());
+        assert_eq!(None, CONTEXT.with(Cell::get).map(|c| c.overflowed));
+
+        let panicked =
+            std::panic::catch_unwind(|| with_policy(OverflowPolicy::Saturate, || panic!("failed")));
+        assert!(panicked.is_err());
+        assert_eq!(parse(huge, OverflowPolicy::Error), (None, true));
+        assert!(CONTEXT.with(Cell::get).is_none());
+    }
+}
@@ -1,82 +1,906 @@
-use std::io::BufReader;
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{error::Result, Error, Query};
-use futures::{stream, TryStream};
-use reqwest::{header, Client, IntoUrl, StatusCode, Url};
+use crate::{
+    auth::{self, DatasetAuthenticator},
+    decode::{Decoder, ResponseFormat},
+    endpoints::Endpoints,
+    error::Result,
+    metrics::Metrics,
+    middleware::Middlewares,
+    numbers::{self, OverflowPolicy},
+    progress::ProgressSink,
+    rate_limit::Permit,
+    rate_limit::RateLimiter,
+    retry::{self, Failure},
+    rt, telemetry,
+    warning::Warnings,
+    Error, Progress, Query, RetryBudget, RetryPolicy, Warning,
+};
+#[cfg(feature = "cache")]
+use crate::{CacheBackend, CachedPage};
+use futures::{
+    future::{self, Either},
+    stream, Stream, StreamExt, TryStream, TryStreamExt,
+};
+use reqwest::{
+    header::{self, HeaderMap},
+    Client, Method, Response, StatusCode,
+};
 use serde::de::DeserializeOwned;
+use tracing::Instrument;
 
-struct PagerState<Q: Query> {
-    client: Client,
-    url: Url,
+/// A single page of the results as returned by FINRA.
+pub(crate) struct Page {
+    /// The value of the `Record-Total` header, if FINRA sent it.
+    pub(crate) total: Option<u64>,
+    /// The records on the page in the `format`.
+    pub(crate) body: Body,
+    pub(crate) format: ResponseFormat,
+}
+
+/// The body of a page, read chunk by chunk as it arrives.
+pub(crate) enum Body {
+    /// The response from FINRA. The permit of the rate limiter is held until the body is read,
+    /// which must happen before the `deadline`, if any.
+    Http {
+        response: Response,
+        deadline: Option<Instant>,
+        _permit: Permit,
+    },
+    #[cfg(feature = "test-util")]
+    Text(Option<String>),
+    /// The body read from the cache or already stored in it.
+    #[cfg(feature = "cache")]
+    Cached(Option<Vec<u8>>),
+}
+
+impl Body {
+    /// Reads the next chunk of the body. Returns `None` at the end of the body.
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::Http {
+                response,
+                deadline: None,
+                ..
+            } => Ok(response.chunk().await?.map(|c| c.to_vec())),
+            Self::Http {
+                response,
+                deadline: Some(deadline),
+                ..
+            } => {
+                let url = response.url().to_string();
+                let left = deadline.saturating_duration_since(Instant::now());
+                match rt::timeout(left, response.chunk()).await {
+                    Some(chunk) => Ok(chunk?.map(|c| c.to_vec())),
+                    None => Err(request_timeout(&url)),
+                }
+            }
+            #[cfg(feature = "test-util")]
+            Self::Text(text) => Ok(text.take().map(String::into_bytes)),
+            #[cfg(feature = "cache")]
+            Self::Cached(body) => Ok(body.take()),
+        }
+    }
+}
+
+/// Where the pages of the results come from. This is FINRA in the real world, but can be an
+/// in-memory sequence of pages in the tests.
+pub(crate) trait PageSource {
+    /// Identifies the source in the warnings, usually the URL of the dataset.
+    fn name(&self) -> String;
+
+    /// Fetches the page of the results selected by the limit and offset of the `query`. Returns
+    /// `None` if there is no content.
+    fn fetch<Q: Query>(&self, query: &Q) -> impl Future<Output = Result<Option<Page>>> + Send;
+
+    /// Decides whether to fetch the page again after its reading was interrupted by the `error` on
+    /// the `attempt`, counted from 1. Returns the delay before fetching it again, or the error to
+    /// fail with. By default, the pages are not fetched again.
+    fn retry(&self, attempt: u32, error: Error) -> Result<Duration> {
+        let _ = attempt;
+        Err(error)
+    }
+}
+
+/// The pages of the results of a FINRA dataset at the `path` relative to the base URL of the
+/// data API. The authorized client and the base URL are chosen for each page, so that the token
+/// can be refreshed and the base URL can fail over between the pages of long streams.
+#[derive(Clone)]
+pub(crate) struct HttpPages {
+    authenticator: DatasetAuthenticator,
+    endpoints: Arc<Endpoints>,
+    path: String,
+    /// The method and the URL requested instead of posting the queries to the `path`, if any.
+    url: Option<(Method, String)>,
+    metrics: Arc<Metrics>,
+    retry_policy: RetryPolicy,
+    rate_limiter: Arc<RateLimiter>,
+    format: ResponseFormat,
+    middlewares: Middlewares,
+    timeout: Option<Duration>,
+    retry_budget: Option<RetryBudget>,
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<dyn CacheBackend>>,
+}
+
+impl HttpPages {
+    pub(crate) fn new(
+        authenticator: DatasetAuthenticator,
+        endpoints: Arc<Endpoints>,
+        path: String,
+        metrics: Arc<Metrics>,
+        retry_policy: RetryPolicy,
+        rate_limiter: Arc<RateLimiter>,
+        format: ResponseFormat,
+    ) -> Self {
+        Self {
+            authenticator,
+            endpoints,
+            path,
+            url: None,
+            metrics,
+            retry_policy,
+            rate_limiter,
+            format,
+            middlewares: Middlewares::default(),
+            timeout: None,
+            retry_budget: None,
+            #[cfg(feature = "cache")]
+            cache: None,
+        }
+    }
+
+    /// Fails the page requests that don't finish within the `timeout`, including the reading of
+    /// the page.
+    pub(crate) fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Passes the page requests through the `middlewares`.
+    pub(crate) fn with_middlewares(self, middlewares: Middlewares) -> Self {
+        Self {
+            middlewares,
+            ..self
+        }
+    }
+
+    /// Requests the `url` outside of the data API using the `method` instead of posting the
+    /// queries to the path of the dataset, e.g. to poll the asynchronous requests. The base URLs of
+    /// the data API don't fail over for it.
+    #[cfg(feature = "experimental-async-requests")]
+    pub(crate) fn with_url(self, method: Method, url: String) -> Self {
+        Self {
+            url: Some((method, url)),
+            ..self
+        }
+    }
+
+    /// Retries the failed requests according to the `retry_policy` instead of the configured one.
+    #[cfg(feature = "experimental-async-requests")]
+    pub(crate) fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Asks for the pages in the `format` instead of the configured one.
+    #[cfg(feature = "experimental-async-requests")]
+    pub(crate) fn with_format(self, format: ResponseFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    pub(crate) fn with_retry_budget(self, retry_budget: Option<RetryBudget>) -> Self {
+        Self {
+            retry_budget,
+            ..self
+        }
+    }
+
+    /// Reads the pages from the `cache` if present there and stores the fetched pages in it.
+    #[cfg(feature = "cache")]
+    pub(crate) fn with_cache(self, cache: Option<Arc<dyn CacheBackend>>) -> Self {
+        Self { cache, ..self }
+    }
+
+    /// The key of the page of the serialized query in the `body` in the cache.
+    #[cfg(feature = "cache")]
+    fn cache_key(&self, body: &[u8]) -> String {
+        format!(
+            "{} {} {}",
+            self.path,
+            self.format.media_type(),
+            String::from_utf8_lossy(body)
+        )
+    }
+
+    /// The page of the serialized query in the `body`, if it is in the cache.
+    #[cfg(feature = "cache")]
+    fn cached(&self, body: &[u8]) -> Option<Page> {
+        let page = self.cache.as_ref()?.get(&self.cache_key(body))?;
+        tracing::debug!(path = %self.path, "page read from the cache");
+        Some(Page {
+            total: page.total,
+            body: Body::Cached(Some(page.body)),
+            format: self.format,
+        })
+    }
+
+    /// Reads the whole fetched `page` and stores it in the cache, if there is one.
+    #[cfg(feature = "cache")]
+    async fn store(&self, body: &[u8], page: Option<Page>) -> Result<Option<Page>> {
+        let Some(cache) = &self.cache else {
+            return Ok(page);
+        };
+        let Some(mut page) = page else {
+            return Ok(None);
+        };
+
+        let mut content = Vec::new();
+        while let Some(chunk) = page.body.chunk().await? {
+            content.extend_from_slice(&chunk);
+        }
+        let cached = CachedPage {
+            total: page.total,
+            body: content,
+        };
+        cache.put(&self.cache_key(body), &cached);
+        Ok(Some(Page {
+            total: cached.total,
+            body: Body::Cached(Some(cached.body)),
+            format: page.format,
+        }))
+    }
+
+    /// Makes a single attempt to fetch the page using the serialized query in the `body`.
+    async fn try_fetch(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> std::result::Result<Option<Page>, Failure> {
+        let permit = self.rate_limiter.acquire().await;
+        let (endpoint, method, url) = match &self.url {
+            Some((method, url)) => (None, method.clone(), url.clone()),
+            None => {
+                let (endpoint, url) = self.endpoints.select(&self.path);
+                (Some(endpoint), Method::POST, url)
+            }
+        };
+        let send = |client: Client| {
+            Metrics::add(&self.metrics.requests, 1);
+            let mut request = client
+                .request(method.clone(), url.clone())
+                .headers(headers.clone())
+                .header(header::ACCEPT, self.format.media_type());
+            if !body.is_empty() {
+                request = request
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(body.to_vec());
+            }
+            let request = self.middlewares.request(request).send();
+            // timed by the crate rather than by reqwest, which would need the tokio timer
+            let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+            let url = url.to_string();
+            async move {
+                let response = match deadline {
+                    Some(deadline) => {
+                        let left = deadline.saturating_duration_since(Instant::now());
+                        match rt::timeout(left, request).await {
+                            Some(response) => response,
+                            None => {
+                                let e = request_timeout(&url);
+                                return Err(Failure::Transient(e, None));
+                            }
+                        }
+                    }
+                    None => request.await,
+                };
+                if let Ok(response) = &response {
+                    self.middlewares.response(response);
+                }
+                if let Some(endpoint) = endpoint {
+                    self.endpoints.report(
+                        endpoint,
+                        matches!(&response, Ok(r) if !r.status().is_server_error()),
+                    );
+                }
+                response
+                    .map(|response| (response, deadline))
+                    .map_err(Failure::from_http_error)
+            }
+        };
+
+        let authenticator = self.authenticator.current();
+        let mut authorized = authenticator.authorized().await.map_err(Failure::Fatal)?;
+        let (mut response, mut deadline) = send(authorized.client.clone()).await?;
+        // a token rejected for the scopes it lacks is re-obtained with them below instead
+        let rejected_scopes = response.status() == StatusCode::FORBIDDEN
+            && !auth::required_scopes(response.headers()).is_empty();
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) && !rejected_scopes
+        {
+            // the token might have been revoked, let's try once more with a new one
+            if let Some(reauthorized) = authenticator
+                .reauthorized(&authorized.token)
+                .await
+                .map_err(Failure::Fatal)?
+            {
+                authorized = reauthorized;
+                (response, deadline) = send(authorized.client.clone()).await?;
+            }
+        }
+        if response.status() == StatusCode::FORBIDDEN {
+            // the dataset might need the scopes the token doesn't have, let's try once more with
+            // a token with them
+            let required = auth::required_scopes(response.headers());
+            if let Some(rescoped) = self
+                .authenticator
+                .rescoped(&authenticator, &required)
+                .await
+                .map_err(Failure::Fatal)?
+            {
+                authorized = rescoped;
+                (response, deadline) = send(authorized.client.clone()).await?;
+            }
+        }
+        if response.status() == StatusCode::FORBIDDEN {
+            let required = auth::required_scopes(response.headers());
+            let authenticator = self.authenticator.current();
+            return Err(Failure::Fatal(auth::insufficient_scope(
+                &authenticator,
+                &authorized,
+                required,
+            )));
+        }
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            Metrics::add(&self.metrics.throttled, 1);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let retry_after = retry::retry_after(response.headers());
+            return Err(Failure::Transient(
+                Error::from_response(response).await,
+                retry_after,
+            ));
+        }
+        if status.is_client_error() {
+            return Err(Failure::Fatal(Error::from_response(response).await));
+        }
+
+        if response.status() != StatusCode::OK {
+            // this includes 204 - no content
+            return Ok(None);
+        }
+
+        let total = response
+            .headers()
+            .get("Record-Total")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+
+        Ok(Some(Page {
+            total,
+            format: self.format,
+            body: Body::Http {
+                response,
+                deadline,
+                _permit: permit,
+            },
+        }))
+    }
+
+    /// Fetches the page using the serialized query in the `body`, which is not sent if empty,
+    /// retrying the transient failures.
+    pub(crate) async fn fetch_body(
+        &self,
+        headers: HeaderMap,
+        body: Vec<u8>,
+    ) -> Result<Option<Page>> {
+        #[cfg(feature = "cache")]
+        if let Some(page) = self.cached(&body) {
+            return Ok(Some(page));
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.try_fetch(&headers, &body).await {
+                #[cfg(feature = "cache")]
+                Ok(page) => return self.store(&body, page).await,
+                #[cfg(not(feature = "cache"))]
+                Ok(page) => return Ok(page),
+                Err(Failure::Fatal(e)) => return Err(e),
+                Err(Failure::Transient(e, retry_after)) => {
+                    let message = e.to_string();
+                    let delay = self.next_attempt(attempt, e, retry_after)?;
+                    tracing::warn!(attempt, ?delay, error = %message, "page request failed, retrying");
+                    rt::sleep(delay).await;
+                    Metrics::add(&self.metrics.retries, 1);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// The delay before the next attempt after the failed `attempt` with the `error`, or the error
+    /// to fail with if the retries are exhausted.
+    fn next_attempt(
+        &self,
+        attempt: u32,
+        error: Error,
+        retry_after: Option<Duration>,
+    ) -> Result<Duration> {
+        let policy = &self.retry_policy;
+        if attempt >= policy.max_attempts {
+            return Err(error);
+        }
+
+        let delay = retry_after.unwrap_or_else(|| policy.delay(attempt));
+        if let Some(budget) = &self.retry_budget {
+            if !budget.spend(delay) {
+                return Err(Error::RetryBudgetExhausted(Box::new(error)));
+            }
+        }
+        Ok(delay)
+    }
+
+    /// Fetches the JSON document in response to the `body`, like [`HttpPages::fetch_body`].
+    #[cfg(feature = "experimental-async-requests")]
+    pub(crate) async fn fetch_json<T: DeserializeOwned>(&self, body: Vec<u8>) -> Result<T> {
+        let Some(mut page) = self.fetch_body(telemetry::trace_headers(), body).await? else {
+            return Err(Error::NoContent(self.name()));
+        };
+
+        let mut content = Vec::new();
+        while let Some(chunk) = page.body.chunk().await? {
+            content.extend_from_slice(&chunk);
+        }
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
+
+/// The error of a request to FINRA at the `url` that timed out.
+fn request_timeout(url: &str) -> Error {
+    Error::Timeout(format!("the request to {}", url))
+}
+
+impl PageSource for HttpPages {
+    fn name(&self) -> String {
+        match &self.url {
+            Some((_, url)) => url.clone(),
+            None => self.endpoints.select(&self.path).1,
+        }
+    }
+
+    fn fetch<Q: Query>(&self, query: &Q) -> impl Future<Output = Result<Option<Page>>> + Send {
+        let body = serde_json::to_vec(query);
+        let headers = telemetry::trace_headers();
+        let pages = self.clone();
+
+        async move { pages.fetch_body(headers, body?).await }
+    }
+
+    fn retry(&self, attempt: u32, error: Error) -> Result<Duration> {
+        self.next_attempt(attempt, error, None)
+    }
+}
+
+struct PagerState<S: PageSource, Q: Query> {
+    source: S,
     query: Q,
+    /// The page being read, if any.
+    page: Option<PageReader>,
     end: bool,
+    warnings: Warnings,
+    metrics: Arc<Metrics>,
+    progress: ProgressSink,
+    /// Whether the rows that cannot be deserialized fail the stream instead of being skipped.
+    strict_rows: bool,
+    overflow_policy: OverflowPolicy,
+    records: u64,
+    schema_checked: bool,
+    /// Whether the missing total of the records was already reported.
+    total_reported: bool,
+    /// The number of the interrupted attempts to read the current page.
+    interrupted: u32,
+    /// The number of the rows of the current page already read before its reading was
+    /// interrupted, to skip when it is read again.
+    read_rows: u64,
+}
+
+impl<S: PageSource, Q: Query> PagerState<S, Q> {
+    fn new(
+        source: S,
+        query: Q,
+        warnings: Warnings,
+        metrics: Arc<Metrics>,
+        progress: ProgressSink,
+        strict_rows: bool,
+        overflow_policy: OverflowPolicy,
+    ) -> Self {
+        Self {
+            source,
+            query,
+            page: None,
+            end: false,
+            warnings,
+            metrics,
+            progress,
+            strict_rows,
+            overflow_policy,
+            records: 0,
+            schema_checked: false,
+            total_reported: false,
+            interrupted: 0,
+            read_rows: 0,
+        }
+    }
+
+    /// Starts reading the fetched `page`.
+    fn open(&mut self, page: Page) -> PageReader {
+        if page.total.is_none() && !self.total_reported {
+            self.warnings.emit(Warning::MissingRecordTotal {
+                url: self.source.name(),
+            });
+            self.total_reported = true;
+        }
+
+        PageReader {
+            total: page.total,
+            body: page.body,
+            decoder: Decoder::new(page.format),
+            rows: 0,
+            skip: std::mem::take(&mut self.read_rows),
+            dropped: 0,
+            bytes: 0,
+        }
+    }
 }
 
-/// Gets all the results of the query as a stream. The pagination query parameters are
-/// automatically added.
-pub async fn all_results<T, Q>(
-    client: Client,
-    url: impl IntoUrl,
+/// Whether the reading of a page failed on the way rather than on its content, e.g. due to a
+/// timeout or a reset connection, so that it makes sense to fetch the page again.
+fn interrupted(error: &Error) -> bool {
+    match error {
+        Error::Timeout(_) => true,
+        // reqwest reports a connection closed in the middle of the body as a decoding error
+        Error::HttpError(e) => e.is_body() || e.is_decode() || e.is_connect() || e.is_request(),
+        _ => false,
+    }
+}
+
+/// Decodes the records of a page as its body arrives.
+struct PageReader {
+    total: Option<u64>,
+    body: Body,
+    decoder: Decoder,
+    /// The number of the rows decoded so far, including the skipped ones.
+    rows: u64,
+    /// The number of the rows to skip because they were already read before the page was fetched
+    /// again.
+    skip: u64,
+    dropped: u64,
+    bytes: u64,
+}
+
+/// Fails the stream of the `records` with [`Error::Timeout`] if it doesn't end within the
+/// `deadline` from when it is first polled.
+pub(crate) fn with_deadline<T, S>(
+    records: S,
+    deadline: Option<Duration>,
+) -> impl Stream<Item = Result<T>>
+where
+    S: TryStream<Ok = T, Error = Error>,
+{
+    let timer = deadline.map(|d| (d, Box::pin(rt::sleep(d))));
+    stream::unfold(
+        Some((Box::pin(records.into_stream()), timer)),
+        |state| async move {
+            let (mut records, mut timer) = state?;
+            let next = match &mut timer {
+                Some((deadline, sleep)) => {
+                    match future::select(records.next(), sleep.as_mut()).await {
+                        Either::Left((next, _)) => next,
+                        Either::Right(_) => {
+                            let e = Error::Timeout(format!(
+                                "the results were not read within {:?}",
+                                deadline
+                            ));
+                            return Some((Err(e), None));
+                        }
+                    }
+                }
+                None => records.next().await,
+            };
+            next.map(|next| (next, Some((records, timer))))
+        },
+    )
+}
+
+/// Gets the total number of the records matching the query, as reported by FINRA, without
+/// reading the returned data. Use a query with a small limit to keep the response small.
+pub(crate) async fn record_total<S: PageSource, Q: Query>(
+    source: &S,
+    query: &Q,
+    warnings: &Warnings,
+) -> Result<u64> {
+    let span = tracing::info_span!("finra_record_total", url = %source.name());
+    let Some(page) = source.fetch(query).instrument(span).await? else {
+        return Ok(0);
+    };
+
+    if page.total.is_none() {
+        warnings.emit(Warning::MissingRecordTotal { url: source.name() });
+    }
+
+    Ok(page.total.unwrap_or(0))
+}
+
+/// Streams the records on the pages of the `source`, moving the cursor of the `query` from page
+/// to page until all the records are read. The records are decoded as the pages arrive, so the
+/// stream yields a batch of records for each chunk of a page.
+pub(crate) fn paginate<T, Q, S>(
+    source: S,
     query: Q,
-) -> Result<impl TryStream<Ok = Vec<T>, Error = Error>>
+    warnings: Warnings,
+    metrics: Arc<Metrics>,
+    progress: ProgressSink,
+    strict_rows: bool,
+    overflow_policy: OverflowPolicy,
+) -> impl TryStream<Ok = Vec<T>, Error = Error>
 where
     T: DeserializeOwned,
     Q: Query,
+    S: PageSource,
 {
-    Ok(stream::try_unfold(
-        PagerState {
-            client,
-            url: url.into_url()?,
-            query,
-            end: false,
-        },
-        |state| {
-            Box::pin(async move {
+    read_pages(PagerState::new(
+        source,
+        query,
+        warnings,
+        metrics,
+        progress,
+        strict_rows,
+        overflow_policy,
+    ))
+}
+
+/// Like [`paginate`], but fetches the first page right away, so that the total number of the
+/// records reported by FINRA is known before the records are streamed. The total is `None` if
+/// FINRA didn't report it.
+pub(crate) async fn paginate_with_total<T, Q, S>(
+    source: S,
+    query: Q,
+    warnings: Warnings,
+    metrics: Arc<Metrics>,
+    progress: ProgressSink,
+    strict_rows: bool,
+    overflow_policy: OverflowPolicy,
+) -> Result<(Option<u64>, impl TryStream<Ok = Vec<T>, Error = Error>)>
+where
+    T: DeserializeOwned,
+    Q: Query,
+    S: PageSource,
+{
+    let mut state = PagerState::new(
+        source,
+        query,
+        warnings,
+        metrics,
+        progress,
+        strict_rows,
+        overflow_policy,
+    );
+    let span = tracing::info_span!(
+        "finra_page",
+        url = %state.source.name(),
+        offset = state.query.offset(),
+        limit = state.query.limit(),
+    );
+    let total = match state.source.fetch(&state.query).instrument(span).await? {
+        Some(page) => {
+            let total = page.total;
+            state.page = Some(state.open(page));
+            total
+        }
+        None => {
+            state.end = true;
+            Some(0)
+        }
+    };
+
+    Ok((total, read_pages(state)))
+}
+
+fn read_pages<T, Q, S>(state: PagerState<S, Q>) -> impl TryStream<Ok = Vec<T>, Error = Error>
+where
+    T: DeserializeOwned,
+    Q: Query,
+    S: PageSource,
+{
+    stream::try_unfold(state, |mut state| {
+        let span = tracing::info_span!(
+            "finra_page",
+            url = %state.source.name(),
+            offset = state.query.offset(),
+            limit = state.query.limit(),
+        );
+        Box::pin(
+            async move {
                 if state.end {
                     return Ok(None);
                 }
 
-                let response = state
-                    .client
-                    .post(state.url.clone())
-                    .header(header::ACCEPT, "text/plain")
-                    .header(header::CONTENT_TYPE, "application/json")
-                    .json(&state.query)
-                    .send()
-                    .await?
-                    .error_for_status()?;
+                let mut page = match state.page.take() {
+                    Some(page) => page,
+                    None => {
+                        let Some(page) = state.source.fetch(&state.query).await? else {
+                            return Ok(None);
+                        };
+                        state.open(page)
+                    }
+                };
 
-                if response.status() != StatusCode::OK {
-                    // this includes 204 - no content
-                    return Ok(None);
+                let mut rows = Vec::new();
+                let chunk = match page.body.chunk().await {
+                    Ok(chunk) => chunk,
+                    Err(e) if interrupted(&e) => {
+                        state.interrupted += 1;
+                        let message = e.to_string();
+                        let delay = state.source.retry(state.interrupted, e)?;
+                        tracing::warn!(
+                            attempt = state.interrupted,
+                            ?delay,
+                            error = %message,
+                            "page reading interrupted, fetching the page again"
+                        );
+                        rt::sleep(delay).await;
+                        Metrics::add(&state.metrics.retries, 1);
+                        state.read_rows = page.rows;
+                        return Ok(Some((Vec::new(), state)));
+                    }
+                    Err(e) => return Err(e),
+                };
+                match &chunk {
+                    Some(chunk) => {
+                        page.bytes += chunk.len() as u64;
+                        Metrics::add(&state.metrics.bytes, chunk.len() as u64);
+                        page.decoder.feed(chunk, &mut rows);
+                    }
+                    None => page.decoder.finish(&mut rows),
                 }
 
-                let total: u64 = response
-                    .headers()
-                    .get("Record-Total")
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|v| v.parse().ok())
-                    .unwrap_or(0);
-
-                let body = response.text().await?;
-                let mut rdr =
-                    csv::ReaderBuilder::new().from_reader(BufReader::new(body.as_bytes()));
-                let items: Vec<T> = rdr.deserialize().flatten().collect();
-
-                let new_query = state.query.move_cursor(items.len() as u64);
-
-                let end = total <= new_query.offset();
-
-                Ok(Some((
-                    items,
-                    PagerState {
-                        client: state.client,
-                        url: state.url,
-                        query: new_query,
-                        end,
-                    },
-                )))
-            })
-        },
-    ))
+                let known = state.query.known_fields();
+                if !state.schema_checked && !known.is_empty() {
+                    if let Some(fields) = page.decoder.fields() {
+                        let unknown_fields: Vec<String> = fields
+                            .iter()
+                            .filter(|f| !known.contains(&f.as_str()))
+                            .cloned()
+                            .collect();
+                        if !unknown_fields.is_empty() {
+                            state.warnings.emit(Warning::SchemaDrift {
+                                url: state.source.name(),
+                                unknown_fields,
+                            });
+                        }
+                        if state.strict_rows {
+                            let missing: Vec<String> = state
+                                .query
+                                .requested_fields()
+                                .into_iter()
+                                .filter(|f| !fields.iter().any(|field| field == f))
+                                .map(str::to_string)
+                                .collect();
+                            if !missing.is_empty() {
+                                return Err(Error::MissingFields {
+                                    url: state.source.name(),
+                                    fields: missing,
+                                });
+                            }
+                        }
+                        state.schema_checked = true;
+                    }
+                }
+
+                // the rows read before the page was fetched again were already returned
+                let skipped = rows
+                    .len()
+                    .min(usize::try_from(page.skip).unwrap_or(usize::MAX));
+                page.skip -= skipped as u64;
+
+                let mut items = Vec::with_capacity(rows.len());
+                for row in &rows[skipped..] {
+                    let (item, overflow) = numbers::with_policy(state.overflow_policy, || {
+                        page.decoder.deserialize(row)
+                    });
+                    match item {
+                        Ok(item) => items.push(item),
+                        Err(source) if overflow => {
+                            return Err(Error::NumericOverflow {
+                                offset: state.query.offset(),
+                                position: row.position(),
+                                source,
+                            });
+                        }
+                        Err(source) if state.strict_rows => {
+                            return Err(Error::RowDeserialization {
+                                offset: state.query.offset(),
+                                position: row.position(),
+                                source,
+                            });
+                        }
+                        Err(_) => {}
+                    }
+                }
+                page.rows += rows.len() as u64;
+                page.dropped += (rows.len() - skipped - items.len()) as u64;
+                Metrics::add(&state.metrics.records, items.len() as u64);
+
+                if chunk.is_some() {
+                    state.page = Some(page);
+                    return Ok(Some((items, state)));
+                }
+
+                if page.dropped > 0 {
+                    state.warnings.emit(Warning::DroppedRows {
+                        url: state.source.name(),
+                        count: page.dropped,
+                    });
+                }
+
+                tracing::debug!(
+                    records = page.rows,
+                    dropped = page.dropped,
+                    bytes = page.bytes,
+                    total = ?page.total,
+                    "page read"
+                );
+
+                let records = state.records + page.rows;
+                state.progress.report(&Progress {
+                    url: state.source.name(),
+                    first_page: state.records == 0,
+                    page_records: page.rows,
+                    page_bytes: page.bytes,
+                    records,
+                    total: page.total,
+                });
+                state.records = records;
+                state.interrupted = 0;
+
+                // a page shorter than requested means there's no more data even if FINRA didn't
+                // tell us the total
+                let short_page = page.rows < state.query.limit();
+
+                state.query = state.query.move_cursor(page.rows);
+
+                state.end = short_page || page.total.is_some_and(|t| t <= state.query.offset());
+
+                Ok(Some((items, state)))
+            }
+            .instrument(span),
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn stalled_stream_fails_after_deadline() {
+        let stalled = stream::iter([Ok(1)]).chain(stream::pending());
+        let records = with_deadline(stalled, Some(Duration::from_millis(50)))
+            .collect::<Vec<Result<u32>>>()
+            .await;
+
+        assert_eq!(2, records.len());
+        assert_eq!(1, *records[0].as_ref().unwrap());
+        assert!(matches!(records[1], Err(Error::Timeout(_))));
+    }
 }
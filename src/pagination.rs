@@ -0,0 +1,160 @@
+use std::ops::Range;
+
+use time::{Date, Duration};
+
+/// How the results of a query are fetched from FINRA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaginationStrategy {
+    /// The results are read page by page, moving the offset of the query until all the records
+    /// are read. This is the default.
+    #[default]
+    OffsetLimit,
+    /// The date range of the query is split into consecutive partitions that are paged through
+    /// separately. Deep offsets get slow on large results, so this is faster for long date
+    /// ranges. Queries without a date range are paged through as a whole.
+    Partitioned {
+        /// The length of each partition. If `None`, a length suitable for the dataset is used,
+        /// e.g. a week for the daily datasets.
+        partition: Option<Duration>,
+        /// How many partitions are read at the same time. If more than 1, the records of the
+        /// concurrently read partitions are interleaved in the results.
+        concurrency: usize,
+    },
+    /// The number of the matching records is checked first and the results are read as
+    /// [`PaginationStrategy::Partitioned`] with the default partition length if there are more
+    /// than `threshold` of them, or as [`PaginationStrategy::OffsetLimit`] otherwise.
+    Auto {
+        threshold: u64,
+        /// How many partitions are read at the same time, if the results are partitioned.
+        concurrency: usize,
+    },
+    /// Only a sample of the results is read, e.g. to explore the shape of the data before
+    /// downloading all of them. The number of the matching records is checked first and up to
+    /// `records` of them are read from `pages` pages spread evenly over the results. With 3 pages,
+    /// the records come from the start, the middle and the last third of the results.
+    Sampled { records: u64, pages: u64 },
+    /// The query is submitted to FINRA to be processed asynchronously using
+    /// [`crate::Finra::submit_async_query`], its status is polled every `poll_interval` until it
+    /// is completed and the results are then downloaded at once, as by [`crate::Finra::download`].
+    /// This is meant for the extracts too large to page through reliably. The limit of the query
+    /// is ignored.
+    ///
+    /// Only available with the experimental `experimental-async-requests` feature, as the
+    /// asynchronous requests have not been verified against the live FINRA API.
+    #[cfg(feature = "experimental-async-requests")]
+    AsyncDownload { poll_interval: std::time::Duration },
+}
+
+impl PaginationStrategy {
+    /// Chooses the strategy for the query with the `total` number of the matching records. Only
+    /// [`PaginationStrategy::Auto`] depends on the total.
+    pub(crate) fn for_total(self, total: u64) -> Self {
+        match self {
+            Self::Auto {
+                threshold,
+                concurrency,
+            } if total > threshold => Self::Partitioned {
+                partition: None,
+                concurrency,
+            },
+            Self::Auto { .. } => Self::OffsetLimit,
+            strategy => strategy,
+        }
+    }
+
+    /// Partitions the date range of the query using the dataset's `default_partition` if none is
+    /// configured. Returns the whole `date_range` as a single partition if the strategy doesn't
+    /// partition the query.
+    pub(crate) fn partitions(
+        &self,
+        date_range: Option<&Range<Date>>,
+        default_partition: Duration,
+    ) -> Vec<Option<Range<Date>>> {
+        let (Self::Partitioned { partition, .. }, Some(date_range)) = (self, date_range) else {
+            return vec![date_range.cloned()];
+        };
+
+        let partition = partition.unwrap_or(default_partition).max(Duration::DAY);
+        let mut partitions = Vec::new();
+        let mut start = date_range.start;
+        while start < date_range.end {
+            let end = start.saturating_add(partition).min(date_range.end);
+            partitions.push(Some(start..end));
+            start = end;
+        }
+        partitions
+    }
+
+    /// The offsets of the pages of [`PaginationStrategy::Sampled`] in the `total` matching records
+    /// together with the numbers of the records to read from them. Empty for the other strategies.
+    pub(crate) fn sample_pages(&self, total: u64) -> Vec<(u64, u64)> {
+        let Self::Sampled { records, pages } = *self else {
+            return Vec::new();
+        };
+
+        let records = records.min(total);
+        let pages = pages.clamp(1, records.max(1));
+        (0..pages)
+            .map(|i| {
+                let offset = i * total / pages;
+                let end = (i + 1) * total / pages;
+                let quota = (i + 1) * records / pages - i * records / pages;
+                (offset, quota.min(end - offset))
+            })
+            .filter(|(_, records)| *records > 0)
+            .collect()
+    }
+
+    /// How many partitions are read at the same time.
+    pub(crate) fn concurrency(&self) -> usize {
+        match self {
+            Self::OffsetLimit | Self::Auto { .. } | Self::Sampled { .. } => 1,
+            #[cfg(feature = "experimental-async-requests")]
+            Self::AsyncDownload { .. } => 1,
+            Self::Partitioned { concurrency, .. } => (*concurrency).max(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn date_range_partitioned() {
+        let strategy = PaginationStrategy::Partitioned {
+            partition: None,
+            concurrency: 1,
+        };
+
+        assert_eq!(
+            strategy.partitions(
+                Some(&(date!(2024 - 01 - 01)..date!(2024 - 01 - 20))),
+                Duration::weeks(1)
+            ),
+            vec![
+                Some(date!(2024 - 01 - 01)..date!(2024 - 01 - 08)),
+                Some(date!(2024 - 01 - 08)..date!(2024 - 01 - 15)),
+                Some(date!(2024 - 01 - 15)..date!(2024 - 01 - 20)),
+            ]
+        );
+        assert_eq!(strategy.partitions(None, Duration::weeks(1)), vec![None]);
+    }
+
+    #[test]
+    fn sample_spread_over_results() {
+        let strategy = PaginationStrategy::Sampled {
+            records: 10,
+            pages: 3,
+        };
+
+        assert_eq!(
+            strategy.sample_pages(3000),
+            vec![(0, 3), (1000, 3), (2000, 4)]
+        );
+        assert_eq!(strategy.sample_pages(5), vec![(0, 1), (1, 2), (3, 2)]);
+        assert_eq!(strategy.sample_pages(0), vec![]);
+        assert_eq!(PaginationStrategy::OffsetLimit.sample_pages(3000), vec![]);
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use plotters::{coord::Shift, prelude::*};
+use time::Date;
+
+use crate::{dates::format_date, ConsolidatedShortInterest};
+
+/// Draws the history of the short positions of each symbol in the `records` into a line chart on
+/// the provided drawing `area`. The x axis shows the settlement dates and the y axis the number of
+/// shares sold short.
+///
+/// Note that the fonts needed to draw the labels need to be enabled in the `plotters` dependency
+/// of the calling crate, e.g. using its default features.
+pub fn plot_short_interest<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    records: &[ConsolidatedShortInterest],
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let mut series: BTreeMap<&str, Vec<(i32, f64)>> = BTreeMap::new();
+    for r in records {
+        if let Some(date) = r.settlement_date {
+            series.entry(&r.symbol_code).or_default().push((
+                date.to_julian_day(),
+                r.current_short_position_quantity as f64,
+            ));
+        }
+    }
+
+    let points = series.values().flatten();
+    let Some(min_x) = points.clone().map(|p| p.0).min() else {
+        // nothing to draw
+        return Ok(());
+    };
+    let max_x = points.clone().map(|p| p.0).max().unwrap_or(min_x);
+    let max_y = points.map(|p| p.1).fold(0f64, f64::max);
+
+    let mut chart = ChartBuilder::on(area)
+        .caption("Short interest", ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(70)
+        .build_cartesian_2d(min_x..max_x.max(min_x + 1), 0f64..(max_y * 1.1).max(1.0))?;
+
+    chart
+        .configure_mesh()
+        .x_label_formatter(&|d| {
+            Date::from_julian_day(*d)
+                .map(|d| format_date(&d))
+                .unwrap_or_default()
+        })
+        .y_desc("shares sold short")
+        .draw()?;
+
+    for (i, (symbol, mut points)) in series.into_iter().enumerate() {
+        points.sort_by_key(|p| p.0);
+        let color = Palette99::pick(i).to_rgba();
+        chart
+            .draw_series(LineSeries::new(points, color.stroke_width(2)))?
+            .label(symbol)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
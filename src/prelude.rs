@@ -0,0 +1,19 @@
+//! The commonly used types, to be glob-imported with `use finra_rs::prelude::*;`.
+
+pub use futures::{StreamExt, TryStreamExt};
+
+pub use crate::{
+    fixed_income::{
+        CorporateMarketBreadth, CorporateMarketBreadthField, CorporateMarketBreadthQuery,
+        TreasuryWeeklyAggregates, TreasuryWeeklyAggregatesField, TreasuryWeeklyAggregatesQuery,
+    },
+    registration::{FirmField, FirmQuery, FirmRecord},
+    BlocksSummaryField, BlocksSummaryQuery, BlocksSummaryRecord, CompareFilter, CompareType,
+    ConsolidatedShortInterest, ConsolidatedShortInterestField, ConsolidatedShortInterestQuery,
+    DailyShortSaleVolume, DailyShortSaleVolumeField, DailyShortSaleVolumeQuery, DatasetQuery,
+    DomainFilter, EquityShortInterest, EquityShortInterestField, EquityShortInterestQuery, Error,
+    Finra, FinraBuilder, FinraStreamExt, MonthlySummaryField, MonthlySummaryQuery,
+    MonthlySummaryRecord, PaginationStrategy, Result, SortField, ThresholdListField,
+    ThresholdListQuery, ThresholdListRecord, Tier, WeeklySummaryField, WeeklySummaryQuery,
+    WeeklySummaryRecord,
+};
@@ -0,0 +1,72 @@
+use std::{fmt::Debug, sync::Arc};
+
+/// Describes the progress of reading the results of a query. Reported after each page of the
+/// results is read.
+///
+/// Use [`crate::FinraBuilder::progress`] to get notified about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// The URL of the queried dataset.
+    pub url: String,
+    /// Whether this is the first page of the results of the query.
+    pub first_page: bool,
+    /// The number of the records on this page.
+    pub page_records: u64,
+    /// The size of this page in bytes.
+    pub page_bytes: u64,
+    /// The number of the records of the query read so far, including this page.
+    pub records: u64,
+    /// The total number of the records of the query, if reported by FINRA.
+    pub total: Option<u64>,
+}
+
+type Sink = dyn Fn(&Progress) + Send + Sync;
+
+/// Where the progress is reported to. Does nothing unless a sink is set up.
+#[derive(Clone, Default)]
+pub(crate) struct ProgressSink(Option<Arc<Sink>>);
+
+impl ProgressSink {
+    pub(crate) fn new(sink: Arc<Sink>) -> Self {
+        Self(Some(sink))
+    }
+
+    pub(crate) fn report(&self, progress: &Progress) {
+        if let Some(sink) = &self.0 {
+            sink(progress);
+        }
+    }
+}
+
+impl Debug for ProgressSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ProgressSink")
+            .field(&self.0.as_ref().map(|_| "sink"))
+            .finish()
+    }
+}
+
+/// Creates a progress sink that shows the progress of all the queries in the `bar`. The length of
+/// the bar grows by the total number of the records of each query, so it can be shared by the
+/// queries running concurrently. The number of the downloaded bytes is shown as the message of
+/// the bar.
+#[cfg(feature = "indicatif")]
+pub(crate) fn progress_bar_sink(
+    bar: indicatif::ProgressBar,
+) -> impl Fn(&Progress) + Send + Sync + 'static {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let bytes = AtomicU64::new(0);
+    move |progress| {
+        if progress.first_page {
+            bar.inc_length(progress.total.unwrap_or(progress.page_records));
+        } else if progress.total.is_none() {
+            // we don't know how many records there are so let's at least show what we have
+            bar.inc_length(progress.page_records);
+        }
+        bar.inc(progress.page_records);
+
+        let bytes = bytes.fetch_add(progress.page_bytes, Ordering::Relaxed) + progress.page_bytes;
+        bar.set_message(indicatif::HumanBytes(bytes).to_string());
+    }
+}
@@ -0,0 +1,145 @@
+use serde::de::DeserializeOwned;
+
+/// A record holding only some of the fields of a dataset, declared using the [`projection!`]
+/// macro. Only the fields of the projection are requested from FINRA and all of them need to be
+/// in the results - a missing field is an error rather than a zero or an empty string.
+///
+/// [`projection!`]: crate::projection
+pub trait Projection: DeserializeOwned {
+    /// The enum of the fields of the dataset.
+    type Field;
+
+    /// The fields of the dataset the projection consists of.
+    fn fields() -> Vec<Self::Field>;
+}
+
+/// Declares a [`Projection`] - a struct with the selected fields of a dataset. Each field of the
+/// struct is mapped onto a variant of the field enum of the dataset. The fields of the struct can
+/// be of any type deserializable from the values of the dataset fields.
+///
+/// ```
+/// use finra_rs::{projection, ConsolidatedShortInterestField};
+///
+/// projection! {
+///     /// The change of the short interest of a symbol.
+///     #[derive(Debug, Clone)]
+///     pub struct ShortInterestChange: ConsolidatedShortInterestField {
+///         pub symbol: String = SymbolCode,
+///         pub change_percent: f64 = ChangePercent,
+///     }
+/// }
+/// ```
+///
+/// Use it with e.g. [`crate::Finra::consolidated_short_interest_projected`].
+#[macro_export]
+macro_rules! projection {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident : $field_enum:ty {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident : $ty:ty = $variant:ident),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty),*
+        }
+
+        impl $crate::Projection for $name {
+            type Field = $field_enum;
+
+            fn fields() -> ::std::vec::Vec<$field_enum> {
+                ::std::vec![$(<$field_enum>::$variant),*]
+            }
+        }
+
+        impl<'de> $crate::__serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: $crate::__serde::Deserializer<'de>,
+            {
+                use $crate::__serde::de;
+
+                struct Visitor;
+
+                impl<'de> de::Visitor<'de> for Visitor {
+                    type Value = $name;
+
+                    fn expecting(
+                        &self,
+                        f: &mut ::std::fmt::Formatter,
+                    ) -> ::std::fmt::Result {
+                        f.write_str(::std::stringify!($name))
+                    }
+
+                    fn visit_map<A>(self, mut map: A) -> ::std::result::Result<$name, A::Error>
+                    where
+                        A: de::MapAccess<'de>,
+                    {
+                        $(let mut $field: ::std::option::Option<$ty> = None;)*
+                        while let Some(key) = map.next_key::<::std::string::String>()? {
+                            $(
+                                if key == <$field_enum>::$variant.as_str() {
+                                    $field = Some(map.next_value()?);
+                                    continue;
+                                }
+                            )*
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+
+                        Ok($name {
+                            $($field: $field.ok_or_else(|| {
+                                de::Error::missing_field(<$field_enum>::$variant.as_str())
+                            })?),*
+                        })
+                    }
+                }
+
+                deserializer.deserialize_map(Visitor)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ConsolidatedShortInterestField;
+
+    use super::*;
+
+    projection! {
+        #[derive(Debug, PartialEq)]
+        struct Change: ConsolidatedShortInterestField {
+            symbol: String = SymbolCode,
+            change: i64 = ChangePreviousNumber,
+        }
+    }
+
+    #[test]
+    fn missing_fields_not_defaulted() {
+        assert_eq!(
+            vec![
+                ConsolidatedShortInterestField::SymbolCode,
+                ConsolidatedShortInterestField::ChangePreviousNumber
+            ],
+            Change::fields()
+        );
+
+        let mut csv = csv::Reader::from_reader(
+            "symbolCode,changePreviousNumber,issueName\nAAPL,-42,Apple\n".as_bytes(),
+        );
+        let change: Change = csv.deserialize().next().unwrap().unwrap();
+        assert_eq!(
+            Change {
+                symbol: "AAPL".to_string(),
+                change: -42
+            },
+            change
+        );
+
+        let missing = serde_json::from_str::<Change>(r#"{"symbolCode": "AAPL"}"#);
+        assert!(missing
+            .unwrap_err()
+            .to_string()
+            .contains("changePreviousNumber"));
+    }
+}
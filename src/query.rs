@@ -1,110 +1,165 @@
-use std::{fmt::Display, ops::Range};
+use std::ops::Range;
 
-use serde::{
-    ser::{SerializeMap, SerializeSeq},
-    Serialize,
-};
-use time::Date;
+use serde::{ser::SerializeMap, Serialize};
+use time::{Date, Duration};
 
-const MAX_RESULTS_PER_PAGE: u64 = 1000;
+use crate::{dates::format_date, PaginationStrategy};
 
-pub(crate) trait Query: Serialize {
+pub(crate) const MAX_RESULTS_PER_PAGE: u64 = 1000;
+
+/// Generates a public enum of the fields of a dataset together with the conversions to their
+/// FINRA names.
+macro_rules! dataset_fields {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $field:literal),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            /// The FINRA names of all the fields.
+            pub(crate) const NAMES: &'static [&'static str] = &[$($field),*];
+
+            /// The FINRA names of the `fields`, or of all the fields if `None`.
+            pub(crate) fn names_of(fields: Option<&[Self]>) -> Vec<&'static str> {
+                match fields {
+                    Some(fields) => fields.iter().map(Self::as_str).collect(),
+                    None => Self::NAMES.to_vec(),
+                }
+            }
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $field),*
+                }
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                self.as_str()
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+    };
+}
+pub(crate) use dataset_fields;
+
+pub(crate) trait Query: Serialize + Clone {
     fn limit(&self) -> u64;
     fn offset(&self) -> u64;
     fn move_cursor(self, by: u64) -> Self;
+    /// Sets the maximum number of records returned in a single page.
+    fn with_limit(self, limit: u64) -> Self;
+    /// Sets the date range to the provided one, unless the query already has one.
+    fn default_date_range(&mut self, date_range: Range<Date>);
+    /// The names of all the fields of the queried dataset known to this crate. Empty if the
+    /// dataset is not known.
+    fn known_fields(&self) -> &'static [&'static str];
+    /// The names of the fields the results are expected to contain - the fields limited to by the
+    /// query, or all the known fields. Empty if the dataset is not known.
+    fn requested_fields(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    /// The symbols the query is filtered by.
+    fn symbols_mut(&mut self) -> Vec<&mut String>;
+    /// The date range the results are limited to, if any.
+    fn date_range(&self) -> Option<&Range<Date>>;
+    /// Limits the results to the date range. Does nothing if the dataset has no date to filter by.
+    fn set_date_range(&mut self, date_range: Range<Date>);
+    /// How the results are fetched from FINRA.
+    fn pagination(&self) -> PaginationStrategy;
+    /// The length of the partitions of the date range of [`PaginationStrategy::Partitioned`], if
+    /// not configured.
+    fn default_partition(&self) -> Duration {
+        Duration::days(30)
+    }
 }
 
-/// This enum is used to limit which fields are included in the query results.
-#[derive(Debug, Clone, Copy)]
-pub enum ConsolidatedShortInterestField {
-    StockSplitFlag,
-    PreviousShortPositionQuantity,
-    AverageDailyVolumeQuantity,
-    IssueName,
-    CurrentShortPositionQuantity,
-    ChangePreviousNumber,
-    AccountingYearMonthNumber,
-    SettlementDate,
-    MarketClassCode,
-    SymbolCode,
-    DaysToCoverQuantity,
-    IssuerServicesGroupExchangeCode,
-    RevisionFlag,
-    ChangePercent,
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the query results.
+    ConsolidatedShortInterestField {
+        StockSplitFlag => "stockSplitFlag",
+        PreviousShortPositionQuantity => "previousShortPositionQuantity",
+        AverageDailyVolumeQuantity => "averageDailyVolumeQuantity",
+        IssueName => "issueName",
+        CurrentShortPositionQuantity => "currentShortPositionQuantity",
+        ChangePreviousNumber => "changePreviousNumber",
+        AccountingYearMonthNumber => "accountingYearMonthNumber",
+        SettlementDate => "settlementDate",
+        MarketClassCode => "marketClassCode",
+        SymbolCode => "symbolCode",
+        DaysToCoverQuantity => "daysToCoverQuantity",
+        IssuerServicesGroupExchangeCode => "issuerServicesGroupExchangeCode",
+        RevisionFlag => "revisionFlag",
+        ChangePercent => "changePercent",
+    }
 }
 
 /// Represents the query to limit the number of results. This does not correspond to the generic
 /// nature of the queries supported by FINRA but supports the common usecases.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConsolidatedShortInterestQuery {
     /// If `None`, all fields are included.
     pub fields: Option<Vec<ConsolidatedShortInterestField>>,
     /// If `None`, the full available history is included.
     pub date_range: Option<Range<Date>>,
-    // If `None` the data for all symbols is included.
-    pub symbol: Option<String>,
+    /// If empty, the data for all symbols is included.
+    pub symbols: Vec<String>,
+    /// Any additional filters the records need to match.
+    pub compare_filters: Vec<CompareFilter>,
+    /// Any additional sets of values the fields of the records need to be in.
+    pub domain_filters: Vec<DomainFilter>,
+    /// The order of the results. If empty, the order is decided by FINRA.
+    pub sort: Vec<SortField>,
+    /// How the results are fetched from FINRA.
+    pub pagination: PaginationStrategy,
 
     // These are internally used for paging...
     limit: u64,
     offset: u64,
 }
 
-struct AsSeq<T: Serialize>(T);
-struct ConsolidatedShortInterestQueryDateRange(Range<Date>);
-struct ConsolidatedShortInterestQuerySymbolFilter<'a>(&'a str);
-
-impl ConsolidatedShortInterestField {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Self::StockSplitFlag => "stockSplitFlag",
-            Self::PreviousShortPositionQuantity => "previousShortPositionQuantity",
-            Self::AverageDailyVolumeQuantity => "averageDailyVolumeQuantity",
-            Self::IssueName => "issueName",
-            Self::CurrentShortPositionQuantity => "currentShortPositionQuantity",
-            Self::ChangePreviousNumber => "changePreviousNumber",
-            Self::AccountingYearMonthNumber => "accountingYearMonthNumber",
-            Self::SettlementDate => "settlementDate",
-            Self::MarketClassCode => "marketClassCode",
-            Self::SymbolCode => "symbolCode",
-            Self::DaysToCoverQuantity => "daysToCoverQuantity",
-            Self::IssuerServicesGroupExchangeCode => "issuerServicesGroupExchangeCode",
-            Self::RevisionFlag => "revisionFlag",
-            Self::ChangePercent => "changePercent",
-        }
-    }
-}
-
-impl AsRef<str> for ConsolidatedShortInterestField {
-    fn as_ref(&self) -> &str {
-        self.as_str()
-    }
-}
-
-impl Display for ConsolidatedShortInterestField {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.as_str())
-    }
-}
-
-impl Serialize for ConsolidatedShortInterestField {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(self.as_str())
-    }
-}
-
 impl ConsolidatedShortInterestQuery {
+    /// Creates a query for the data of a single symbol or, if it is `None`, all symbols.
     pub fn new(
         fields: Option<Vec<ConsolidatedShortInterestField>>,
         date_range: Option<Range<Date>>,
         symbol: Option<String>,
+    ) -> Self {
+        Self::with_symbols(fields, date_range, symbol.into_iter().collect())
+    }
+
+    /// Creates a query for the data of any of the provided symbols, or all symbols if `symbols`
+    /// is empty. All the symbols are fetched in a single paged stream.
+    pub fn with_symbols(
+        fields: Option<Vec<ConsolidatedShortInterestField>>,
+        date_range: Option<Range<Date>>,
+        symbols: Vec<String>,
     ) -> Self {
         Self {
             fields,
             date_range,
-            symbol,
+            symbols,
+            compare_filters: Vec::new(),
+            domain_filters: Vec::new(),
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
             limit: MAX_RESULTS_PER_PAGE,
             offset: 0,
         }
@@ -124,11 +179,49 @@ impl Query for ConsolidatedShortInterestQuery {
         Self {
             fields: self.fields,
             date_range: self.date_range,
-            symbol: self.symbol,
+            symbols: self.symbols,
+            compare_filters: self.compare_filters,
+            domain_filters: self.domain_filters,
+            sort: self.sort,
+            pagination: self.pagination,
             limit: self.limit,
             offset: self.offset + by,
         }
     }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, date_range: Range<Date>) {
+        if self.date_range.is_none() {
+            self.date_range = Some(date_range);
+        }
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        ConsolidatedShortInterestField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        ConsolidatedShortInterestField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        self.symbols.iter_mut().collect()
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref()
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        self.date_range = Some(date_range);
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
 }
 
 impl Serialize for ConsolidatedShortInterestQuery {
@@ -136,92 +229,307 @@ impl Serialize for ConsolidatedShortInterestQuery {
     where
         S: serde::Serializer,
     {
-        let len = 2
-            + self.fields.iter().count()
-            + self.date_range.iter().count()
-            + self.date_range.iter().count();
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|r| {
+                    DateRangeFilter::new(ConsolidatedShortInterestField::SettlementDate.as_str(), r)
+                })
+                .collect(),
+            compare_filters: match self.symbols.as_slice() {
+                [symbol] => vec![CompareFilter::equal(
+                    ConsolidatedShortInterestField::SymbolCode,
+                    symbol.as_str(),
+                )],
+                _ => Vec::new(),
+            }
+            .into_iter()
+            .chain(self.compare_filters.iter().cloned())
+            .collect(),
+            domain_filters: match self.symbols.as_slice() {
+                [] | [_] => Vec::new(),
+                symbols => vec![DomainFilter::new(
+                    ConsolidatedShortInterestField::SymbolCode,
+                    symbols.iter().map(String::as_str),
+                )],
+            }
+            .into_iter()
+            .chain(self.domain_filters.iter().cloned())
+            .collect(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// The body of the query as understood by FINRA. The individual queries are serialized using it.
+#[derive(Serialize)]
+pub(crate) struct QueryBody<'a, F: Serialize> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fields: Option<&'a [F]>,
+    #[serde(rename = "dateRangeFilters", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) date_range_filters: Vec<DateRangeFilter>,
+    #[serde(rename = "compareFilters", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) compare_filters: Vec<CompareFilter>,
+    #[serde(rename = "domainFilters", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) domain_filters: Vec<DomainFilter>,
+    #[serde(rename = "sortFields", skip_serializing_if = "<[_]>::is_empty")]
+    pub(crate) sort_fields: &'a [SortField],
+    pub(crate) limit: u64,
+    pub(crate) offset: u64,
+}
 
-        let mut map = serializer.serialize_map(Some(len))?;
+pub(crate) struct DateRangeFilter {
+    field: String,
+    range: Range<Date>,
+}
+
+/// Compares the value of a field with the provided value. Use the field enums of the datasets
+/// (e.g. [`ConsolidatedShortInterestField`]) or the FINRA field names to identify the field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompareFilter {
+    pub field: String,
+    pub value: serde_json::Value,
+    pub compare_type: CompareType,
+}
 
-        if let Some(ref fields) = self.fields {
-            map.serialize_entry("fields", fields)?;
+/// How the value of the field is compared in a [`CompareFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CompareType {
+    Equal,
+    NotEqual,
+    /// Greater than or equal.
+    Gte,
+    /// Less than or equal.
+    Lte,
+    /// Greater than.
+    Gt,
+    /// Less than.
+    Lt,
+}
+
+/// Matches the records whose `field` has any of the `values`, e.g. a set of market classes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainFilter {
+    pub field: String,
+    pub values: Vec<serde_json::Value>,
+}
+
+/// Orders the results by the value of a field. Use the field enums of the datasets (e.g.
+/// [`ConsolidatedShortInterestField`]) or the FINRA field names to identify the field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortField {
+    pub field: String,
+    pub descending: bool,
+}
+
+impl SortField {
+    pub fn ascending(field: impl AsRef<str>) -> Self {
+        Self {
+            field: field.as_ref().to_string(),
+            descending: false,
         }
-        if let Some(ref date_range) = self.date_range {
-            map.serialize_entry(
-                "dateRangeFilters",
-                &AsSeq(ConsolidatedShortInterestQueryDateRange(date_range.clone())),
-            )?;
+    }
+
+    pub fn descending(field: impl AsRef<str>) -> Self {
+        Self {
+            field: field.as_ref().to_string(),
+            descending: true,
         }
+    }
+}
 
-        if let Some(ref symbol) = self.symbol {
-            map.serialize_entry(
-                "compareFilters",
-                &AsSeq(ConsolidatedShortInterestQuerySymbolFilter(symbol)),
-            )?;
+impl DateRangeFilter {
+    pub(crate) fn new(field: impl Into<String>, range: &Range<Date>) -> Self {
+        Self {
+            field: field.into(),
+            range: range.clone(),
         }
+    }
+}
 
-        map.serialize_entry("limit", &self.limit)?;
-        map.serialize_entry("offset", &self.offset)?;
+impl CompareFilter {
+    pub fn new(
+        field: impl AsRef<str>,
+        compare_type: CompareType,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        Self {
+            field: field.as_ref().to_string(),
+            value: value.into(),
+            compare_type,
+        }
+    }
 
-        map.end()
+    /// Matches the records with the value of the `field` equal to the `value`.
+    pub fn equal(field: impl AsRef<str>, value: impl Into<serde_json::Value>) -> Self {
+        Self::new(field, CompareType::Equal, value)
     }
 }
 
-impl<T> Serialize for AsSeq<T>
-where
-    T: Serialize,
-{
+impl DomainFilter {
+    pub fn new<V: Into<serde_json::Value>>(
+        field: impl AsRef<str>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        Self {
+            field: field.as_ref().to_string(),
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Serialize for DateRangeFilter {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut seq = serializer.serialize_seq(Some(1))?;
+        let mut map = serializer.serialize_map(Some(3))?;
+
+        map.serialize_entry("fieldName", &self.field)?;
 
-        seq.serialize_element(&self.0)?;
+        map.serialize_entry("startDate", &format_date(&self.range.start))?;
+        map.serialize_entry("endDate", &format_date(&self.range.end))?;
 
-        seq.end()
+        map.end()
     }
 }
 
-impl Serialize for ConsolidatedShortInterestQueryDateRange {
+impl Serialize for CompareFilter {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         let mut map = serializer.serialize_map(Some(3))?;
-
-        map.serialize_entry("fieldName", "settlementDate")?;
-
-        let start = self.0.start;
-        let end = self.0.end;
-        map.serialize_entry(
-            "startDate",
-            &format!(
-                "{}-{:02}-{:02}",
-                start.year(),
-                start.month() as u8,
-                start.day()
-            ),
-        )?;
-        map.serialize_entry(
-            "endDate",
-            &format!("{}-{:02}-{:02}", end.year(), end.month() as u8, end.day()),
-        )?;
+        map.serialize_entry("fieldName", &self.field)?;
+        map.serialize_entry("fieldValue", &self.value)?;
+        map.serialize_entry("compareType", &self.compare_type)?;
 
         map.end()
     }
 }
 
-impl<'a> Serialize for ConsolidatedShortInterestQuerySymbolFilter<'a> {
+impl Serialize for SortField {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut map = serializer.serialize_map(Some(3))?;
-        map.serialize_entry("fieldName", "symbolCode")?;
-        map.serialize_entry("fieldValue", self.0)?;
-        map.serialize_entry("compareType", "EQUAL")?;
+        let direction = if self.descending { "-" } else { "+" };
+        serializer.serialize_str(&format!("{}{}", direction, self.field))
+    }
+}
+
+impl Serialize for DomainFilter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("fieldName", &self.field)?;
+        map.serialize_entry("values", &self.values)?;
 
         map.end()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn consolidated_short_interest_query_serialization() {
+        let query = ConsolidatedShortInterestQuery::new(
+            Some(vec![
+                ConsolidatedShortInterestField::SymbolCode,
+                ConsolidatedShortInterestField::SettlementDate,
+            ]),
+            Some(date!(2024 - 01 - 01)..date!(2024 - 02 - 01)),
+            Some("BDRBF".to_string()),
+        );
+
+        assert_eq!(
+            serde_json::json!({
+                "fields": ["symbolCode", "settlementDate"],
+                "dateRangeFilters": [{
+                    "fieldName": "settlementDate",
+                    "startDate": "2024-01-01",
+                    "endDate": "2024-02-01",
+                }],
+                "compareFilters": [{
+                    "fieldName": "symbolCode",
+                    "fieldValue": "BDRBF",
+                    "compareType": "EQUAL",
+                }],
+                "limit": 1000,
+                "offset": 0,
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+    }
+
+    #[test]
+    fn compare_filters_and_sort_serialization() {
+        let mut query = ConsolidatedShortInterestQuery::new(None, None, Some("AAPL".to_string()));
+        query.compare_filters.push(CompareFilter::new(
+            ConsolidatedShortInterestField::CurrentShortPositionQuantity,
+            CompareType::Gte,
+            1_000_000,
+        ));
+        query.sort = vec![
+            SortField::descending(ConsolidatedShortInterestField::SettlementDate),
+            SortField::ascending(ConsolidatedShortInterestField::SymbolCode),
+        ];
+
+        assert_eq!(
+            serde_json::json!({
+                "compareFilters": [{
+                    "fieldName": "symbolCode",
+                    "fieldValue": "AAPL",
+                    "compareType": "EQUAL",
+                }, {
+                    "fieldName": "currentShortPositionQuantity",
+                    "fieldValue": 1_000_000,
+                    "compareType": "GTE",
+                }],
+                "sortFields": ["-settlementDate", "+symbolCode"],
+                "limit": 1000,
+                "offset": 0,
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+    }
+
+    #[test]
+    fn multiple_symbols_serialized_as_domain_filter() {
+        let mut query = ConsolidatedShortInterestQuery::with_symbols(
+            None,
+            None,
+            vec!["AAPL".to_string(), "MSFT".to_string()],
+        );
+        query.domain_filters.push(DomainFilter::new(
+            ConsolidatedShortInterestField::MarketClassCode,
+            ["NYSE", "NNM"],
+        ));
+
+        assert_eq!(
+            serde_json::json!({
+                "domainFilters": [{
+                    "fieldName": "symbolCode",
+                    "values": ["AAPL", "MSFT"],
+                }, {
+                    "fieldName": "marketClassCode",
+                    "values": ["NYSE", "NNM"],
+                }],
+                "limit": 1000,
+                "offset": 0,
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+    }
+}
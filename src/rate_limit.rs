@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use futures::{channel::mpsc, StreamExt};
+
+use crate::rt;
+
+#[cfg(not(feature = "tokio"))]
+use futures::lock::Mutex;
+
+#[cfg(feature = "tokio")]
+use tokio::sync::Mutex;
+
+/// Limits the rate of the requests sent to FINRA for the pages of the results, so that long
+/// pagination runs don't exceed the request quota of the credentials.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit {
+    /// The maximum number of the requests sent per second. Unlimited if `None`.
+    pub requests_per_second: Option<f64>,
+    /// The maximum number of the requests in flight at the same time. Unlimited if `None`.
+    pub max_concurrent_requests: Option<usize>,
+}
+
+/// Enforces the [`RateLimit`] shared by all the requests of a [`crate::Finra`] instance.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    /// The minimum time between the starts of two requests and the time the next one can start.
+    spacing: Option<(Duration, Mutex<Instant>)>,
+    /// Hands out the permits to send a request. The permits are returned when the requests
+    /// finish.
+    permits: Option<(mpsc::Sender<()>, Mutex<mpsc::Receiver<()>>)>,
+}
+
+/// Allows sending a request. Releases its slot of the concurrent requests when dropped.
+pub(crate) struct Permit(Option<mpsc::Sender<()>>);
+
+impl RateLimiter {
+    pub(crate) fn new(limit: &RateLimit) -> Self {
+        let spacing = limit
+            .requests_per_second
+            .filter(|rps| *rps > 0.0)
+            .map(|rps| {
+                (
+                    Duration::from_secs_f64(1.0 / rps),
+                    Mutex::new(Instant::now()),
+                )
+            });
+
+        let permits = limit.max_concurrent_requests.map(|max| {
+            let max = max.max(1);
+            let (mut tx, rx) = mpsc::channel(max);
+            for _ in 0..max {
+                // cannot fail, the channel has enough capacity and the receiver is alive
+                let _ = tx.try_send(());
+            }
+            (tx, Mutex::new(rx))
+        });
+
+        Self { spacing, permits }
+    }
+
+    /// Waits until a request can be sent according to the limit.
+    pub(crate) async fn acquire(&self) -> Permit {
+        let permit = match &self.permits {
+            Some((tx, rx)) => {
+                // the receiver cannot end because we hold a sender
+                rx.lock().await.next().await;
+                Permit(Some(tx.clone()))
+            }
+            None => Permit(None),
+        };
+
+        if let Some((interval, next)) = &self.spacing {
+            let wait = {
+                let mut next = next.lock().await;
+                let now = Instant::now();
+                let start = (*next).max(now);
+                *next = start + *interval;
+                start - now
+            };
+            if !wait.is_zero() {
+                rt::sleep(wait).await;
+            }
+        }
+
+        permit
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(tx) = &mut self.0 {
+            let _ = tx.try_send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn concurrent_requests_limited() {
+        let limiter = RateLimiter::new(&RateLimit {
+            requests_per_second: None,
+            max_concurrent_requests: Some(2),
+        });
+
+        let first = limiter.acquire().await;
+        let _second = limiter.acquire().await;
+        assert!(limiter.acquire().now_or_never().is_none());
+
+        drop(first);
+        assert!(limiter.acquire().now_or_never().is_some());
+    }
+}
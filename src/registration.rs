@@ -0,0 +1,259 @@
+//! The datasets of the `registration` group, i.e. the registration data of the firms, like the
+//! data available in BrokerCheck.
+//!
+//! The names of the dataset and its fields follow the FINRA documentation of the group. If a query
+//! fails on an unknown field, compare them with [`Finra::field_metadata`] of the `registration`
+//! group and `registeredFirms` dataset.
+
+use std::ops::Range;
+
+use futures::TryStream;
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    dates::finra_date,
+    numbers,
+    query::{dataset_fields, CompareFilter, Query, QueryBody, SortField, MAX_RESULTS_PER_PAGE},
+    Error, Finra, PaginationStrategy, Result,
+};
+
+const GROUP: &str = "registration";
+
+/// A firm registered with FINRA.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct FirmRecord {
+    /// The Central Registration Depository number identifying the firm.
+    #[serde(rename = "firmCRDNumber", deserialize_with = "numbers::deserialize")]
+    pub firm_crd_number: u64,
+
+    #[serde(rename = "firmName")]
+    pub firm_name: String,
+
+    /// The SEC file number of the firm, if it is registered with the SEC.
+    #[serde(rename = "secNumber")]
+    pub sec_number: Option<String>,
+
+    #[serde(rename = "registrationStatus")]
+    pub registration_status: String,
+
+    #[serde(rename = "registrationDate", with = "finra_date")]
+    pub registration_date: Option<Date>,
+
+    #[serde(rename = "mainOfficeCity")]
+    pub main_office_city: String,
+
+    #[serde(rename = "mainOfficeState")]
+    pub main_office_state: String,
+
+    #[serde(rename = "mainOfficeCountry")]
+    pub main_office_country: String,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the firm query results.
+    FirmField {
+        FirmCrdNumber => "firmCRDNumber",
+        FirmName => "firmName",
+        SecNumber => "secNumber",
+        RegistrationStatus => "registrationStatus",
+        RegistrationDate => "registrationDate",
+        MainOfficeCity => "mainOfficeCity",
+        MainOfficeState => "mainOfficeState",
+        MainOfficeCountry => "mainOfficeCountry",
+    }
+}
+
+/// The query of the registered firms. Use the builder methods to narrow it down.
+#[derive(Debug, Clone)]
+pub struct FirmQuery {
+    fields: Option<Vec<FirmField>>,
+    crd_number: Option<u64>,
+    firm_name: Option<String>,
+    sort: Vec<SortField>,
+    pagination: PaginationStrategy,
+    limit: u64,
+    offset: u64,
+}
+
+impl Default for FirmQuery {
+    fn default() -> Self {
+        Self {
+            fields: None,
+            crd_number: None,
+            firm_name: None,
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl FirmQuery {
+    /// Creates a query of all the fields of all the firms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the fields included in the results.
+    pub fn fields(mut self, fields: Vec<FirmField>) -> Self {
+        self.fields = Some(fields);
+        self
+    }
+
+    /// Limits the results to the firm with the CRD number.
+    pub fn crd_number(mut self, crd_number: u64) -> Self {
+        self.crd_number = Some(crd_number);
+        self
+    }
+
+    /// Limits the results to the firms with exactly the name, as registered.
+    pub fn firm_name(mut self, firm_name: impl Into<String>) -> Self {
+        self.firm_name = Some(firm_name.into());
+        self
+    }
+
+    /// Orders the results by the field. When called multiple times, the results are ordered by
+    /// the fields in the order of the calls.
+    pub fn sort(mut self, sort: SortField) -> Self {
+        self.sort.push(sort);
+        self
+    }
+
+    /// Sets how the results are fetched from FINRA.
+    pub fn pagination(mut self, pagination: PaginationStrategy) -> Self {
+        self.pagination = pagination;
+        self
+    }
+}
+
+impl Query for FirmQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(mut self, by: u64) -> Self {
+        self.offset += by;
+        self
+    }
+
+    fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    fn default_date_range(&mut self, _date_range: Range<Date>) {
+        // the firms are not a time series
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        FirmField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        FirmField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        vec![]
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        None
+    }
+
+    fn set_date_range(&mut self, _date_range: Range<Date>) {}
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+}
+
+impl Serialize for FirmQuery {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: Vec::new(),
+            compare_filters: self
+                .crd_number
+                .iter()
+                .map(|n| CompareFilter::equal(FirmField::FirmCrdNumber, *n))
+                .chain(
+                    self.firm_name
+                        .iter()
+                        .map(|n| CompareFilter::equal(FirmField::FirmName, n.as_str())),
+                )
+                .collect(),
+            domain_filters: Vec::new(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Finra {
+    /// Queries the firms registered with FINRA. Use the `query` to look up the firms by their CRD
+    /// numbers or names.
+    pub async fn firms(
+        &self,
+        query: FirmQuery,
+    ) -> Result<impl TryStream<Ok = FirmRecord, Error = Error>> {
+        self.fetch(GROUP, "registeredFirms", query).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filtered_by_crd_number_and_name() {
+        let query = FirmQuery::new()
+            .crd_number(7691)
+            .firm_name("ACME SECURITIES")
+            .fields(vec![FirmField::FirmCrdNumber, FirmField::FirmName]);
+
+        let json = serde_json::to_value(&query).unwrap();
+
+        assert_eq!(
+            serde_json::json!(["firmCRDNumber", "firmName"]),
+            json["fields"]
+        );
+        assert_eq!("firmCRDNumber", json["compareFilters"][0]["fieldName"]);
+        assert_eq!(7691, json["compareFilters"][0]["fieldValue"]);
+        assert_eq!("ACME SECURITIES", json["compareFilters"][1]["fieldValue"]);
+        assert!(json.get("dateRangeFilters").is_none());
+    }
+
+    #[test]
+    fn registration_date_parsed() {
+        let firm: FirmRecord = serde_json::from_value(serde_json::json!({
+            "firmCRDNumber": 7691,
+            "firmName": "ACME SECURITIES",
+            "registrationDate": "1985-03-12",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            Some(time::macros::date!(1985 - 03 - 12)),
+            firm.registration_date
+        );
+        assert_eq!(None, firm.sec_number);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use reqwest::header::{self, HeaderMap};
+
+use crate::Error;
+
+/// Describes how failed requests are retried. The delay between the attempts grows exponentially
+/// from `base_delay` up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one. `1` means no retries.
+    pub max_attempts: u32,
+    /// The delay after the first failed attempt.
+    pub base_delay: Duration,
+    /// The upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+    /// Whether to randomize the delays so that many clients failing at the same time don't retry
+    /// at the same time, too.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The delay to wait after the failed `attempt` (counted from 1) before trying again.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let delay = self.base_delay.saturating_mul(1 << exp).min(self.max_delay);
+
+        if self.jitter {
+            // "equal jitter" - keep at least half of the delay so that the backoff still grows
+            let half = delay / 2;
+            half + half.mul_f64(fastrand::f64())
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+        }
+    }
+}
+
+/// Limits the retries of all the page requests of a job, e.g. a backfill or a bulk query, on top of
+/// the per-request [`RetryPolicy`], so that a long outage fails the job promptly instead of
+/// retrying each of its pages. Once the budget is exhausted, the page requests fail with
+/// [`Error::RetryBudgetExhausted`] on their first transient failure.
+///
+/// The clones of the budget share the spent retries, so the same budget can be used by several
+/// [`crate::Finra`] instances. See [`crate::FinraBuilder::retry_budget`].
+#[derive(Debug, Clone, Default)]
+pub struct RetryBudget {
+    max_retries: Option<u64>,
+    max_retry_time: Option<Duration>,
+    /// The number of the retries and the total delay before them spent so far.
+    spent: Arc<Mutex<(u64, Duration)>>,
+}
+
+impl RetryBudget {
+    /// An unlimited budget. Use the `with_*` methods to set the limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the total number of the retries.
+    pub fn with_max_retries(self, max_retries: u64) -> Self {
+        Self {
+            max_retries: Some(max_retries),
+            ..self
+        }
+    }
+
+    /// Limits the total time spent waiting before the retries.
+    pub fn with_max_retry_time(self, max_retry_time: Duration) -> Self {
+        Self {
+            max_retry_time: Some(max_retry_time),
+            ..self
+        }
+    }
+
+    /// The number of the retries made so far.
+    pub fn retries(&self) -> u64 {
+        self.spent.lock().unwrap_or_else(PoisonError::into_inner).0
+    }
+
+    /// The total time spent waiting before the retries so far.
+    pub fn retry_time(&self) -> Duration {
+        self.spent.lock().unwrap_or_else(PoisonError::into_inner).1
+    }
+
+    /// Spends a retry after the `delay` from the budget, unless that would exceed it.
+    pub(crate) fn spend(&self, delay: Duration) -> bool {
+        let mut spent = self.spent.lock().unwrap_or_else(PoisonError::into_inner);
+        let (retries, time) = (spent.0 + 1, spent.1.saturating_add(delay));
+        if self.max_retries.is_some_and(|max| retries > max)
+            || self.max_retry_time.is_some_and(|max| time > max)
+        {
+            return false;
+        }
+        *spent = (retries, time);
+        true
+    }
+}
+
+/// The failure of a single attempt of a request.
+pub(crate) enum Failure {
+    /// It makes sense to try again, optionally after the time requested by the server.
+    Transient(Error, Option<Duration>),
+    Fatal(Error),
+}
+
+impl Failure {
+    pub(crate) fn from_http_error(e: reqwest::Error) -> Self {
+        if e.is_connect() || e.is_timeout() || e.is_request() || e.is_body() {
+            Self::Transient(e.into(), None)
+        } else {
+            Self::Fatal(e.into())
+        }
+    }
+}
+
+/// Parses the `Retry-After` header, which can either contain the number of seconds to wait or
+/// the date to wait until.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    httpdate::parse_http_date(value)
+        .ok()
+        .map(|at| at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn budget_shared_by_clones() {
+        let budget = RetryBudget::new()
+            .with_max_retries(3)
+            .with_max_retry_time(Duration::from_secs(10));
+        let clone = budget.clone();
+
+        assert!(budget.spend(Duration::from_secs(4)));
+        assert!(clone.spend(Duration::from_secs(4)));
+        assert!(!budget.spend(Duration::from_secs(4)));
+        assert!(clone.spend(Duration::from_secs(1)));
+        assert!(!clone.spend(Duration::ZERO));
+
+        assert_eq!(3, budget.retries());
+        assert_eq!(Duration::from_secs(9), budget.retry_time());
+    }
+}
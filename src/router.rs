@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{Error, Finra, Result};
+
+/// Creates the instance of a tenant, if the tenant is known.
+type Factory<K> = Box<dyn Fn(&K) -> Option<Finra> + Send + Sync>;
+
+/// Routes the calls of multiple tenants, e.g. the customers of a service, each to its own
+/// [`Finra`] instance authenticating using the credentials of the tenant. The instances are
+/// created on the first use of each tenant, so each tenant has its own tokens, rate limits and
+/// metrics, isolated from the others.
+pub struct FinraRouter<K> {
+    factory: Factory<K>,
+    instances: Mutex<HashMap<K, Arc<Finra>>>,
+}
+
+impl<K: Clone + Eq + Hash + Debug> FinraRouter<K> {
+    /// Creates a router creating the instances of the tenants using the `factory`. The factory
+    /// returns `None` for the unknown tenants.
+    pub fn new(factory: impl Fn(&K) -> Option<Finra> + Send + Sync + 'static) -> Self {
+        Self {
+            factory: Box::new(factory),
+            instances: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The instance of the `tenant`, created if it doesn't exist yet. Fails with
+    /// [`Error::UnknownTenant`] if the factory doesn't know the tenant.
+    pub fn get(&self, tenant: &K) -> Result<Arc<Finra>> {
+        let mut instances = self
+            .instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(finra) = instances.get(tenant) {
+            return Ok(finra.clone());
+        }
+
+        let finra = Arc::new(
+            (self.factory)(tenant).ok_or_else(|| Error::UnknownTenant(format!("{:?}", tenant)))?,
+        );
+        instances.insert(tenant.clone(), finra.clone());
+        Ok(finra)
+    }
+
+    /// Uses the `finra` instance for the `tenant` instead of creating one using the factory.
+    pub fn insert(&self, tenant: K, finra: Finra) {
+        let mut instances = self
+            .instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        instances.insert(tenant, Arc::new(finra));
+    }
+
+    /// Forgets the instance of the `tenant`, e.g. after its credentials changed. The next call of
+    /// [`FinraRouter::get`] creates a new one. Returns the forgotten instance, if any.
+    pub fn remove(&self, tenant: &K) -> Option<Arc<Finra>> {
+        let mut instances = self
+            .instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        instances.remove(tenant)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use reqwest::ClientBuilder;
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+
+    #[test]
+    fn instances_created_once_per_tenant() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let router = FinraRouter::new(move |tenant: &&str| {
+            (*tenant != "unknown").then(|| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Finra::with_token(
+                    Arc::new(ClientBuilder::new),
+                    tenant.to_string(),
+                    OffsetDateTime::now_utc() + Duration::hours(1),
+                    false,
+                )
+            })
+        });
+
+        let a = router.get(&"a").unwrap();
+        assert!(Arc::ptr_eq(&a, &router.get(&"a").unwrap()));
+        assert!(!Arc::ptr_eq(&a, &router.get(&"b").unwrap()));
+        assert_eq!(2, created.load(Ordering::SeqCst));
+        assert!(matches!(
+            router.get(&"unknown"),
+            Err(Error::UnknownTenant(_))
+        ));
+
+        router.remove(&"a");
+        router.get(&"a").unwrap();
+        assert_eq!(3, created.load(Ordering::SeqCst));
+    }
+}
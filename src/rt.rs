@@ -0,0 +1,42 @@
+//! The bits that depend on the async runtime used.
+
+use std::{future::Future, pin::pin, time::Duration};
+
+use futures::future::{self, Either};
+
+/// Waits for the given duration without blocking the executor.
+#[cfg(feature = "tokio")]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+/// Waits for the given duration without blocking the executor.
+#[cfg(not(feature = "tokio"))]
+pub(crate) async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await
+}
+
+/// Runs the `future` to completion unless the `duration` passes first, in which case it is
+/// dropped and `None` is returned.
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    match future::select(pin!(future), pin!(sleep(duration))).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+// the timers must work outside of the tokio runtime unless the tokio feature is enabled
+#[cfg(all(test, not(feature = "tokio")))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timers_run_on_any_executor() {
+        futures::executor::block_on(async {
+            let pending = future::pending::<()>();
+            assert_eq!(None, timeout(Duration::from_millis(10), pending).await);
+            let ready = async { 42 };
+            assert_eq!(Some(42), timeout(Duration::from_secs(10), ready).await);
+        });
+    }
+}
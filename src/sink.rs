@@ -0,0 +1,191 @@
+//! Writing the streams of the records into files.
+//!
+//! The records are written as they arrive, so only a bounded number of them is held in memory
+//! regardless of the size of the results. They are written into a temporary file next to the
+//! target file, which replaces the target file only once all the records are written, so a failed
+//! stream leaves any existing file intact. The files are written using the blocking standard
+//! library I/O, buffered, which is fine for the local files but may stall the executor on a slow
+//! network filesystem.
+
+use std::path::Path;
+
+use futures::{TryStream, TryStreamExt};
+
+use crate::{files::PendingFile, Error, Result};
+
+#[cfg(feature = "parquet")]
+use crate::ArrowRecord;
+#[cfg(feature = "serialize")]
+use crate::ExportTransforms;
+
+/// The number of records in the batches and the row groups written to Parquet files.
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH: usize = 64 * 1024;
+
+fn export_error(e: impl std::fmt::Display) -> Error {
+    Error::Export(e.to_string())
+}
+
+fn create(path: &Path) -> Result<PendingFile> {
+    PendingFile::create(path).map_err(|e| Error::Export(format!("{}: {}", path.display(), e)))
+}
+
+fn commit(file: PendingFile) -> Result<()> {
+    let path = file.path().display().to_string();
+    file.commit()
+        .map_err(|e| Error::Export(format!("{}: {}", path, e)))
+}
+
+/// Writes the records of the stream into a CSV file at `path`, replacing any existing file. The
+/// columns are named by the FINRA names of the fields. Returns the number of the records written.
+///
+/// ```no_run
+/// # async fn example(finra: finra_rs::Finra) -> finra_rs::Result<()> {
+/// use finra_rs::{sink, ConsolidatedShortInterestQuery};
+///
+/// let records = finra
+///     .consolidated_short_interest(ConsolidatedShortInterestQuery::new(None, None, None))
+///     .await?;
+/// sink::write_csv(records, "short_interest.csv").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "serialize")]
+pub async fn write_csv<S, T>(stream: S, path: impl AsRef<Path>) -> Result<u64>
+where
+    S: TryStream<Ok = T, Error = Error>,
+    T: serde::Serialize,
+{
+    let mut writer = csv::Writer::from_writer(create(path.as_ref())?);
+    let mut stream = Box::pin(stream.into_stream());
+    let mut count = 0;
+    while let Some(record) = stream.try_next().await? {
+        writer.serialize(&record).map_err(export_error)?;
+        count += 1;
+    }
+    commit(writer.into_inner().map_err(export_error)?)?;
+    Ok(count)
+}
+
+/// Like [`write_csv`], but applies the `transforms` to the fields of the records.
+#[cfg(feature = "serialize")]
+pub async fn write_csv_with<S, T>(
+    stream: S,
+    path: impl AsRef<Path>,
+    transforms: &ExportTransforms,
+) -> Result<u64>
+where
+    S: TryStream<Ok = T, Error = Error>,
+    T: serde::Serialize,
+{
+    let mut writer = csv::Writer::from_writer(create(path.as_ref())?);
+    let mut stream = Box::pin(transforms.apply_stream(stream).into_stream());
+    let mut count = 0;
+    while let Some(row) = stream.try_next().await? {
+        if count == 0 {
+            writer
+                .write_record(row.iter().map(|(name, _)| name))
+                .map_err(export_error)?;
+        }
+        writer
+            .write_record(row.iter().map(|(_, value)| value))
+            .map_err(export_error)?;
+        count += 1;
+    }
+    commit(writer.into_inner().map_err(export_error)?)?;
+    Ok(count)
+}
+
+/// Writes the records of the stream into a Parquet file at `path`, replacing any existing file.
+/// The columns are as described in [`ArrowRecord`]. Returns the number of the records written.
+#[cfg(feature = "parquet")]
+pub async fn write_parquet<S, T>(stream: S, path: impl AsRef<Path>) -> Result<u64>
+where
+    S: TryStream<Ok = T, Error = Error>,
+    T: ArrowRecord,
+{
+    use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(PARQUET_BATCH)
+        .build();
+    let mut writer = ArrowWriter::try_new(create(path.as_ref())?, T::schema(), Some(properties))
+        .map_err(export_error)?;
+
+    let mut batches = Box::pin(crate::record_batches(stream, PARQUET_BATCH).into_stream());
+    let mut count = 0;
+    while let Some(batch) = batches.try_next().await? {
+        writer.write(&batch).map_err(export_error)?;
+        count += batch.num_rows() as u64;
+    }
+    commit(writer.into_inner().map_err(export_error)?)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+
+    use crate::ConsolidatedShortInterest;
+
+    use super::*;
+
+    fn records() -> Vec<Result<ConsolidatedShortInterest>> {
+        ["AAPL", "MSFT"]
+            .into_iter()
+            .map(|symbol| {
+                Ok(ConsolidatedShortInterest {
+                    symbol_code: symbol.to_string(),
+                    current_short_position_quantity: 42,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "serialize")]
+    #[tokio::test]
+    async fn records_written_to_csv() {
+        let path = std::env::temp_dir().join(format!("finra-sink-{}.csv", std::process::id()));
+
+        let count = write_csv(stream::iter(records()), &path).await.unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, count);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with("stockSplitFlag,"));
+        assert!(lines[1].contains(",42,") && lines[1].contains("AAPL"));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn records_written_to_parquet() {
+        use arrow_array::{cast::AsArray, types::UInt64Type};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = std::env::temp_dir().join(format!("finra-sink-{}.parquet", std::process::id()));
+
+        let count = write_parquet(stream::iter(records()), &path).await.unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, count);
+        assert_eq!(2, batches[0].num_rows());
+        let symbols = batches[0]
+            .column_by_name("symbolCode")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!("MSFT", symbols.value(1));
+        let quantities = batches[0]
+            .column_by_name("currentShortPositionQuantity")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(42, quantities.value(0));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    ops::Range,
+    sync::Arc,
+};
+
+use futures::{TryStream, TryStreamExt};
+use time::Date;
+
+use crate::ConsolidatedShortInterest;
+
+/// An immutable collection of the consolidated short interest records, indexed by the symbol and
+/// the settlement date.
+///
+/// Cloning the snapshot is cheap - all the clones share the same records, so a snapshot fetched
+/// once can be handed out to any number of reader threads.
+#[derive(Debug, Clone, Default)]
+pub struct ShortInterestSnapshot(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The records sorted by the symbol and then by the settlement date.
+    records: Vec<ConsolidatedShortInterest>,
+    /// The range of the records of each symbol.
+    symbols: BTreeMap<String, Range<usize>>,
+    /// The indices of the records of each settlement date.
+    dates: BTreeMap<Date, Vec<u32>>,
+    /// The replaced versions of the records of each symbol and settlement date, oldest first.
+    revisions: BTreeMap<String, BTreeMap<Date, Vec<ConsolidatedShortInterest>>>,
+}
+
+impl ShortInterestSnapshot {
+    /// Collects the records of the `stream` into a snapshot.
+    pub async fn from_stream<S>(stream: S) -> Result<Self, S::Error>
+    where
+        S: TryStream<Ok = ConsolidatedShortInterest>,
+    {
+        let records: Vec<_> = stream.try_collect().await?;
+        Ok(Self::from_records(records))
+    }
+
+    /// Creates a snapshot of the `records`.
+    pub fn from_records(records: Vec<ConsolidatedShortInterest>) -> Self {
+        Self::with_revisions(records, BTreeMap::new())
+    }
+
+    /// Creates a new snapshot with the `records` fetched later, e.g. by a periodic refresh. They
+    /// replace the records of the same symbol and settlement date in this snapshot. The replaced
+    /// records that differ from their replacements, usually because FINRA revised them (see
+    /// [`ConsolidatedShortInterest::revision_flag`]), are retained and can be obtained using
+    /// [`Self::revisions_of`].
+    pub fn update(&self, records: impl IntoIterator<Item = ConsolidatedShortInterest>) -> Self {
+        let mut revisions = self.0.revisions.clone();
+        let mut current: BTreeMap<_, _> = self
+            .0
+            .records
+            .iter()
+            .map(|r| ((r.symbol_code.clone(), r.settlement_date), r.clone()))
+            .collect();
+
+        for r in records {
+            match current.entry((r.symbol_code.clone(), r.settlement_date)) {
+                Entry::Vacant(e) => {
+                    e.insert(r);
+                }
+                Entry::Occupied(mut e) if *e.get() != r => {
+                    let old = e.insert(r);
+                    if let Some(date) = old.settlement_date {
+                        revisions
+                            .entry(old.symbol_code.clone())
+                            .or_default()
+                            .entry(date)
+                            .or_default()
+                            .push(old);
+                    }
+                }
+                Entry::Occupied(_) => {}
+            }
+        }
+
+        Self::with_revisions(current.into_values().collect(), revisions)
+    }
+
+    fn with_revisions(
+        mut records: Vec<ConsolidatedShortInterest>,
+        revisions: BTreeMap<String, BTreeMap<Date, Vec<ConsolidatedShortInterest>>>,
+    ) -> Self {
+        records.sort_by(|a, b| {
+            a.symbol_code
+                .cmp(&b.symbol_code)
+                .then(a.settlement_date.cmp(&b.settlement_date))
+        });
+        records.shrink_to_fit();
+
+        let mut symbols = BTreeMap::<String, Range<usize>>::new();
+        let mut dates = BTreeMap::<Date, Vec<u32>>::new();
+        for (i, r) in records.iter().enumerate() {
+            match symbols.get_mut(&r.symbol_code) {
+                Some(range) => range.end = i + 1,
+                None => {
+                    symbols.insert(r.symbol_code.clone(), i..i + 1);
+                }
+            }
+            if let Some(date) = r.settlement_date {
+                dates.entry(date).or_default().push(i as u32);
+            }
+        }
+
+        Self(Arc::new(Inner {
+            records,
+            symbols,
+            dates,
+            revisions,
+        }))
+    }
+
+    /// The number of the records in the snapshot.
+    pub fn len(&self) -> usize {
+        self.0.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.records.is_empty()
+    }
+
+    /// All the records, ordered by the symbol and then by the settlement date.
+    pub fn records(&self) -> &[ConsolidatedShortInterest] {
+        &self.0.records
+    }
+
+    /// The symbols in the snapshot in the alphabetical order.
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.0.symbols.keys().map(String::as_str)
+    }
+
+    /// The settlement dates in the snapshot in the chronological order.
+    pub fn settlement_dates(&self) -> impl Iterator<Item = Date> + '_ {
+        self.0.dates.keys().copied()
+    }
+
+    /// The records of the `symbol` ordered by the settlement date. Empty if the symbol is not in
+    /// the snapshot.
+    pub fn symbol(&self, symbol: &str) -> &[ConsolidatedShortInterest] {
+        self.0
+            .symbols
+            .get(symbol)
+            .map_or(&[], |range| &self.0.records[range.clone()])
+    }
+
+    /// The records of all the symbols on the settlement `date`, ordered by the symbol.
+    pub fn settlement_date(
+        &self,
+        date: Date,
+    ) -> impl Iterator<Item = &ConsolidatedShortInterest> + '_ {
+        self.0
+            .dates
+            .get(&date)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.0.records[*i as usize])
+    }
+
+    /// The record of the `symbol` on the settlement `date`, if any.
+    pub fn get(&self, symbol: &str, date: Date) -> Option<&ConsolidatedShortInterest> {
+        let records = self.symbol(symbol);
+        records
+            .binary_search_by(|r| r.settlement_date.cmp(&Some(date)))
+            .ok()
+            .map(|i| &records[i])
+    }
+
+    /// The earlier versions of the record of the `symbol` on the settlement `date` that were
+    /// replaced by [`Self::update`], oldest first. Empty if the record was never revised.
+    pub fn revisions_of(&self, symbol: &str, date: Date) -> &[ConsolidatedShortInterest] {
+        self.0
+            .revisions
+            .get(symbol)
+            .and_then(|dates| dates.get(&date))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+impl FromIterator<ConsolidatedShortInterest> for ShortInterestSnapshot {
+    fn from_iter<I: IntoIterator<Item = ConsolidatedShortInterest>>(iter: I) -> Self {
+        Self::from_records(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::date;
+
+    fn record(symbol: &str, date: Date) -> ConsolidatedShortInterest {
+        ConsolidatedShortInterest {
+            symbol_code: symbol.to_string(),
+            settlement_date: Some(date),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn indexed_by_symbol_and_date() {
+        let snapshot: ShortInterestSnapshot = [
+            record("MSFT", date!(2024 - 01 - 31)),
+            record("AAPL", date!(2024 - 01 - 31)),
+            record("AAPL", date!(2024 - 01 - 12)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(snapshot.symbols().collect::<Vec<_>>(), ["AAPL", "MSFT"]);
+        assert_eq!(snapshot.symbol("AAPL").len(), 2);
+        assert!(snapshot.symbol("GME").is_empty());
+        assert_eq!(
+            snapshot
+                .settlement_date(date!(2024 - 01 - 31))
+                .map(|r| r.symbol_code.as_str())
+                .collect::<Vec<_>>(),
+            ["AAPL", "MSFT"]
+        );
+        assert!(snapshot.get("AAPL", date!(2024 - 01 - 12)).is_some());
+        assert!(snapshot.get("MSFT", date!(2024 - 01 - 12)).is_none());
+    }
+
+    #[test]
+    fn revisions_retained_on_update() {
+        let original = record("AAPL", date!(2024 - 01 - 31));
+        let revised = ConsolidatedShortInterest {
+            current_short_position_quantity: 42,
+            revision_flag: Some("R".to_string()),
+            ..original.clone()
+        };
+
+        let snapshot = ShortInterestSnapshot::from_records(vec![original.clone()])
+            .update([revised.clone(), record("MSFT", date!(2024 - 01 - 31))]);
+        let snapshot = snapshot.update([record("MSFT", date!(2024 - 01 - 31))]);
+
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot.get("AAPL", date!(2024 - 01 - 31)), Some(&revised));
+        assert_eq!(
+            snapshot.revisions_of("AAPL", date!(2024 - 01 - 31)),
+            [original]
+        );
+        assert!(snapshot
+            .revisions_of("MSFT", date!(2024 - 01 - 31))
+            .is_empty());
+    }
+}
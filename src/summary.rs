@@ -0,0 +1,164 @@
+use std::collections::{BTreeSet, HashSet};
+
+use futures::{TryStream, TryStreamExt};
+use time::Date;
+
+use crate::TimeSeriesRecord;
+
+/// The statistics of the records of a stream, produced by [`summarize`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary<F> {
+    /// The number of the records.
+    pub records: u64,
+    /// The number of the distinct symbols.
+    pub symbols: usize,
+    /// The earliest date of the records, if any has a date.
+    pub first_date: Option<Date>,
+    /// The latest date of the records, if any has a date.
+    pub last_date: Option<Date>,
+    /// The number of the distinct dates of the records.
+    pub dates: usize,
+    /// The number of the records without a date.
+    pub undated: u64,
+    /// The statistics of each of the [`TimeSeriesRecord::numeric_fields`].
+    pub columns: Vec<(F, ColumnSummary)>,
+}
+
+/// The statistics of the values of a numeric field.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColumnSummary {
+    /// The number of the records with a value of the field.
+    pub values: u64,
+    /// The number of the records without a value of the field.
+    pub nulls: u64,
+    /// The number of the zero values.
+    pub zeros: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// The arithmetic mean of the values.
+    pub mean: Option<f64>,
+}
+
+impl ColumnSummary {
+    fn add(&mut self, value: Option<f64>) {
+        let Some(value) = value else {
+            self.nulls += 1;
+            return;
+        };
+
+        self.values += 1;
+        if value == 0.0 {
+            self.zeros += 1;
+        }
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        // the running mean doesn't overflow like the sum of the large quantities could
+        let mean = self.mean.unwrap_or(0.0);
+        self.mean = Some(mean + (value - mean) / self.values as f64);
+    }
+}
+
+/// Computes the [`Summary`] of the records of the stream, e.g. as a quick sanity check of the
+/// downloaded data. The records are not kept in memory, only the distinct symbols and dates are.
+pub async fn summarize<S, R>(stream: S) -> Result<Summary<R::Field>, S::Error>
+where
+    S: TryStream<Ok = R>,
+    R: TimeSeriesRecord,
+{
+    let fields = R::numeric_fields();
+    let mut columns = vec![ColumnSummary::default(); fields.len()];
+    let mut records = 0;
+    let mut undated = 0;
+    let mut symbols = HashSet::new();
+    let mut dates = BTreeSet::new();
+
+    let mut stream = Box::pin(stream.into_stream());
+    while let Some(r) = stream.try_next().await? {
+        records += 1;
+        if !symbols.contains(r.symbol()) {
+            symbols.insert(r.symbol().to_string());
+        }
+        match r.date() {
+            Some(date) => {
+                dates.insert(date);
+            }
+            None => undated += 1,
+        }
+        for (field, column) in fields.iter().zip(&mut columns) {
+            column.add(r.value(*field));
+        }
+    }
+
+    Ok(Summary {
+        records,
+        symbols: symbols.len(),
+        first_date: dates.first().copied(),
+        last_date: dates.last().copied(),
+        dates: dates.len(),
+        undated,
+        columns: fields.into_iter().zip(columns).collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+    use time::macros::date;
+
+    use super::*;
+
+    /// A record with a single optional numeric field, so that the nulls are covered, too.
+    struct Point(&'static str, Option<Date>, Option<u64>);
+
+    impl TimeSeriesRecord for Point {
+        type Field = ();
+
+        fn symbol(&self) -> &str {
+            self.0
+        }
+
+        fn date(&self) -> Option<Date> {
+            self.1
+        }
+
+        fn value(&self, _field: ()) -> Option<f64> {
+            self.2.map(|v| v as f64)
+        }
+
+        fn numeric_fields() -> Vec<()> {
+            vec![()]
+        }
+    }
+
+    #[tokio::test]
+    async fn statistics_of_columns() {
+        let records = [
+            Point("A", Some(date!(2024 - 01 - 01)), Some(2)),
+            Point("A", Some(date!(2024 - 01 - 08)), Some(0)),
+            Point("B", Some(date!(2024 - 01 - 01)), None),
+            Point("B", None, Some(4)),
+        ];
+
+        let summary = summarize(stream::iter(records.map(Ok::<_, ()>)))
+            .await
+            .unwrap();
+
+        assert_eq!(4, summary.records);
+        assert_eq!(2, summary.symbols);
+        assert_eq!(Some(date!(2024 - 01 - 01)), summary.first_date);
+        assert_eq!(Some(date!(2024 - 01 - 08)), summary.last_date);
+        assert_eq!((2, 1), (summary.dates, summary.undated));
+        let ((), values) = summary.columns[0];
+        assert_eq!(
+            ColumnSummary {
+                values: 3,
+                nulls: 1,
+                zeros: 1,
+                min: Some(0.0),
+                max: Some(4.0),
+                mean: Some(2.0),
+            },
+            values
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Checks the symbols used in the queries before they are sent to FINRA. A typo in a symbol
+/// otherwise just results in no data being returned.
+///
+/// Use [`crate::FinraBuilder::symbol_validator`] to set it up.
+pub trait SymbolValidator: Send + Sync {
+    /// Converts the symbol to the form used by FINRA. By default, the symbol is trimmed and
+    /// uppercased.
+    fn normalize(&self, symbol: &str) -> String {
+        symbol.trim().to_uppercase()
+    }
+
+    /// Whether the (normalized) symbol is known to exist.
+    fn is_known(&self, symbol: &str) -> bool;
+}
+
+/// What to do when a query contains a symbol unknown to the [`SymbolValidator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownSymbolPolicy {
+    /// Report [`crate::Warning::UnknownSymbol`] and send the query anyway.
+    #[default]
+    Warn,
+    /// Fail the query with [`crate::Error::UnknownSymbol`].
+    Error,
+}
+
+impl SymbolValidator for HashSet<String> {
+    fn is_known(&self, symbol: &str) -> bool {
+        self.contains(symbol)
+    }
+}
+
+impl SymbolValidator for BTreeSet<String> {
+    fn is_known(&self, symbol: &str) -> bool {
+        self.contains(symbol)
+    }
+}
+
+impl<F> SymbolValidator for F
+where
+    F: Fn(&str) -> bool + Send + Sync,
+{
+    fn is_known(&self, symbol: &str) -> bool {
+        self(symbol)
+    }
+}
+
+/// The separators of the class-share suffixes used by the various data sources, e.g. `BRK.B`,
+/// `BRK/B`, `BRK-B` or `BRK B`.
+const CLASS_SEPARATORS: &[char] = &['.', '/', '-', ' '];
+
+/// Maps between the variants of the symbols used by other data sources and the symbols used by
+/// FINRA. A symbol in a form FINRA doesn't use silently matches no data, so mapping the symbols
+/// before querying, and back when joining the results with other data, avoids the empty results.
+///
+/// The mapping table is provided by the user, e.g. collected from pairs of `(variant, finra)`
+/// symbols. Optionally, the class-share suffixes of the symbols not in the table can be rewritten
+/// to a single convention.
+///
+/// Use [`crate::FinraBuilder::symbol_map`] to apply it to the symbols in the queries.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    to_finra: HashMap<String, String>,
+    from_finra: HashMap<String, String>,
+    class_separator: Option<char>,
+}
+
+impl SymbolMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rewrites the class-share suffix of the symbols not in the table, separated by any of `.`,
+    /// `/`, `-` or a space, to be separated by `separator`, e.g. `BRK/B` to `BRK.B` if `separator`
+    /// is `.`.
+    pub fn class_separator(mut self, separator: char) -> Self {
+        self.class_separator = Some(separator);
+        self
+    }
+
+    /// Maps the `variant` of a symbol to the `finra` symbol and back. The variants are matched
+    /// regardless of the case and the surrounding whitespace.
+    pub fn insert(&mut self, variant: impl Into<String>, finra: impl Into<String>) {
+        let variant = variant.into();
+        let finra = finra.into();
+        self.to_finra.insert(normalize(&variant), finra.clone());
+        self.from_finra.insert(finra, variant);
+    }
+
+    /// The symbol FINRA uses for the `symbol`. If it is not in the table, it is trimmed,
+    /// uppercased and its class-share suffix is rewritten, if configured.
+    pub fn to_finra(&self, symbol: &str) -> String {
+        let symbol = normalize(symbol);
+        if let Some(finra) = self.to_finra.get(&symbol) {
+            return finra.clone();
+        }
+
+        match self.class_separator {
+            Some(separator) => symbol.replace(CLASS_SEPARATORS, &separator.to_string()),
+            None => symbol,
+        }
+    }
+
+    /// The variant of the FINRA `symbol` in the table, or the symbol itself if it is not in the
+    /// table. Useful for joining the results with the data using the other variants.
+    pub fn from_finra<'a>(&'a self, symbol: &'a str) -> &'a str {
+        self.from_finra.get(symbol).map_or(symbol, String::as_str)
+    }
+
+    /// Maps all the `symbols` using [`Self::to_finra`].
+    pub fn to_finra_all<'a>(
+        &'a self,
+        symbols: impl IntoIterator<Item = &'a str> + 'a,
+    ) -> impl Iterator<Item = String> + 'a {
+        symbols.into_iter().map(|s| self.to_finra(s))
+    }
+}
+
+impl<V: Into<String>, F: Into<String>> Extend<(V, F)> for SymbolMap {
+    fn extend<T: IntoIterator<Item = (V, F)>>(&mut self, iter: T) {
+        for (variant, finra) in iter {
+            self.insert(variant, finra);
+        }
+    }
+}
+
+impl<V: Into<String>, F: Into<String>> FromIterator<(V, F)> for SymbolMap {
+    fn from_iter<T: IntoIterator<Item = (V, F)>>(iter: T) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+fn normalize(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn symbols_mapped_both_ways() {
+        let map: SymbolMap = [("goog", "GOOGL"), ("BF-B", "BF.B")].into_iter().collect();
+        let map = map.class_separator('.');
+
+        assert_eq!(map.to_finra(" Goog "), "GOOGL");
+        assert_eq!(map.to_finra("brk/b"), "BRK.B");
+        assert_eq!(map.to_finra("BF-B"), "BF.B");
+        assert_eq!(map.to_finra("MSFT"), "MSFT");
+        assert_eq!(map.from_finra("GOOGL"), "goog");
+        assert_eq!(map.from_finra("BRK.B"), "BRK.B");
+    }
+}
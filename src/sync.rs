@@ -0,0 +1,217 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use futures::{future, stream, StreamExt, TryStream, TryStreamExt};
+use time::{Date, Duration};
+
+use crate::{ConsolidatedShortInterest, Error};
+
+/// The checkpoint of an incremental fetch of the consolidated short interest - the latest
+/// settlement date of the records already stored. Returned by
+/// [`crate::Finra::sync_consolidated_short_interest`] together with the newer records.
+///
+/// The checkpoint moves to the latest settlement date of the new records only after all of them
+/// are read successfully. If the stream fails or is dropped early, it stays at the date the sync
+/// started from, so that the next sync fetches the same records again rather than skipping the
+/// unread ones.
+///
+/// With the `serialize` feature, the checkpoint can be stored between the runs in the serde
+/// formats, e.g. as `{"version":1,"settlementDate":"2024-01-31"}` in JSON. The format is
+/// versioned: the later releases read the checkpoints stored by the earlier ones, and the fields
+/// they add are ignored by the earlier ones, which only reject the checkpoints of a newer,
+/// incompatible version.
+#[derive(Debug, Clone)]
+pub struct SyncCheckpoint(Arc<Mutex<State>>);
+
+/// The version of the stored [`SyncCheckpoint`]s, increased only by incompatible changes.
+#[cfg(feature = "serialize")]
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// The [`SyncCheckpoint`] as stored.
+#[cfg(feature = "serialize")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredCheckpoint {
+    version: u32,
+    #[serde(rename = "settlementDate", with = "crate::dates::finra_date", default)]
+    settlement_date: Option<Date>,
+}
+
+#[derive(Debug)]
+struct State {
+    checkpoint: Option<Date>,
+    latest: Option<Date>,
+    failed: bool,
+}
+
+impl SyncCheckpoint {
+    fn new(since: Option<Date>) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            checkpoint: since,
+            latest: since,
+            failed: false,
+        })))
+    }
+
+    /// The settlement date to start the next sync from.
+    pub fn get(&self) -> Option<Date> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .checkpoint
+    }
+
+    fn record(&self, record: &Result<ConsolidatedShortInterest, Error>) {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match record {
+            Ok(record) => state.latest = state.latest.max(record.settlement_date),
+            Err(_) => state.failed = true,
+        }
+    }
+
+    fn complete(&self) {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !state.failed {
+            state.checkpoint = state.latest;
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl serde::Serialize for SyncCheckpoint {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredCheckpoint {
+            version: CHECKPOINT_VERSION,
+            settlement_date: self.get(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> serde::Deserialize<'de> for SyncCheckpoint {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredCheckpoint::deserialize(deserializer)?;
+        if stored.version > CHECKPOINT_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported checkpoint version {}, the latest supported is {}",
+                stored.version, CHECKPOINT_VERSION
+            )));
+        }
+        Ok(SyncCheckpoint::new(stored.settlement_date))
+    }
+}
+
+/// The date range of the records settled after `since`, limited to the `date_range` of the query,
+/// if any, or `None` if there are no such dates to query. FINRA includes both ends of the date
+/// ranges.
+pub(crate) fn date_range_since(
+    since: Date,
+    date_range: Option<&Range<Date>>,
+    today: Date,
+) -> Option<Range<Date>> {
+    let start = since.saturating_add(Duration::DAY);
+    let range = match date_range {
+        Some(range) => start.max(range.start)..range.end,
+        None => start..today,
+    };
+    (range.start <= range.end).then_some(range)
+}
+
+/// Keeps only the records settled after `since` and tracks the checkpoint of the records read.
+pub(crate) fn track<S>(
+    records: S,
+    since: Option<Date>,
+) -> (
+    impl TryStream<Ok = ConsolidatedShortInterest, Error = Error>,
+    SyncCheckpoint,
+)
+where
+    S: TryStream<Ok = ConsolidatedShortInterest, Error = Error>,
+{
+    let checkpoint = SyncCheckpoint::new(since);
+    let tracked = checkpoint.clone();
+    let completed = checkpoint.clone();
+
+    let records = records
+        .try_filter(move |r| {
+            future::ready(
+                since.is_none() || r.settlement_date.is_none() || r.settlement_date > since,
+            )
+        })
+        .into_stream()
+        .inspect(move |r| tracked.record(r))
+        .chain(
+            stream::once(future::lazy(move |_| completed.complete()))
+                .filter_map(|()| future::ready(None)),
+        );
+
+    (records, checkpoint)
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    fn settled(date: Date) -> Result<ConsolidatedShortInterest, Error> {
+        Ok(ConsolidatedShortInterest {
+            settlement_date: Some(date),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn checkpoint_moved_after_all_records_read() {
+        let since = date!(2024 - 01 - 15);
+        assert_eq!(
+            Some(date!(2024 - 01 - 16)..date!(2024 - 02 - 01)),
+            date_range_since(since, None, date!(2024 - 02 - 01))
+        );
+        let before = date!(2024 - 01 - 01)..date!(2024 - 01 - 15);
+        assert_eq!(
+            None,
+            date_range_since(since, Some(&before), date!(2024 - 02 - 01))
+        );
+
+        let records = [
+            settled(date!(2024 - 01 - 15)),
+            settled(date!(2024 - 01 - 31)),
+            settled(date!(2024 - 01 - 16)),
+        ];
+        let (records, checkpoint) = track(stream::iter(records), Some(since));
+        let mut records = Box::pin(records.into_stream());
+
+        let first = records.try_next().await.unwrap().unwrap();
+        assert_eq!(Some(date!(2024 - 01 - 31)), first.settlement_date);
+        assert_eq!(Some(since), checkpoint.get());
+
+        assert_eq!(1, records.try_collect::<Vec<_>>().await.unwrap().len());
+        assert_eq!(Some(date!(2024 - 01 - 31)), checkpoint.get());
+
+        let failing = [settled(date!(2024 - 02 - 15)), Err(Error::TokenExpired)];
+        let (records, checkpoint) = track(stream::iter(failing), Some(since));
+        let _ = records.into_stream().collect::<Vec<_>>().await;
+        assert_eq!(Some(since), checkpoint.get());
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn checkpoint_stored_versioned() {
+        let (_, checkpoint) = track(stream::empty(), Some(date!(2024 - 01 - 31)));
+        let stored = serde_json::to_string(&checkpoint).unwrap();
+        assert_eq!(r#"{"version":1,"settlementDate":"2024-01-31"}"#, stored);
+
+        let read: SyncCheckpoint = serde_json::from_str(&stored).unwrap();
+        assert_eq!(Some(date!(2024 - 01 - 31)), read.get());
+        let initial: SyncCheckpoint = serde_json::from_str(r#"{"version":1}"#).unwrap();
+        assert_eq!(None, initial.get());
+
+        let extended = r#"{"version":1,"settlementDate":"2024-01-31","cursor":42}"#;
+        let read: SyncCheckpoint = serde_json::from_str(extended).unwrap();
+        assert_eq!(Some(date!(2024 - 01 - 31)), read.get());
+        assert!(serde_json::from_str::<SyncCheckpoint>(r#"{"version":2}"#).is_err());
+    }
+}
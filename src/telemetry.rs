@@ -0,0 +1,37 @@
+use reqwest::header::HeaderMap;
+
+/// Returns the headers propagating the OpenTelemetry context of the current tracing span to FINRA,
+/// i.e. the `traceparent` header and whatever else the globally configured propagator produces.
+///
+/// Without the `opentelemetry` feature, there are no such headers.
+#[cfg(feature = "opentelemetry")]
+pub(crate) fn trace_headers() -> HeaderMap {
+    use opentelemetry::propagation::Injector;
+    use reqwest::header::{HeaderName, HeaderValue};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(key.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+#[cfg(not(feature = "opentelemetry"))]
+pub(crate) fn trace_headers() -> HeaderMap {
+    HeaderMap::new()
+}
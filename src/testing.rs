@@ -0,0 +1,853 @@
+//! Tools for testing code consuming the paged results of FINRA without talking to FINRA.
+//!
+//! [`MockPages`] is a programmable sequence of the pages "returned by FINRA" and [`paginate`]
+//! drives the same pager that is used for the real datasets against it. This makes it possible
+//! to reproduce edge cases like a missing or wrong `Record-Total`, failing or short pages
+//! deterministically.
+//!
+//! [`MockServer`] serves canned CSV or JSON [`Fixture`]s of the datasets over a local HTTP server,
+//! including the OAuth2 login, so that a whole [`Finra`](crate::Finra) instance can be tested
+//! against it without any credentials.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use futures::{stream, StreamExt, TryStream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{
+    metrics::Metrics,
+    pager::{self, Body, Page, PageSource},
+    progress::ProgressSink,
+    warning::Warnings,
+    DatasetQuery, Error, FinraBuilder, OverflowPolicy, Query, ResponseFormat, Result, Warning,
+};
+
+/// The response to a single page request.
+#[derive(Debug)]
+pub enum MockPage {
+    /// A page with the records in the CSV format, including the header line. `total` is the
+    /// value of the `Record-Total` header, if any.
+    Records { total: Option<u64>, csv: String },
+    /// The "204 No Content" response.
+    NoContent,
+    /// The request for the page fails with the error.
+    Failure(Error),
+}
+
+impl MockPage {
+    /// A page with the records in the `csv` and the `Record-Total` header set to `total`.
+    pub fn records(total: u64, csv: impl Into<String>) -> Self {
+        Self::Records {
+            total: Some(total),
+            csv: csv.into(),
+        }
+    }
+}
+
+/// A sequence of pages returned in order to the page requests. Once the sequence is exhausted, all
+/// the subsequent requests get no content.
+///
+/// The requests and the warnings emitted by the pager are recorded so that the tests can check
+/// them.
+#[derive(Debug, Default)]
+pub struct MockPages {
+    pages: Mutex<VecDeque<MockPage>>,
+    requests: Mutex<Vec<PageRequest>>,
+    warnings: Arc<Mutex<Vec<Warning>>>,
+}
+
+/// The pagination parameters of a request sent to [`MockPages`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    pub offset: u64,
+    pub limit: u64,
+}
+
+impl MockPages {
+    pub fn new(pages: impl IntoIterator<Item = MockPage>) -> Self {
+        Self {
+            pages: Mutex::new(pages.into_iter().collect()),
+            ..Default::default()
+        }
+    }
+
+    /// The requests for the pages received so far.
+    pub fn requests(&self) -> Vec<PageRequest> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    /// The warnings emitted by the pager so far.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.warnings.lock().map(|w| w.clone()).unwrap_or_default()
+    }
+}
+
+impl PageSource for &MockPages {
+    fn name(&self) -> String {
+        "mock".to_string()
+    }
+
+    fn fetch<Q: Query>(&self, query: &Q) -> impl Future<Output = Result<Option<Page>>> + Send {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(PageRequest {
+                offset: query.offset(),
+                limit: query.limit(),
+            });
+        }
+        let page = self.pages.lock().ok().and_then(|mut p| p.pop_front());
+
+        async move {
+            match page {
+                Some(MockPage::Records { total, csv }) => Ok(Some(Page {
+                    total,
+                    body: Body::Text(Some(csv)),
+                    format: ResponseFormat::Csv,
+                })),
+                Some(MockPage::NoContent) | None => Ok(None),
+                Some(MockPage::Failure(e)) => Err(e),
+            }
+        }
+    }
+}
+
+/// Streams the records from the `pages`, requesting `page_size` records at a time, exactly like
+/// the records are streamed from FINRA.
+pub fn paginate<'a, T: DeserializeOwned + 'a>(
+    pages: &'a MockPages,
+    page_size: u64,
+) -> impl TryStream<Ok = T, Error = Error> + 'a {
+    let warnings = pages.warnings.clone();
+    let warnings = Warnings::new(Arc::new(move |w| {
+        if let Ok(mut warnings) = warnings.lock() {
+            warnings.push(w);
+        }
+    }));
+
+    pager::paginate(
+        pages,
+        DatasetQuery::new().with_limit(page_size),
+        warnings,
+        Arc::new(Metrics::default()),
+        ProgressSink::default(),
+        false,
+        OverflowPolicy::default(),
+    )
+    .map_ok(|vs| stream::iter(vs).map(Ok::<T, Error>))
+    .try_flatten()
+}
+
+/// The records of a dataset served by the [`MockServer`]. The pages requested by the client are
+/// cut out of them, so the fixture is the whole dataset.
+///
+/// The client needs to use the [`ResponseFormat`] matching the fixture.
+#[derive(Debug, Clone)]
+pub enum Fixture {
+    /// The records in the CSV format, including the header line.
+    Csv(String),
+    /// The records as an array of JSON objects.
+    Json(String),
+}
+
+impl Fixture {
+    /// Reads the fixture from the file, e.g. the results recorded from FINRA. Files with the
+    /// `json` extension are read as [`Fixture::Json`], all the others as [`Fixture::Csv`].
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        Ok(match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("json") => Self::Json(content),
+            _ => Self::Csv(content),
+        })
+    }
+
+    /// The records from the `offset` on, at most `limit` of them, together with the total number
+    /// of the records. `None` if the fixture is not valid.
+    fn page(&self, offset: usize, limit: usize) -> Option<(usize, String)> {
+        match self {
+            Self::Csv(csv) => {
+                let mut lines = csv.lines().filter(|l| !l.trim().is_empty());
+                let header = lines.next()?;
+                let rows = lines.collect::<Vec<_>>();
+                let page = rows.iter().skip(offset).take(limit);
+                let body =
+                    std::iter::once(&header)
+                        .chain(page)
+                        .fold(String::new(), |mut body, line| {
+                            body.push_str(line);
+                            body.push('\n');
+                            body
+                        });
+                Some((rows.len(), body))
+            }
+            Self::Json(json) => {
+                let rows: Vec<serde_json::Value> = serde_json::from_str(json).ok()?;
+                let page = rows.iter().skip(offset).take(limit).collect::<Vec<_>>();
+                Some((rows.len(), serde_json::to_string(&page).ok()?))
+            }
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv(_) => ResponseFormat::Csv.media_type(),
+            Self::Json(_) => ResponseFormat::Json.media_type(),
+        }
+    }
+}
+
+/// A request received by the [`MockServer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub method: String,
+    /// The path of the request, including the query string.
+    pub path: String,
+    /// The value of the `Authorization` header, if any.
+    pub authorization: Option<String>,
+    pub body: String,
+}
+
+#[derive(Default)]
+struct ServerState {
+    /// The fixtures by `group/name` of the dataset.
+    datasets: Mutex<HashMap<String, Fixture>>,
+    /// The scopes the tokens need to query the datasets, by `group/name` of the dataset.
+    scopes: Mutex<HashMap<String, String>>,
+    requests: Mutex<Vec<MockRequest>>,
+    /// The fixtures of the submitted asynchronous requests, with whether they were polled.
+    #[cfg(feature = "experimental-async-requests")]
+    async_requests: Mutex<Vec<(Fixture, bool)>>,
+    /// The statuses to respond with to the next requests, other than the logins.
+    failures: Mutex<VecDeque<u16>>,
+    /// The interruptions of the responses to the next requests, other than the logins, with the
+    /// number of the bytes of the body sent before them.
+    interruptions: Mutex<VecDeque<(usize, Interruption)>>,
+}
+
+/// How [`MockServer::interrupt_next`] interrupts a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    /// Stops sending the response until the client gives up on it.
+    Stall,
+    /// Closes the connection.
+    Reset,
+}
+
+/// A local HTTP server mimicking the FINRA API. It serves the [`Fixture`]s of the datasets
+/// registered using [`MockServer::dataset`] and hands out tokens with the requested scopes to any
+/// client logging in. The datasets that are not registered are not found.
+///
+/// With the `experimental-async-requests` feature, the queries submitted asynchronously are
+/// reported as pending when first polled and completed afterwards, their results are the whole
+/// fixture of the dataset.
+///
+/// The server runs on the tokio runtime it is started on until it is dropped.
+pub struct MockServer {
+    url: String,
+    state: Arc<ServerState>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts the server on a random local port.
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(ServerState::default());
+
+        let server_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((connection, _)) = listener.accept().await {
+                tokio::spawn(serve(connection, server_state.clone()));
+            }
+        });
+
+        Ok(Self { url, state, task })
+    }
+
+    /// The base URL of the server, to be used with [`FinraBuilder::api_url`].
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Serves the `fixture` as the dataset `name` in the `group`, replacing any previous one.
+    pub fn dataset(&self, group: &str, name: &str, fixture: Fixture) {
+        if let Ok(mut datasets) = self.state.datasets.lock() {
+            datasets.insert(format!("{}/{}", group, name), fixture);
+        }
+    }
+
+    /// Requires the tokens used to query the dataset `name` in the `group` to have the `scope`.
+    /// The requests with the other tokens are rejected as forbidden, with the `WWW-Authenticate`
+    /// header asking for the `scope`.
+    pub fn dataset_scope(&self, group: &str, name: &str, scope: &str) {
+        if let Ok(mut scopes) = self.state.scopes.lock() {
+            scopes.insert(format!("{}/{}", group, name), scope.to_string());
+        }
+    }
+
+    /// Responds with the `status` to the next request, other than a login, instead of serving
+    /// it, e.g. to test the retries or the re-authentication. When called multiple times, the
+    /// statuses are used in the order of the calls.
+    pub fn fail_next(&self, status: u16) {
+        if let Ok(mut failures) = self.state.failures.lock() {
+            failures.push_back(status);
+        }
+    }
+
+    /// Sends only the first `after` bytes of the body of the response to the next request, other
+    /// than a login, and then interrupts it, e.g. to test the pages whose reading fails midway.
+    /// When called multiple times, the interruptions are used in the order of the calls.
+    pub fn interrupt_next(&self, after: usize, interruption: Interruption) {
+        if let Ok(mut interruptions) = self.state.interruptions.lock() {
+            interruptions.push_back((after, interruption));
+        }
+    }
+
+    /// A builder of the [`Finra`](crate::Finra) instances talking to this server.
+    pub fn builder(&self) -> FinraBuilder {
+        FinraBuilder::new("mock-client".to_string(), "mock-secret".to_string())
+            .api_url(&self.url)
+            .oauth2_url(format!("{}/oauth2/access_token", self.url))
+    }
+
+    /// The requests received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state
+            .requests
+            .lock()
+            .map(|r| r.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Serves the requests coming over the `connection` until the client closes it.
+async fn serve(connection: TcpStream, state: Arc<ServerState>) {
+    let mut connection = BufReader::new(connection);
+    while let Ok(Some(request)) = read_request(&mut connection).await {
+        if let Ok(mut requests) = state.requests.lock() {
+            requests.push(request.clone());
+        }
+
+        let response = respond(&request, &state);
+        let interruption = if request.path.starts_with("/oauth2") {
+            None
+        } else {
+            state
+                .interruptions
+                .lock()
+                .ok()
+                .and_then(|mut i| i.pop_front())
+        };
+        let Some((after, interruption)) = interruption else {
+            if connection.get_mut().write_all(&response).await.is_err() {
+                break;
+            }
+            continue;
+        };
+
+        let body = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map_or(response.len(), |headers| headers + 4);
+        let sent = &response[..(body + after).min(response.len())];
+        if connection.get_mut().write_all(sent).await.is_ok() && interruption == Interruption::Stall
+        {
+            // until the client closes the connection
+            let _ = connection.read(&mut [0; 1]).await;
+        }
+        break;
+    }
+}
+
+/// Reads the next request from the `connection`, `None` if the client closed it.
+async fn read_request(connection: &mut BufReader<TcpStream>) -> io::Result<Option<MockRequest>> {
+    let mut line = String::new();
+    if connection.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        line.clear();
+        if connection.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    connection.read_exact(&mut body).await?;
+
+    Ok(Some(MockRequest {
+        method,
+        path,
+        authorization,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    }))
+}
+
+/// The raw HTTP response to the `request`.
+fn respond(request: &MockRequest, state: &ServerState) -> Vec<u8> {
+    let path = request.path.split('?').next().unwrap_or_default();
+
+    if path.starts_with("/oauth2") {
+        // grants the requested scopes, naming the token after them
+        let scope = request
+            .path
+            .split_once("?scope=")
+            .map(|(_, scope)| scope.replace("%20", " ").replace('+', " "))
+            .unwrap_or_default();
+        let access_token = match scope.as_str() {
+            "" => "mock-token".to_string(),
+            scope => format!("mock-token-{}", scope.replace(' ', "-")),
+        };
+        let token = serde_json::json!({
+            "access_token": access_token,
+            "expires_in": "3600",
+            "scope": scope,
+        });
+        return response(200, "application/json", None, &token.to_string());
+    }
+
+    if let Some(status) = state.failures.lock().ok().and_then(|mut f| f.pop_front()) {
+        return response(status, "text/plain", None, "");
+    }
+
+    #[cfg(feature = "experimental-async-requests")]
+    if let Some(request) = path.strip_prefix("/async/") {
+        return respond_async(request, state);
+    }
+
+    let dataset = path
+        .strip_prefix("/data/group/")
+        .and_then(|dataset| dataset.split_once("/name/"))
+        .map(|(group, name)| format!("{}/{}", group, name));
+    let scope = dataset
+        .as_ref()
+        .and_then(|dataset| state.scopes.lock().ok()?.get(dataset).cloned());
+    if let Some(scope) = scope {
+        let granted = request
+            .authorization
+            .as_deref()
+            .and_then(|a| a.strip_prefix("Bearer mock-token-"))
+            .unwrap_or_default();
+        if !granted.split('-').any(|g| g == scope) {
+            return insufficient_scope(&scope);
+        }
+    }
+    let fixture = dataset.and_then(|dataset| state.datasets.lock().ok()?.get(&dataset).cloned());
+    let Some(fixture) = fixture else {
+        return response(404, "text/plain", None, "");
+    };
+
+    let query: serde_json::Value = serde_json::from_str(&request.body).unwrap_or_default();
+    #[cfg(feature = "experimental-async-requests")]
+    if query.get("async") == Some(&serde_json::Value::Bool(true)) {
+        let Ok(mut requests) = state.async_requests.lock() else {
+            return response(500, "text/plain", None, "");
+        };
+        requests.push((fixture, false));
+        let body = serde_json::json!({ "requestId": (requests.len() - 1).to_string() });
+        return response(200, "application/json", None, &body.to_string());
+    }
+
+    let number = |field: &str| {
+        query
+            .get(field)
+            .and_then(serde_json::Value::as_u64)
+            .map(|n| n as usize)
+    };
+    let offset = number("offset").unwrap_or(0);
+    let limit = number("limit").unwrap_or(usize::MAX);
+
+    match fixture.page(offset, limit) {
+        Some((total, _)) if offset >= total => response(204, "text/plain", None, ""),
+        Some((total, body)) => response(200, fixture.content_type(), Some(total), &body),
+        None => response(500, "text/plain", None, "invalid fixture"),
+    }
+}
+
+/// The response to the request of the status (`id`) or the results (`id/data`) of an asynchronous
+/// request.
+#[cfg(feature = "experimental-async-requests")]
+fn respond_async(request: &str, state: &ServerState) -> Vec<u8> {
+    let (id, data) = match request.strip_suffix("/data") {
+        Some(id) => (id, true),
+        None => (request, false),
+    };
+    let Ok(mut requests) = state.async_requests.lock() else {
+        return response(500, "text/plain", None, "");
+    };
+    let Some((fixture, polled)) = id.parse().ok().and_then(|id: usize| requests.get_mut(id)) else {
+        return response(404, "text/plain", None, "");
+    };
+
+    if data {
+        return match fixture.page(0, usize::MAX) {
+            Some((total, body)) => response(200, fixture.content_type(), Some(total), &body),
+            None => response(500, "text/plain", None, "invalid fixture"),
+        };
+    }
+
+    let status = if *polled { "COMPLETED" } else { "PENDING" };
+    *polled = true;
+    let body = serde_json::json!({ "status": status });
+    response(200, "application/json", None, &body.to_string())
+}
+
+/// The response rejecting a token without the `scope`, as an OAuth2 resource server would.
+fn insufficient_scope(scope: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 403 Forbidden\r\nWWW-Authenticate: Bearer error=\"insufficient_scope\", \
+         scope=\"{}\"\r\nContent-Length: 0\r\n\r\n",
+        scope
+    )
+    .into_bytes()
+}
+
+fn response(status: u16, content_type: &str, total: Option<usize>, body: &str) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let total = total
+        .map(|t| format!("Record-Total: {}\r\n", t))
+        .unwrap_or_default();
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        total,
+        body
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Record {
+        id: u32,
+    }
+
+    #[tokio::test]
+    async fn stops_on_short_page_despite_wrong_total() {
+        let pages = MockPages::new([
+            MockPage::records(10, "id\n1\n2\n"),
+            MockPage::records(10, "id\n3\n"),
+            MockPage::records(10, "id\n4\n"),
+        ]);
+
+        let records: Vec<Record> = paginate(&pages, 2).try_collect().await.unwrap();
+
+        assert_eq!(
+            vec![Record { id: 1 }, Record { id: 2 }, Record { id: 3 }],
+            records
+        );
+        assert_eq!(
+            vec![
+                PageRequest {
+                    offset: 0,
+                    limit: 2
+                },
+                PageRequest {
+                    offset: 2,
+                    limit: 2
+                },
+            ],
+            pages.requests()
+        );
+    }
+
+    #[tokio::test]
+    async fn fixture_served_in_pages() {
+        let server = MockServer::start().await.unwrap();
+        server.dataset(
+            "otcMarket",
+            "mock",
+            Fixture::Csv("id\n1\n2\n3\n".to_string()),
+        );
+        let finra = server.builder().build();
+
+        let records: Vec<Record> = finra
+            .dataset("otcMarket", "mock", DatasetQuery::new().with_limit(2))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![Record { id: 1 }, Record { id: 2 }, Record { id: 3 }],
+            records
+        );
+        let requests = server.requests();
+        assert!(requests[0].path.starts_with("/oauth2/access_token"));
+        assert_eq!(3, requests.len());
+        assert!(finra
+            .dataset::<Record>("otcMarket", "unknown", DatasetQuery::new())
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_fields_fail_strict_rows() {
+        use crate::{ConsolidatedShortInterestField as Field, ConsolidatedShortInterestQuery};
+
+        let server = MockServer::start().await.unwrap();
+        server.dataset(
+            "otcMarket",
+            "consolidatedShortInterest",
+            Fixture::Csv("symbolCode,changePercent\nAAPL,1.5\n".to_string()),
+        );
+        let finra = server.builder().strict_rows(true).build();
+        let query = |fields| ConsolidatedShortInterestQuery::new(fields, None, None);
+
+        let records: Vec<_> = finra
+            .consolidated_short_interest(query(Some(vec![Field::SymbolCode, Field::ChangePercent])))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(1.5, records[0].change_percent);
+
+        let err = finra
+            .consolidated_short_interest(query(None))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::MissingFields { fields, .. } if fields.contains(&"currentShortPositionQuantity".to_string()))
+        );
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn repeated_query_read_from_cache() {
+        let dir = std::env::temp_dir().join(format!("finra-testing-cache-{}", std::process::id()));
+        let server = MockServer::start().await.unwrap();
+        server.dataset(
+            "otcMarket",
+            "mock",
+            Fixture::Csv("id\n1\n2\n3\n".to_string()),
+        );
+        let cache = crate::FileCache::new(&dir, std::time::Duration::from_secs(60)).unwrap();
+        let finra = server.builder().cache(cache).build();
+        let query = || async {
+            finra
+                .dataset::<Record>("otcMarket", "mock", DatasetQuery::new().with_limit(2))
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+        };
+
+        let records = query().await;
+        let data_requests = server.requests().len();
+        let cached = query().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(records, cached);
+        assert_eq!(3, cached.len());
+        assert_eq!(data_requests, server.requests().len());
+    }
+
+    #[tokio::test]
+    async fn dataset_queried_with_its_scopes() {
+        let server = MockServer::start().await.unwrap();
+        server.dataset("otcMarket", "mock", Fixture::Csv("id\n1\n".to_string()));
+        server.dataset("otcMarket", "other", Fixture::Csv("id\n2\n".to_string()));
+        let finra = server
+            .builder()
+            .scopes(vec!["data".to_string()])
+            .dataset_scopes(
+                "otcmarket",
+                "mock",
+                vec!["data".to_string(), "x".to_string()],
+            )
+            .build();
+
+        for name in ["mock", "other", "mock"] {
+            finra
+                .dataset::<Record>("otcMarket", name, DatasetQuery::new())
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+        }
+
+        let requests = server.requests();
+        let authorization = |path: &str| {
+            requests
+                .iter()
+                .filter(|r| r.path.ends_with(path))
+                .map(|r| r.authorization.clone().unwrap_or_default())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["Bearer mock-token-data-x"; 2],
+            authorization("/name/mock")
+        );
+        assert_eq!(vec!["Bearer mock-token-data"], authorization("/name/other"));
+        // one login per set of the scopes
+        assert_eq!(
+            2,
+            requests
+                .iter()
+                .filter(|r| r.path.starts_with("/oauth2"))
+                .count()
+        );
+    }
+
+    #[tokio::test]
+    async fn dataset_queried_with_the_scopes_asked_for() {
+        let server = MockServer::start().await.unwrap();
+        server.dataset("otcMarket", "mock", Fixture::Csv("id\n1\n".to_string()));
+        server.dataset_scope("otcMarket", "mock", "x");
+        let finra = server.builder().scopes(vec!["data".to_string()]).build();
+
+        for _ in 0..2 {
+            let records = finra
+                .dataset::<Record>("otcMarket", "mock", DatasetQuery::new())
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(1, records.len());
+        }
+
+        let requests = server.requests();
+        let data = requests
+            .iter()
+            .filter(|r| r.path.ends_with("/name/mock"))
+            .map(|r| r.authorization.clone().unwrap_or_default())
+            .collect::<Vec<_>>();
+        // rejected once, then the token with the scope asked for is kept for the dataset
+        assert_eq!(
+            vec![
+                "Bearer mock-token-data",
+                "Bearer mock-token-data-x",
+                "Bearer mock-token-data-x"
+            ],
+            data
+        );
+
+        let external = FinraBuilder::with_token(
+            "mock-token-data".to_string(),
+            time::OffsetDateTime::now_utc() + time::Duration::hours(1),
+        )
+        .api_url(&server.url)
+        .build();
+        let result = external
+            .dataset::<Record>("otcMarket", "mock", DatasetQuery::new())
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::InsufficientScope { requested, .. }) if requested == vec!["x".to_string()]
+        ));
+    }
+
+    #[tokio::test]
+    async fn interrupted_page_read_again() {
+        for interruption in [Interruption::Stall, Interruption::Reset] {
+            let server = MockServer::start().await.unwrap();
+            server.dataset(
+                "otcMarket",
+                "mock",
+                Fixture::Csv("id\n1\n2\n3\n".to_string()),
+            );
+            let finra = server
+                .builder()
+                .page_timeout(std::time::Duration::from_millis(200))
+                .retry_policy(crate::RetryPolicy {
+                    base_delay: std::time::Duration::from_millis(1),
+                    ..Default::default()
+                })
+                .build();
+            // the first record and a part of the second one
+            server.interrupt_next("id\n1\n2".len(), interruption);
+
+            let records: Vec<Record> = finra
+                .dataset("otcMarket", "mock", DatasetQuery::new())
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+
+            assert_eq!(
+                vec![Record { id: 1 }, Record { id: 2 }, Record { id: 3 }],
+                records,
+                "{:?}",
+                interruption
+            );
+            let pages = server.requests();
+            let pages = pages.iter().filter(|r| r.path.contains("/name/mock"));
+            assert_eq!(2, pages.count(), "{:?}", interruption);
+        }
+    }
+
+    #[tokio::test]
+    async fn paged_without_total_until_short_page() {
+        let page = |csv: &str| MockPage::Records {
+            total: None,
+            csv: csv.to_string(),
+        };
+        let pages = MockPages::new([page("id\n1\n2\n"), page("id\n3\n4\n"), page("id\n5\n")]);
+
+        let records: Vec<Record> = paginate(&pages, 2).try_collect().await.unwrap();
+
+        assert_eq!(5, records.len());
+        assert_eq!(3, pages.requests().len());
+        assert_eq!(1, pages.warnings().len());
+    }
+}
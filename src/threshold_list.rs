@@ -0,0 +1,177 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration};
+
+use crate::{
+    dates::finra_date,
+    query::{
+        dataset_fields, CompareFilter, DateRangeFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    PaginationStrategy,
+};
+
+/// Represents a single symbol on the Reg SHO threshold list on a single trade date.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct ThresholdListRecord {
+    #[serde(rename = "tradeDate", with = "finra_date")]
+    pub trade_date: Option<Date>,
+
+    #[serde(rename = "issueSymbolIdentifier")]
+    pub issue_symbol_identifier: String,
+
+    #[serde(rename = "issueName")]
+    pub issue_name: String,
+
+    #[serde(rename = "marketClassCode")]
+    pub market_class_code: String,
+
+    #[serde(rename = "regShoThresholdFlag")]
+    pub reg_sho_threshold_flag: Option<String>,
+
+    #[serde(rename = "rule4320Flag")]
+    pub rule_4320_flag: Option<String>,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the threshold list query results.
+    ThresholdListField {
+        TradeDate => "tradeDate",
+        IssueSymbolIdentifier => "issueSymbolIdentifier",
+        IssueName => "issueName",
+        MarketClassCode => "marketClassCode",
+        RegShoThresholdFlag => "regShoThresholdFlag",
+        Rule4320Flag => "rule4320Flag",
+    }
+}
+
+/// The query of the Reg SHO threshold list.
+#[derive(Debug, Clone)]
+pub struct ThresholdListQuery {
+    /// If `None`, all fields are included.
+    pub fields: Option<Vec<ThresholdListField>>,
+    /// The range of the trade dates. If `None`, the full available history is included.
+    pub date_range: Option<Range<Date>>,
+    /// If `None` the data for all symbols is included.
+    pub symbol: Option<String>,
+    /// Any additional filters the records need to match.
+    pub compare_filters: Vec<CompareFilter>,
+    /// The order of the results. If empty, the order is decided by FINRA.
+    pub sort: Vec<SortField>,
+    /// How the results are fetched from FINRA.
+    pub pagination: PaginationStrategy,
+
+    // These are internally used for paging...
+    limit: u64,
+    offset: u64,
+}
+
+impl ThresholdListQuery {
+    pub fn new(
+        fields: Option<Vec<ThresholdListField>>,
+        date_range: Option<Range<Date>>,
+        symbol: Option<String>,
+    ) -> Self {
+        Self {
+            fields,
+            date_range,
+            symbol,
+            compare_filters: Vec::new(),
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl Query for ThresholdListQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(self, by: u64) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, date_range: Range<Date>) {
+        if self.date_range.is_none() {
+            self.date_range = Some(date_range);
+        }
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        ThresholdListField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        ThresholdListField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        self.symbol.iter_mut().collect()
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref()
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        self.date_range = Some(date_range);
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+
+    fn default_partition(&self) -> Duration {
+        Duration::weeks(1)
+    }
+}
+
+impl Serialize for ThresholdListQuery {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|r| DateRangeFilter::new(ThresholdListField::TradeDate.as_str(), r))
+                .collect(),
+            compare_filters: self
+                .symbol
+                .iter()
+                .map(|s| {
+                    CompareFilter::equal(ThresholdListField::IssueSymbolIdentifier, s.as_str())
+                })
+                .chain(self.compare_filters.iter().cloned())
+                .collect(),
+            domain_filters: Vec::new(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}
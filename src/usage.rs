@@ -0,0 +1,157 @@
+use std::{
+    collections::VecDeque,
+    ops::Sub,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use time::{Duration, OffsetDateTime};
+
+use crate::metrics::Metrics;
+
+/// How much a [`crate::Finra`] instance, i.e. its credentials, has used the FINRA API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct Usage {
+    /// The number of the HTTP requests sent to FINRA, including the login attempts.
+    pub requests: u64,
+    /// The number of the requests that were retried after a transient failure.
+    pub retries: u64,
+    /// The number of the requests rejected by FINRA because the quota has been exceeded.
+    pub throttled: u64,
+    /// The number of the records returned by the streams.
+    pub records: u64,
+    /// The number of the bytes of the pages of the results.
+    pub bytes: u64,
+    /// The number of the obtained OAuth2 tokens.
+    pub token_refreshes: u64,
+}
+
+impl Sub for Usage {
+    type Output = Usage;
+
+    fn sub(self, earlier: Usage) -> Usage {
+        Usage {
+            requests: self.requests.saturating_sub(earlier.requests),
+            retries: self.retries.saturating_sub(earlier.retries),
+            throttled: self.throttled.saturating_sub(earlier.throttled),
+            records: self.records.saturating_sub(earlier.records),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+            token_refreshes: self.token_refreshes.saturating_sub(earlier.token_refreshes),
+        }
+    }
+}
+
+/// The usage between the `start` and the `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageWindow {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub usage: Usage,
+}
+
+/// Splits the usage of a [`crate::Finra`] instance into the time windows, e.g. to render it in a
+/// dashboard. Use [`crate::Finra::usage_tracker`] to create it.
+///
+/// The tracker doesn't run in the background - a window is closed when the tracker is sampled
+/// after the window elapsed. Sample the tracker at least once per window using
+/// [`UsageTracker::sample`] or [`UsageTracker::windows`] to keep the windows of the same length.
+pub struct UsageTracker {
+    metrics: Arc<Metrics>,
+    window: Duration,
+    retained: usize,
+    state: Mutex<TrackerState>,
+}
+
+struct TrackerState {
+    /// The start of the open window.
+    start: OffsetDateTime,
+    /// The usage at the start of the open window.
+    at_start: Usage,
+    /// The closed windows, the oldest first.
+    closed: VecDeque<UsageWindow>,
+}
+
+impl UsageTracker {
+    pub(crate) fn new(metrics: Arc<Metrics>, window: Duration, retained: usize) -> Self {
+        let at_start = metrics.usage();
+        Self {
+            metrics,
+            window,
+            retained,
+            state: Mutex::new(TrackerState {
+                start: OffsetDateTime::now_utc(),
+                at_start,
+                closed: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Closes the open window if it has elapsed.
+    pub fn sample(&self) {
+        self.sample_at(OffsetDateTime::now_utc());
+    }
+
+    /// The retained closed windows, the oldest first, followed by the open window.
+    pub fn windows(&self) -> Vec<UsageWindow> {
+        let now = OffsetDateTime::now_utc();
+        self.sample_at(now);
+
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let open = UsageWindow {
+            start: state.start,
+            end: now,
+            usage: self.metrics.usage() - state.at_start,
+        };
+        state
+            .closed
+            .iter()
+            .copied()
+            .chain(std::iter::once(open))
+            .collect()
+    }
+
+    fn sample_at(&self, now: OffsetDateTime) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if now - state.start < self.window {
+            return;
+        }
+
+        let usage = self.metrics.usage();
+        let closed = UsageWindow {
+            start: state.start,
+            end: now,
+            usage: usage - state.at_start,
+        };
+        state.closed.push_back(closed);
+        while state.closed.len() > self.retained {
+            state.closed.pop_front();
+        }
+        state.start = now;
+        state.at_start = usage;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn windows_closed_when_elapsed() {
+        let metrics = Arc::new(Metrics::default());
+        let tracker = UsageTracker::new(metrics.clone(), Duration::minutes(1), 2);
+        let start = tracker.state.lock().unwrap().start;
+
+        for minute in 1..=3 {
+            Metrics::add(&metrics.requests, minute);
+            tracker.sample_at(start + Duration::seconds(30 + 60 * (minute as i64 - 1)));
+            tracker.sample_at(start + Duration::minutes(minute as i64));
+        }
+
+        let windows = tracker.windows();
+        assert_eq!(3, windows.len());
+        assert_eq!(2, windows[0].usage.requests);
+        assert_eq!(3, windows[1].usage.requests);
+        assert_eq!(start + Duration::minutes(3), windows[1].end);
+        assert_eq!(Usage::default(), windows[2].usage);
+    }
+}
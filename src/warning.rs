@@ -0,0 +1,52 @@
+use std::{fmt::Debug, sync::Arc};
+
+use time::OffsetDateTime;
+
+/// Describes a non-fatal condition encountered while talking to FINRA. These are not errors, but
+/// might signify that the returned data is not exactly what was expected.
+///
+/// Use [`crate::FinraBuilder::warnings`] to get notified about them.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// Some rows of the page returned from `url` could not be deserialized and were skipped.
+    DroppedRows { url: String, count: u64 },
+    /// The response from `url` didn't contain the `Record-Total` header, so it is not known how
+    /// many records there are in total.
+    MissingRecordTotal { url: String },
+    /// The data returned from `url` contain fields that are not known to this crate. This usually
+    /// means that FINRA changed the dataset.
+    SchemaDrift {
+        url: String,
+        unknown_fields: Vec<String>,
+    },
+    /// The query filters by a symbol that is not known to the configured
+    /// [`crate::SymbolValidator`].
+    UnknownSymbol { symbol: String },
+    /// The token that cannot be refreshed by this crate is about to expire.
+    TokenNearExpiry { expires_at: OffsetDateTime },
+}
+
+/// Where the warnings are sent to. Does nothing unless a sink is set up.
+#[derive(Clone, Default)]
+pub(crate) struct Warnings(Option<Arc<dyn Fn(Warning) + Send + Sync>>);
+
+impl Warnings {
+    pub(crate) fn new(sink: Arc<dyn Fn(Warning) + Send + Sync>) -> Self {
+        Self(Some(sink))
+    }
+
+    pub(crate) fn emit(&self, warning: Warning) {
+        if let Some(sink) = &self.0 {
+            sink(warning);
+        }
+    }
+}
+
+impl Debug for Warnings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Warnings")
+            .field(&self.0.as_ref().map(|_| "sink"))
+            .finish()
+    }
+}
@@ -0,0 +1,254 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    dates::finra_date,
+    numbers,
+    query::{
+        dataset_fields, CompareFilter, DateRangeFilter, Query, QueryBody, SortField,
+        MAX_RESULTS_PER_PAGE,
+    },
+    PaginationStrategy,
+};
+
+/// Represents the OTC transparency weekly summary of the trading of a single symbol, either in
+/// total or for a single ATS or non-ATS market participant, depending on the summary type.
+///
+/// With the `serialize` feature, the records can also be serialized. The fields are serialized
+/// under their FINRA names in the order of declaration here. This order is stable - new fields are
+/// only ever added at the end.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[serde(default)]
+pub struct WeeklySummaryRecord {
+    #[serde(rename = "weekStartDate", with = "finra_date")]
+    pub week_start_date: Option<Date>,
+
+    #[serde(rename = "tierIdentifier")]
+    pub tier_identifier: String,
+
+    #[serde(rename = "tierDescription")]
+    pub tier_description: String,
+
+    #[serde(rename = "issueSymbolIdentifier")]
+    pub issue_symbol_identifier: String,
+
+    #[serde(rename = "issueName")]
+    pub issue_name: String,
+
+    #[serde(rename = "summaryTypeCode")]
+    pub summary_type_code: String,
+
+    #[serde(rename = "marketParticipantName")]
+    pub market_participant_name: String,
+
+    #[serde(rename = "MPID")]
+    pub mpid: String,
+
+    #[serde(
+        rename = "firmCRDNumber",
+        deserialize_with = "numbers::deserialize_option"
+    )]
+    pub firm_crd_number: Option<u64>,
+
+    #[serde(
+        rename = "totalWeeklyShareQuantity",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub total_weekly_share_quantity: u64,
+
+    #[serde(
+        rename = "totalWeeklyTradeCount",
+        deserialize_with = "numbers::deserialize"
+    )]
+    pub total_weekly_trade_count: u64,
+
+    #[serde(rename = "productTypeCode")]
+    pub product_type_code: String,
+
+    #[serde(rename = "summaryStartDate", with = "finra_date")]
+    pub summary_start_date: Option<Date>,
+
+    #[serde(rename = "initialPublishedDate", with = "finra_date")]
+    pub initial_published_date: Option<Date>,
+
+    #[serde(rename = "lastUpdateDate", with = "finra_date")]
+    pub last_update_date: Option<Date>,
+
+    #[serde(rename = "lastReportedDate", with = "finra_date")]
+    pub last_reported_date: Option<Date>,
+}
+
+dataset_fields! {
+    /// This enum is used to limit which fields are included in the weekly summary query results.
+    WeeklySummaryField {
+        WeekStartDate => "weekStartDate",
+        TierIdentifier => "tierIdentifier",
+        TierDescription => "tierDescription",
+        IssueSymbolIdentifier => "issueSymbolIdentifier",
+        IssueName => "issueName",
+        SummaryTypeCode => "summaryTypeCode",
+        MarketParticipantName => "marketParticipantName",
+        Mpid => "MPID",
+        FirmCrdNumber => "firmCRDNumber",
+        TotalWeeklyShareQuantity => "totalWeeklyShareQuantity",
+        TotalWeeklyTradeCount => "totalWeeklyTradeCount",
+        ProductTypeCode => "productTypeCode",
+        SummaryStartDate => "summaryStartDate",
+        InitialPublishedDate => "initialPublishedDate",
+        LastUpdateDate => "lastUpdateDate",
+        LastReportedDate => "lastReportedDate",
+    }
+}
+
+/// The tiers of the securities in the weekly summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tier {
+    /// NMS stocks in the S&P 500, Russell 1000 and some ETPs.
+    Tier1,
+    /// All the other NMS stocks.
+    Tier2,
+    /// OTC equity securities.
+    OtcEquity,
+}
+
+/// The query of the OTC transparency weekly summary.
+#[derive(Debug, Clone)]
+pub struct WeeklySummaryQuery {
+    /// If `None`, all fields are included.
+    pub fields: Option<Vec<WeeklySummaryField>>,
+    /// The range of the week start dates. If `None`, the full available history is included.
+    pub date_range: Option<Range<Date>>,
+    /// If `None` the data for all tiers is included.
+    pub tier: Option<Tier>,
+    /// If `None` the data for all symbols is included.
+    pub symbol: Option<String>,
+    /// Any additional filters the records need to match.
+    pub compare_filters: Vec<CompareFilter>,
+    /// The order of the results. If empty, the order is decided by FINRA.
+    pub sort: Vec<SortField>,
+    /// How the results are fetched from FINRA.
+    pub pagination: PaginationStrategy,
+
+    // These are internally used for paging...
+    limit: u64,
+    offset: u64,
+}
+
+impl Tier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tier1 => "T1",
+            Self::Tier2 => "T2",
+            Self::OtcEquity => "OTCE",
+        }
+    }
+}
+
+impl WeeklySummaryQuery {
+    pub fn new(
+        fields: Option<Vec<WeeklySummaryField>>,
+        date_range: Option<Range<Date>>,
+        tier: Option<Tier>,
+        symbol: Option<String>,
+    ) -> Self {
+        Self {
+            fields,
+            date_range,
+            tier,
+            symbol,
+            compare_filters: Vec::new(),
+            sort: Vec::new(),
+            pagination: PaginationStrategy::default(),
+            limit: MAX_RESULTS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl Query for WeeklySummaryQuery {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn move_cursor(self, by: u64) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    fn with_limit(self, limit: u64) -> Self {
+        Self { limit, ..self }
+    }
+
+    fn default_date_range(&mut self, date_range: Range<Date>) {
+        if self.date_range.is_none() {
+            self.date_range = Some(date_range);
+        }
+    }
+
+    fn known_fields(&self) -> &'static [&'static str] {
+        WeeklySummaryField::NAMES
+    }
+
+    fn requested_fields(&self) -> Vec<&'static str> {
+        WeeklySummaryField::names_of(self.fields.as_deref())
+    }
+
+    fn symbols_mut(&mut self) -> Vec<&mut String> {
+        self.symbol.iter_mut().collect()
+    }
+
+    fn date_range(&self) -> Option<&Range<Date>> {
+        self.date_range.as_ref()
+    }
+
+    fn set_date_range(&mut self, date_range: Range<Date>) {
+        self.date_range = Some(date_range);
+    }
+
+    fn pagination(&self) -> PaginationStrategy {
+        self.pagination
+    }
+}
+
+impl Serialize for WeeklySummaryQuery {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let tier_filter = self
+            .tier
+            .map(|t| CompareFilter::equal(WeeklySummaryField::TierIdentifier, t.as_str()));
+        let symbol_filter = self
+            .symbol
+            .as_ref()
+            .map(|s| CompareFilter::equal(WeeklySummaryField::IssueSymbolIdentifier, s.as_str()));
+
+        QueryBody {
+            fields: self.fields.as_deref(),
+            date_range_filters: self
+                .date_range
+                .iter()
+                .map(|r| DateRangeFilter::new(WeeklySummaryField::WeekStartDate.as_str(), r))
+                .collect(),
+            compare_filters: tier_filter
+                .into_iter()
+                .chain(symbol_filter)
+                .chain(self.compare_filters.iter().cloned())
+                .collect(),
+            domain_filters: Vec::new(),
+            sort_fields: &self.sort,
+            limit: self.limit,
+            offset: self.offset,
+        }
+        .serialize(serializer)
+    }
+}